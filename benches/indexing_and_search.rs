@@ -1,9 +1,8 @@
 use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
-use fff_nvim::FILE_PICKER;
-use fff_nvim::file_picker::FilePicker;
+use fff_nvim::FILE_PICKERS;
+use fff_nvim::file_picker::{FilePicker, SharedPicker};
 use std::path::PathBuf;
 use std::time::Duration;
-use tracing_subscriber;
 
 /// Initialize tracing to output to console
 fn init_tracing() {
@@ -21,16 +20,22 @@ fn init_tracing() {
 
 /// Initialize FilePicker and insert into global state
 fn init_file_picker_internal(path: &str) -> Result<(), String> {
-    let picker = FilePicker::new(path.to_string())
-        .map_err(|e| format!("Failed to create FilePicker: {:?}", e))?;
-
-    let mut picker_guard = FILE_PICKER
+    let mut registry = FILE_PICKERS
         .write()
         .map_err(|_| "Failed to acquire write lock")?;
-    *picker_guard = Some(picker);
+    registry
+        .activate(std::path::Path::new(path), Default::default())
+        .map_err(|e| format!("Failed to create FilePicker: {:?}", e))?;
     Ok(())
 }
 
+fn active_picker() -> Result<Option<SharedPicker>, String> {
+    let registry = FILE_PICKERS
+        .read()
+        .map_err(|_| "Failed to acquire read lock")?;
+    Ok(registry.get(None))
+}
+
 /// Helper function to wait for scanning to complete and get file count
 fn wait_for_scan_completion(timeout_secs: u64) -> Result<usize, String> {
     let start = std::time::Instant::now();
@@ -42,10 +47,8 @@ fn wait_for_scan_completion(timeout_secs: u64) -> Result<usize, String> {
         iteration += 1;
 
         {
-            let picker_guard = FILE_PICKER
-                .read()
-                .map_err(|_| "Failed to acquire read lock")?;
-            if let Some(ref picker) = *picker_guard {
+            if let Some(picker) = active_picker()? {
+                let picker = picker.read().map_err(|_| "Failed to acquire read lock")?;
                 let is_scanning = picker.is_scan_active();
                 let file_count = picker.get_files().len();
 
@@ -69,14 +72,12 @@ fn wait_for_scan_completion(timeout_secs: u64) -> Result<usize, String> {
                     );
                     return Ok(file_count);
                 }
-            } else {
-                if iteration % 100 == 0 {
-                    eprintln!(
-                        "  [{:.1}s] FilePicker is None (iteration {})",
-                        start.elapsed().as_secs_f32(),
-                        iteration
-                    );
-                }
+            } else if iteration % 100 == 0 {
+                eprintln!(
+                    "  [{:.1}s] FilePicker is None (iteration {})",
+                    start.elapsed().as_secs_f32(),
+                    iteration
+                );
             }
         }
 
@@ -91,12 +92,10 @@ fn wait_for_scan_completion(timeout_secs: u64) -> Result<usize, String> {
     }
 }
 
-/// Get files from the global FILE_PICKER
-fn get_files_snapshot() -> Result<Vec<fff_nvim::types::FileItem>, String> {
-    let picker_guard = FILE_PICKER
-        .read()
-        .map_err(|_| "Failed to acquire read lock")?;
-    if let Some(ref picker) = *picker_guard {
+/// Get files from the active picker
+fn get_files_snapshot() -> Result<Vec<std::sync::Arc<fff_nvim::types::FileItem>>, String> {
+    if let Some(picker) = active_picker()? {
+        let picker = picker.read().map_err(|_| "Failed to acquire read lock")?;
         Ok(picker.get_files().to_vec())
     } else {
         Err("FilePicker not initialized".to_string())
@@ -105,15 +104,13 @@ fn get_files_snapshot() -> Result<Vec<fff_nvim::types::FileItem>, String> {
 
 /// Clean up global state
 fn cleanup_global_state() {
-    if let Ok(mut picker_guard) = FILE_PICKER.write() {
-        if let Some(mut picker) = picker_guard.take() {
-            picker.stop_background_monitor();
-        }
+    if let Ok(mut registry) = FILE_PICKERS.write() {
+        registry.clear();
     }
 }
 
 /// Initialize FilePicker once and return files snapshot
-fn setup_once() -> Result<Vec<fff_nvim::types::FileItem>, String> {
+fn setup_once() -> Result<Vec<std::sync::Arc<fff_nvim::types::FileItem>>, String> {
     init_tracing();
 
     let big_repo_path = PathBuf::from("./big-repo");
@@ -127,10 +124,8 @@ fn setup_once() -> Result<Vec<fff_nvim::types::FileItem>, String> {
     eprintln!("  Path: {:?}", canonical_path);
 
     {
-        let picker_guard = FILE_PICKER
-            .read()
-            .map_err(|_| "Failed to acquire read lock")?;
-        if let Some(ref picker) = *picker_guard {
+        if let Some(picker) = active_picker()? {
+            let picker = picker.read().map_err(|_| "Failed to acquire read lock")?;
             let files = picker.get_files();
             if !files.is_empty() {
                 eprintln!("  ℹ Reusing existing index with {} files", files.len());
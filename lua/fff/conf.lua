@@ -107,6 +107,12 @@ local function init()
     title = 'FFFiles',
     max_results = 100,
     max_threads = 4,
+    async_search = true, -- search on background threads so typing never waits for the results
+    -- also search the open buffers and the oldfiles, they can be outside of the base path
+    sources = {
+      buffers = false,
+      oldfiles = false,
+    },
     layout = {
       height = 0.8,
       width = 0.8,
@@ -140,6 +146,9 @@ local function init()
       preview_scroll_up = '<C-u>',
       preview_scroll_down = '<C-d>',
       toggle_debug = '<F2>',
+      toggle_ignored = '<C-g>',
+      explain_score = '<F3>',
+      toggle_pin = '<C-b>',
     },
     hl = {
       border = 'FloatBorder',
@@ -152,6 +161,23 @@ local function init()
       frecency = 'Number',
       debug = 'Comment',
     },
+    indexing = {
+      -- ripgrep style globs applied on top of .gitignore, e.g. { '!*.snap', '!fixtures/**' }
+      -- a project-local .fffignore file with the .gitignore syntax is respected as well
+      overrides = {},
+      hidden = true, -- index dot files, exclude specific ones with overrides e.g. '!.cache/'
+      follow_symlinks = false, -- walk into symlinked directories, loops and duplicates are skipped
+      -- extra directories indexed together with the base path, e.g. { '~/work/proto' }
+      -- or { { path = '~/work/proto', label = 'proto' } }, results are prefixed with the label
+      roots = {},
+      max_pickers = 4, -- indexes kept warm when switching between directories, least recently used is dropped
+      -- directory of the trigram indexes of the file contents pruning the files read by grep, persisted
+      -- across sessions, e.g. vim.fn.stdpath('cache') .. '/fff_content_index'
+      content_index = false,
+    },
+    -- files of the same name suggested by :FFFAlternate and boosted for short queries, `{}` is the tested name
+    -- e.g. { test_patterns = { '{}_test', 'test_{}' }, extension_pairs = { { { 'h' }, { 'c', 'cpp' } } } }
+    related = {},
     frecency = {
       enabled = true,
       db_path = vim.fn.stdpath('cache') .. '/fff_nvim',
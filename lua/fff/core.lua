@@ -86,7 +86,7 @@ M.ensure_initialized = function()
   local ok, result = pcall(fuzzy.init_db, db_path, true)
   if not ok then vim.notify('Failed to initialize frecency database: ' .. result, vim.log.levels.WARN) end
 
-  ok, result = pcall(fuzzy.init_file_picker, config.base_path)
+  ok, result = pcall(fuzzy.init_file_picker, config.base_path, config.indexing, config.indexing.max_pickers)
   if not ok then
     vim.notify('Failed to initialize file picker: ' .. result, vim.log.levels.ERROR)
     return fuzzy
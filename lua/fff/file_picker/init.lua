@@ -8,20 +8,35 @@ M.state = {
   initialized = false,
   base_path = nil,
   last_scan_time = 0,
+  search_generation = nil,
+  search_poll_timer = nil,
+  search_on_results = nil,
+  search_empty_polls = 0,
+  grep_generation = nil,
+  grep_poll_timer = nil,
 }
 
+M.SEARCH_POLL_INTERVAL_MS = 8
+-- a search superseded by one which failed never completes, polling gives up after about 5 s
+M.SEARCH_MAX_EMPTY_POLLS = 625
+-- a grep runs longer than a search, batches are shown at about the screen refresh rate
+M.GREP_POLL_INTERVAL_MS = 16
+
 function M.setup()
   local db_path = vim.fn.stdpath('cache') .. '/fff_nvim'
   local ok, result = pcall(fuzzy.init_db, db_path, true)
   if not ok then vim.notify('Failed to initialize frecency database: ' .. result, vim.log.levels.WARN) end
 
   local config = require('fff.conf').get()
-  ok, result = pcall(fuzzy.init_file_picker, config.base_path)
+  ok, result = pcall(fuzzy.init_file_picker, config.base_path, config.indexing, config.indexing.max_pickers)
   if not ok then
     vim.notify('Failed to initialize file picker: ' .. result, vim.log.levels.ERROR)
     return false
   end
 
+  ok, result = pcall(fuzzy.set_related_rules, config.related)
+  if not ok then vim.notify('Invalid related files rules: ' .. result, vim.log.levels.WARN) end
+
   M.state.initialized = true
   M.state.base_path = config.base_path
 
@@ -29,10 +44,11 @@ function M.setup()
 end
 
 --- Trigger scan of files in the current directory (asynchronous)
-function M.scan_files()
+--- @param picker string|nil Base path of the picker to rescan, defaults to the active one
+function M.scan_files(picker)
   if not M.state.initialized then return end
 
-  local ok, result = pcall(fuzzy.scan_files)
+  local ok, result = pcall(fuzzy.scan_files, picker)
   if not ok then
     vim.notify('Failed to trigger file scan: ' .. result, vim.log.levels.ERROR)
     return
@@ -47,16 +63,37 @@ end
 --- @param max_threads number Maximum number of threads (optional)
 --- @param current_file string|nil Path to current file to deprioritize (optional)
 --- @param reverse_order boolean Reverse order of results
---- @return table List of matching files
-function M.search_files(query, max_results, max_threads, current_file, reverse_order)
+--- @param include_ignored boolean|nil Also search files hidden by .gitignore/.ignore rules
+--- @param picker string|nil Base path of the picker to search, defaults to the active one
+--- @param sources table|nil Pushed sources searched with the index, e.g. { 'buffers', 'oldfiles' }
+--- @return table List of matching files, each with the `source` it comes from
+function M.search_files(
+  query,
+  max_results,
+  max_threads,
+  current_file,
+  reverse_order,
+  include_ignored,
+  picker,
+  sources
+)
   local config = require('fff.conf').get()
   if not M.state.initialized then return {} end
 
   max_results = max_results or config.max_results
   max_threads = max_threads or config.max_threads
 
-  local ok, search_result =
-    pcall(fuzzy.fuzzy_search_files, query, max_results, max_threads, current_file, reverse_order)
+  local ok, search_result = pcall(
+    fuzzy.fuzzy_search_files,
+    query,
+    max_results,
+    max_threads,
+    current_file,
+    reverse_order,
+    include_ignored,
+    picker,
+    sources
+  )
   if not ok then
     vim.notify('Failed to search files: ' .. tostring(search_result), vim.log.levels.ERROR)
     return {}
@@ -68,6 +105,199 @@ function M.search_files(query, max_results, max_threads, current_file, reverse_o
   return search_result.items
 end
 
+--- Search files on the Rust worker pool without blocking the UI
+--- Only the latest search delivers its results, older ones are dropped when superseded
+--- @param query string Search query
+--- @param max_results number Maximum number of results (optional)
+--- @param max_threads number Maximum number of threads (optional)
+--- @param current_file string|nil Path to current file to deprioritize (optional)
+--- @param reverse_order boolean Reverse order of results
+--- @param include_ignored boolean|nil Also search files hidden by .gitignore/.ignore rules
+--- @param on_results function Called on the main loop with the list of matching files
+--- @param picker string|nil Base path of the picker to search, defaults to the active one
+--- @param sources table|nil Pushed sources searched with the index, e.g. { 'buffers', 'oldfiles' }
+function M.search_files_async(
+  query,
+  max_results,
+  max_threads,
+  current_file,
+  reverse_order,
+  include_ignored,
+  on_results,
+  picker,
+  sources
+)
+  local config = require('fff.conf').get()
+  if not M.state.initialized then return end
+
+  max_results = max_results or config.max_results
+  max_threads = max_threads or config.max_threads
+
+  local ok, generation = pcall(
+    fuzzy.fuzzy_search_files_async,
+    query,
+    max_results,
+    max_threads,
+    current_file,
+    reverse_order,
+    include_ignored,
+    picker,
+    sources
+  )
+  if not ok then
+    vim.notify('Failed to search files: ' .. tostring(generation), vim.log.levels.ERROR)
+    return
+  end
+
+  M.state.search_generation = generation
+  -- the results go to the latest caller, e.g. a picker reopened in another mode
+  M.state.search_on_results = on_results
+  M.state.search_empty_polls = 0
+  if M.state.search_poll_timer then return end
+
+  -- a single timer polls the result slot for whatever search is the latest one
+  local timer = vim.uv.new_timer()
+  M.state.search_poll_timer = timer
+  timer:start(
+    0,
+    M.SEARCH_POLL_INTERVAL_MS,
+    vim.schedule_wrap(function()
+      if M.state.search_poll_timer ~= timer then return end
+
+      local poll_ok, search_result = pcall(fuzzy.poll_search_result, M.state.search_generation)
+      if poll_ok and not search_result then
+        M.state.search_empty_polls = M.state.search_empty_polls + 1
+        if M.state.search_empty_polls >= M.SEARCH_MAX_EMPTY_POLLS then M.cancel_search() end
+        return
+      end
+
+      local on_latest_results = M.state.search_on_results
+      M.cancel_search()
+
+      if not poll_ok then
+        vim.notify('Failed to search files: ' .. tostring(search_result), vim.log.levels.ERROR)
+        return
+      end
+
+      M.state.last_search_result = search_result
+      on_latest_results(search_result.items)
+    end)
+  )
+end
+
+--- Stop waiting for the latest search, its results are dropped
+function M.cancel_search()
+  if M.state.search_poll_timer then
+    M.state.search_poll_timer:stop()
+    M.state.search_poll_timer:close()
+    M.state.search_poll_timer = nil
+  end
+  M.state.search_on_results = nil
+end
+
+--- Replace the files of a pushed source, searched together with the index when the search asks for it
+--- The same file found by the scan shows up once, tagged with the pushed source
+--- @param source string 'buffers' or 'oldfiles'
+--- @param paths table Absolute paths, the ones which are not files anymore are skipped
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return number Number of files kept
+function M.set_source_files(source, paths, picker)
+  if not M.state.initialized then return 0 end
+
+  local ok, result = pcall(fuzzy.set_source_files, source, paths, picker)
+  if not ok then
+    vim.notify('Failed to set ' .. source .. ' files: ' .. tostring(result), vim.log.levels.WARN)
+    return 0
+  end
+  return result
+end
+
+--- Push the listed open buffers and the Neovim oldfiles enabled in the config
+--- @param sources table Config like { buffers = true, oldfiles = false }
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return table Names of the pushed sources to pass to the searches
+function M.push_sources(sources, picker)
+  local pushed = {}
+  if sources.buffers then
+    local paths = {}
+    for _, buf in ipairs(vim.api.nvim_list_bufs()) do
+      if vim.bo[buf].buflisted and vim.bo[buf].buftype == '' then
+        local name = vim.api.nvim_buf_get_name(buf)
+        if name ~= '' then table.insert(paths, vim.fn.fnamemodify(name, ':p')) end
+      end
+    end
+    M.set_source_files('buffers', paths, picker)
+    table.insert(pushed, 'buffers')
+  end
+
+  if sources.oldfiles then
+    local paths = {}
+    for _, path in ipairs(vim.v.oldfiles) do
+      table.insert(paths, vim.fn.fnamemodify(vim.fn.expand(path), ':p'))
+    end
+    M.set_source_files('oldfiles', paths, picker)
+    table.insert(pushed, 'oldfiles')
+  end
+
+  return pushed
+end
+
+--- Search the files opened in any project, ranked by their frecency before the match score
+--- Only files accessed since the frecency database stores the paths are listed
+--- @param query string Search query
+--- @param max_results number Maximum number of results (optional)
+--- @param max_threads number Maximum number of threads (optional)
+--- @param reverse_order boolean Reverse order of results
+--- @return table List of matching files
+function M.search_recent(query, max_results, max_threads, reverse_order)
+  local config = require('fff.conf').get()
+  if not M.state.initialized then return {} end
+
+  max_results = max_results or config.max_results
+  max_threads = max_threads or config.max_threads
+
+  local ok, search_result = pcall(fuzzy.fuzzy_search_recent, query, max_results, max_threads, reverse_order or false)
+  if not ok then
+    vim.notify('Failed to search recent files: ' .. tostring(search_result), vim.log.levels.ERROR)
+    return {}
+  end
+
+  M.state.last_search_result = search_result
+  return search_result.items
+end
+
+--- Search the directories containing the indexed files
+--- Directories rank by the frecency of the files they contain on top of the match score
+--- @param query string Search query
+--- @param max_results number Maximum number of results (optional)
+--- @param max_threads number Maximum number of threads (optional)
+--- @param reverse_order boolean Reverse order of results
+--- @param picker string|nil Base path of the picker to search, defaults to the active one
+--- @return table List of matching directories
+function M.search_dirs(query, max_results, max_threads, reverse_order, picker)
+  local config = require('fff.conf').get()
+  if not M.state.initialized then return {} end
+
+  max_results = max_results or config.max_results
+  max_threads = max_threads or config.max_threads
+
+  local ok, search_result =
+    pcall(fuzzy.fuzzy_search_dirs, query, max_results, max_threads, reverse_order or false, picker)
+  if not ok then
+    vim.notify('Failed to search directories: ' .. tostring(search_result), vim.log.levels.ERROR)
+    return {}
+  end
+
+  M.state.last_search_result = {
+    items = search_result.items,
+    scores = search_result.scores,
+    total_matched = search_result.total_matched,
+    total_files = search_result.total_dirs,
+  }
+
+  return search_result.items
+end
+
 --- Get the last search result metadata
 --- @return table Search metadata with total_matched and total_files
 function M.get_search_metadata()
@@ -85,6 +315,22 @@ function M.get_search_location()
   return M.state.last_search_result.location
 end
 
+--- Turn a location into lines and byte columns of the file: a symbol (`file@name`) or a pattern (`file:/regex/`)
+--- becomes its first match, byte offsets (`file:#1234`) and char or UTF-16 columns are converted
+--- @param file_path string Path of the chosen file
+--- @param location table|nil Location from the search results
+--- @return table|nil The location with a line, nil when the symbol or the pattern is not found
+function M.resolve_location(file_path, location)
+  if not location or location.encoding == 'byte' or (location.line and not location.encoding) then return location end
+
+  local ok, result = pcall(fuzzy.resolve_location, file_path, location)
+  if not ok then
+    vim.notify('Failed to resolve location: ' .. tostring(result), vim.log.levels.WARN)
+    return nil
+  end
+  return result
+end
+
 --- Get score information for a file by index (1-based)
 --- @param index number The index of the file in the last search results
 --- @return table|nil Score information or nil if not available
@@ -94,6 +340,174 @@ function M.get_file_score(index)
   return M.state.last_search_result.scores[index]
 end
 
+--- Get more results of the last search without searching again
+--- The scores of the page are added to the last search result so `get_file_score` keeps working
+--- @param offset number Number of results already loaded, the rank (0-based) of the first result
+--- @param count number Maximum number of results to return
+--- @param reverse_order boolean|nil The page goes before the loaded results, as the search was reversed
+--- @return table List of matching files, empty when all of them are loaded
+function M.get_results(offset, count, reverse_order)
+  if not M.state.initialized or not M.state.last_search_result then return {} end
+
+  local ok, page = pcall(fuzzy.get_results, offset, count)
+  if not ok then
+    vim.notify('Failed to get search results: ' .. tostring(page), vim.log.levels.ERROR)
+    return {}
+  end
+  if not page then return {} end
+
+  local last_scores = M.state.last_search_result.scores or {}
+  if reverse_order then
+    M.state.last_search_result.scores = vim.list_extend(vim.list_extend({}, page.scores), last_scores)
+  else
+    M.state.last_search_result.scores = vim.list_extend(last_scores, page.scores)
+  end
+
+  return page.items
+end
+
+--- Explain the score of a single file for the query
+--- @param query string Search query
+--- @param path string Path of the file, absolute or relative to the picker base path
+--- @param current_file string|nil Path to current file to deprioritize (optional)
+--- @param include_ignored boolean|nil Rank among the files hidden by .gitignore/.ignore rules too
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return table|nil Score breakdown with the match details, frecency inputs and the rank
+function M.explain(query, path, current_file, include_ignored, picker)
+  if not M.state.initialized then return nil end
+
+  local ok, result = pcall(fuzzy.explain, query, path, current_file, include_ignored, picker)
+  if not ok then
+    vim.notify('Failed to explain score: ' .. tostring(result), vim.log.levels.ERROR)
+    return nil
+  end
+  return result
+end
+
+--- Pin a file so it comes first in the empty query results of the project and gets a large boost otherwise
+--- @param path string Path of the file, absolute or relative to the picker base path
+--- @param picker string|nil Base path of the picker, the project the pin belongs to, defaults to the active one
+--- @return boolean False when the file was already pinned
+function M.add_pin(path, picker)
+  if not M.state.initialized then return false end
+
+  local ok, result = pcall(fuzzy.add_pin, path, picker)
+  if not ok then
+    vim.notify('Failed to pin file: ' .. tostring(result), vim.log.levels.ERROR)
+    return false
+  end
+  return result
+end
+
+--- @param path string Path of the file, absolute or relative to the picker base path
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return boolean False when the file was not pinned
+function M.remove_pin(path, picker)
+  if not M.state.initialized then return false end
+
+  local ok, result = pcall(fuzzy.remove_pin, path, picker)
+  if not ok then
+    vim.notify('Failed to unpin file: ' .. tostring(result), vim.log.levels.ERROR)
+    return false
+  end
+  return result
+end
+
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return table Absolute paths of the pinned files in the order they were pinned
+function M.list_pins(picker)
+  if not M.state.initialized then return {} end
+
+  local ok, result = pcall(fuzzy.list_pins, picker)
+  if not ok then return {} end
+  return result
+end
+
+--- Files related to the current one by the `related` naming rules: its tests, headers or sources, styles
+--- @param current_file string Path of the current file, absolute or relative to the picker base path
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return table File items of the index, the closest directories first
+function M.get_related_files(current_file, picker)
+  if not M.state.initialized then return {} end
+
+  local ok, result = pcall(fuzzy.get_related_files, current_file, picker)
+  if not ok then
+    vim.notify('Failed to find related files: ' .. tostring(result), vim.log.levels.ERROR)
+    return {}
+  end
+  return result
+end
+
+--- Search the contents of the indexed files without spawning a grep process
+--- @param pattern string Text to find, a regex with `mode = 'regex'`
+--- @param opts table|nil `mode` ('literal', 'regex' or 'fuzzy'), `smart_case`, `max_results`, `max_file_size` in bytes
+--- @param picker string|nil Base path of the picker, defaults to the active one
+--- @return table|nil `matches` with `path`, `relative_path`, `line`, `col`, `text`, `spans`, `location`, `score`
+function M.grep_files(pattern, opts, picker)
+  if not M.state.initialized then return nil end
+
+  local ok, result = pcall(fuzzy.grep_files, pattern, opts, picker)
+  if not ok then
+    vim.notify('Failed to grep files: ' .. tostring(result), vim.log.levels.ERROR)
+    return nil
+  end
+  return result
+end
+
+--- Stream the matching lines of the indexed files, starting a grep cancels the running one
+--- @param pattern string Text to find, a regex with `mode = 'regex'`
+--- @param opts table|nil Grep options, see `grep_files`, also `max_matches_per_file`
+--- @param on_batch function Called with each batch of new `matches`, the last one has `done` set.
+---   Batches with `ranked` set (fuzzy mode) hold all the best matches so far and replace the previous ones
+--- @param picker string|nil Base path of the picker, defaults to the active one
+function M.grep_files_async(pattern, opts, on_batch, picker)
+  if not M.state.initialized then return end
+
+  local ok, generation = pcall(fuzzy.grep_files_async, pattern, opts, picker)
+  if not ok then
+    vim.notify('Failed to grep files: ' .. tostring(generation), vim.log.levels.ERROR)
+    return
+  end
+
+  M.state.grep_generation = generation
+  if M.state.grep_poll_timer then
+    M.state.grep_poll_timer:stop()
+    M.state.grep_poll_timer:close()
+  end
+
+  local timer = vim.uv.new_timer()
+  M.state.grep_poll_timer = timer
+  local stop = function()
+    timer:stop()
+    timer:close()
+    M.state.grep_poll_timer = nil
+  end
+  timer:start(
+    0,
+    M.GREP_POLL_INTERVAL_MS,
+    vim.schedule_wrap(function()
+      if M.state.grep_poll_timer ~= timer then return end
+
+      local poll_ok, batch = pcall(fuzzy.poll_grep, generation)
+      -- nil once a newer grep or `cancel_grep` took over
+      if not poll_ok or not batch then return stop() end
+
+      if batch.done then stop() end
+      if #batch.matches > 0 or batch.done then on_batch(batch) end
+    end)
+  )
+end
+
+--- Stop the running grep, its remaining batches are dropped
+function M.cancel_grep()
+  if M.state.grep_poll_timer then
+    M.state.grep_poll_timer:stop()
+    M.state.grep_poll_timer:close()
+    M.state.grep_poll_timer = nil
+  end
+  if M.state.initialized then pcall(fuzzy.cancel_grep) end
+end
+
 --- Record file access for frecency tracking
 --- @param file_path string Path to the file that was accessed
 function M.track_access(file_path)
@@ -129,25 +543,29 @@ end
 function M.is_initialized() return M.state.initialized end
 
 --- Get scan progress information
+--- @param picker string|nil Base path of the picker, defaults to the active one
 --- @return table Progress information with scanned_files_count, is_scanning
-function M.get_scan_progress()
-  if not M.state.initialized then return { total_files = 0, scanned_files_count = 0, is_scanning = false } end
+function M.get_scan_progress(picker)
+  if not M.state.initialized then
+    return { total_files = 0, scanned_files_count = 0, is_scanning = false, is_scanning_ignored = false }
+  end
 
-  local ok, result = pcall(fuzzy.get_scan_progress)
+  local ok, result = pcall(fuzzy.get_scan_progress, picker)
   if not ok then
     vim.notify('Failed to get scan progress: ' .. result, vim.log.levels.WARN)
-    return { scanned_files_count = 0, is_scanning = false }
+    return { scanned_files_count = 0, is_scanning = false, is_scanning_ignored = false }
   end
 
   return result
 end
 
 --- Refresh git status on cached files (call after git status loading completes)
+--- @param picker string|nil Base path of the picker, defaults to the active one
 --- @return table List of files with updated git status
-function M.refresh_git_status()
+function M.refresh_git_status(picker)
   if not M.state.initialized then return {} end
 
-  local ok, result = pcall(fuzzy.refresh_git_status)
+  local ok, result = pcall(fuzzy.refresh_git_status, picker)
   if not ok then
     vim.notify('Failed to refresh git status: ' .. result, vim.log.levels.WARN)
     return {}
@@ -158,11 +576,12 @@ function M.refresh_git_status()
 end
 
 --- Stop background git status monitoring
+--- @param picker string|nil Base path of the picker, defaults to the active one
 --- @return boolean Success status
-function M.stop_background_monitor()
+function M.stop_background_monitor(picker)
   if not M.state.initialized then return false end
 
-  local ok, result = pcall(fuzzy.stop_background_monitor)
+  local ok, result = pcall(fuzzy.stop_background_monitor, picker)
   if not ok then
     vim.notify('Failed to stop background monitor: ' .. result, vim.log.levels.WARN)
     return false
@@ -172,11 +591,12 @@ end
 
 --- Wait for initial scan to complete
 --- @param timeout_ms number Optional timeout in milliseconds (default 5000)
+--- @param picker string|nil Base path of the picker, defaults to the active one
 --- @return boolean True if scan completed, false if timed out
-function M.wait_for_initial_scan(timeout_ms)
+function M.wait_for_initial_scan(timeout_ms, picker)
   if not M.state.initialized then return false end
 
-  local ok, result = pcall(fuzzy.wait_for_initial_scan, timeout_ms)
+  local ok, result = pcall(fuzzy.wait_for_initial_scan, timeout_ms, picker)
   if not ok then
     vim.notify('Failed to wait for initial scan: ' .. result, vim.log.levels.WARN)
     return false
@@ -184,6 +604,16 @@ function M.wait_for_initial_scan(timeout_ms)
   return result
 end
 
+--- Base paths of the pickers kept in memory, the active one comes last
+--- @return table
+function M.list_pickers()
+  if not M.state.initialized then return {} end
+
+  local ok, result = pcall(fuzzy.list_pickers)
+  if not ok then return {} end
+  return result
+end
+
 --- Get current state
 --- @return table
 function M.get_state() return M.state end
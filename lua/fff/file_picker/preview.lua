@@ -690,6 +690,38 @@ function M.get_file_config(file_path)
   return M.config.filetypes[filetype] or {}
 end
 
+--- List the entries of a directory, subdirectories first
+--- @param dir_path string Path to the directory
+--- @param bufnr number Buffer number for preview
+--- @return boolean Success status
+function M.preview_directory(dir_path, bufnr)
+  M.clear_buffer(bufnr)
+
+  local handle = vim.uv.fs_scandir(dir_path)
+  if not handle then
+    set_buffer_lines(bufnr, { 'Failed to read directory' })
+    return false
+  end
+
+  local dirs, files = {}, {}
+  while true do
+    local name, entry_type = vim.uv.fs_scandir_next(handle)
+    if not name then break end
+    if entry_type == 'directory' then
+      table.insert(dirs, name .. '/')
+    else
+      table.insert(files, name)
+    end
+  end
+  table.sort(dirs)
+  table.sort(files)
+
+  local lines = vim.list_extend(dirs, files)
+  if #lines == 0 then lines = { 'Empty directory' } end
+  set_buffer_lines(bufnr, lines)
+  return true
+end
+
 --- @param file_path string Path to the file or directory
 --- @param bufnr number Buffer number for preview
 --- @param location table|nil Optional location data for highlighting
@@ -717,7 +749,9 @@ function M.preview(file_path, bufnr, location)
   M.state.bufnr = bufnr
   M.state.location = location
 
-  if image.is_image(file_path) then
+  if vim.fn.isdirectory(file_path) == 1 then
+    return M.preview_directory(file_path, bufnr)
+  elseif image.is_image(file_path) then
     M.clear_buffer(bufnr)
 
     if not M.state.winid or not vim.api.nvim_win_is_valid(M.state.winid) then return false end
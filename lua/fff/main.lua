@@ -19,6 +19,54 @@ function M.find_files()
   end
 end
 
+--- Find directories containing the indexed files, selecting one changes the working directory
+function M.find_dirs()
+  local picker_ok, picker_ui = pcall(require, 'fff.picker_ui')
+  if picker_ok then
+    picker_ui.open({ mode = 'dirs', title = 'FFFDirs' })
+  else
+    vim.notify('Failed to load picker UI', vim.log.levels.ERROR)
+  end
+end
+
+--- Find files opened in any project, the most frecent first
+function M.find_recent()
+  local picker_ok, picker_ui = pcall(require, 'fff.picker_ui')
+  if picker_ok then
+    picker_ui.open({ mode = 'recent', title = 'FFFRecent' })
+  else
+    vim.notify('Failed to load picker UI', vim.log.levels.ERROR)
+  end
+end
+
+--- Open the file related to the current one (its test, header or source), asks which one if there are several
+function M.open_alternate()
+  local current_file = vim.api.nvim_buf_get_name(0)
+  if current_file == '' then
+    vim.notify('The current buffer has no file', vim.log.levels.WARN)
+    return
+  end
+
+  local file_picker = require('fff.file_picker')
+  if not file_picker.is_initialized() and not file_picker.setup() then return end
+
+  local related = file_picker.get_related_files(current_file)
+  if #related == 0 then
+    vim.notify('No related files for ' .. vim.fn.fnamemodify(current_file, ':.'), vim.log.levels.INFO)
+    return
+  end
+
+  local open = function(file)
+    if file then vim.cmd.edit(vim.fn.fnameescape(file.path)) end
+  end
+  if #related == 1 then return open(related[1]) end
+
+  vim.ui.select(related, {
+    prompt = 'Related files',
+    format_item = function(file) return file.relative_path end,
+  }, open)
+end
+
 function M.find_in_git_root()
   local git_root = vim.fn.system('git rev-parse --show-toplevel 2>/dev/null'):gsub('\n', '')
   if vim.v.shell_error ~= 0 then
@@ -59,6 +107,18 @@ function M.search(query, max_results)
   return {}
 end
 
+--- Search the contents of the indexed files
+--- @param pattern string Text to find
+--- @param opts table|nil Grep options, see `file_picker.grep_files`
+--- @return table List of matching lines
+function M.grep(pattern, opts)
+  local file_picker = require('fff.file_picker')
+  if not file_picker.is_initialized() and not file_picker.setup() then return {} end
+
+  local result = file_picker.grep_files(pattern, opts)
+  return result and result.matches or {}
+end
+
 --- Search and show results in a nice format
 --- @param query string Search query
 function M.search_and_show(query)
@@ -233,6 +293,7 @@ function M.open_file_under_cursor(open_cb)
       if open_cb and type(open_cb) == 'function' then open_cb(files[1].path) end
       vim.api.nvim_command(string.format('e %s', vim.fn.fnameescape(files[1].path)))
 
+      location = require('fff.file_picker').resolve_location(files[1].path, location)
       if location then vim.schedule(function() require('fff.location_utils').jump_to_location(location) end) end
 
       return true
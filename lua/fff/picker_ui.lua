@@ -251,6 +251,8 @@ M.state = {
   cursor = 1,
   top = 1,
   query = '',
+  include_ignored = false, -- Also search files hidden by the ignore rules
+  pushed_sources = nil, -- Buffers and oldfiles searched with the index, see config.sources
   item_line_map = {},
   location = nil, -- Current location from search results
 
@@ -734,6 +736,9 @@ function M.setup_keymaps()
   set_keymap('i', keymaps.preview_scroll_up, M.scroll_preview_up, input_opts)
   set_keymap('i', keymaps.preview_scroll_down, M.scroll_preview_down, input_opts)
   set_keymap('i', keymaps.toggle_debug, M.toggle_debug, input_opts)
+  set_keymap('i', keymaps.toggle_ignored, M.toggle_ignored, input_opts)
+  set_keymap('i', keymaps.explain_score, M.explain_score, input_opts)
+  set_keymap('i', keymaps.toggle_pin, M.toggle_pin, input_opts)
 
   local list_opts = { buffer = M.state.list_buf, noremap = true, silent = true }
 
@@ -747,6 +752,9 @@ function M.setup_keymaps()
   set_keymap('n', keymaps.preview_scroll_up, M.scroll_preview_up, list_opts)
   set_keymap('n', keymaps.preview_scroll_down, M.scroll_preview_down, list_opts)
   set_keymap('n', keymaps.toggle_debug, M.toggle_debug, list_opts)
+  set_keymap('n', keymaps.toggle_ignored, M.toggle_ignored, list_opts)
+  set_keymap('n', keymaps.explain_score, M.explain_score, list_opts)
+  set_keymap('n', keymaps.toggle_pin, M.toggle_pin, list_opts)
 
   if M.state.preview_buf then
     local preview_opts = { buffer = M.state.preview_buf, noremap = true, silent = true }
@@ -757,6 +765,7 @@ function M.setup_keymaps()
     set_keymap('n', keymaps.select_vsplit, function() M.select('vsplit') end, preview_opts)
     set_keymap('n', keymaps.select_tab, function() M.select('tab') end, preview_opts)
     set_keymap('n', keymaps.toggle_debug, M.toggle_debug, preview_opts)
+    set_keymap('n', keymaps.toggle_ignored, M.toggle_ignored, preview_opts)
   end
 
   vim.keymap.set('i', '<C-w>', function()
@@ -823,6 +832,144 @@ function M.toggle_debug()
   end
 end
 
+--- Toggle searching files hidden by .gitignore/.ignore for the current picker session
+function M.toggle_ignored()
+  if not M.state.active then return end
+
+  M.state.include_ignored = not M.state.include_ignored
+  local status = M.state.include_ignored and 'included' or 'excluded'
+  vim.notify('FFF ignored files ' .. status, vim.log.levels.INFO)
+
+  M.update_results()
+  -- the ignored files are indexed lazily on the first request so wait for the scan to finish
+  M.monitor_scan_progress(0)
+end
+
+--- Pin the file under the cursor to the top of the project results or unpin it
+function M.toggle_pin()
+  if not M.state.active then return end
+
+  local item = M.state.filtered_items[M.state.cursor]
+  if not item or item.is_dir then return end
+
+  local pinned
+  if item.pinned then
+    pinned = not file_picker.remove_pin(item.path)
+  else
+    pinned = file_picker.add_pin(item.path)
+  end
+  vim.notify(item.relative_path .. (pinned and ' pinned' or ' unpinned'), vim.log.levels.INFO)
+
+  M.update_results()
+end
+
+--- Show why the file under the cursor got its score and rank
+function M.explain_score()
+  if not M.state.active then return end
+
+  local item = M.state.filtered_items[M.state.cursor]
+  if not item then return end
+
+  local explanation = file_picker.explain(
+    M.state.query,
+    item.relative_path,
+    M.state.current_file_cache,
+    M.state.include_ignored
+  )
+  if not explanation then return end
+
+  local lines = { string.format('%s for "%s"', explanation.relative_path, explanation.query) }
+  local score = explanation.score
+  if not score then
+    table.insert(lines, 'No match')
+  else
+    table.insert(
+      lines,
+      string.format(
+        'Rank %d of %d, total %d (%s)',
+        explanation.rank,
+        explanation.total_matched,
+        score.total,
+        score.match_type
+      )
+    )
+    table.insert(
+      lines,
+      string.format(
+        'base %d, filename bonus %d, special filename %d, frecency %d, distance %d, current file %d, ignored %d, pin %d, related %d, co-access %d',
+        score.base_score,
+        score.filename_bonus,
+        score.special_filename_bonus,
+        score.frecency_boost,
+        score.distance_penalty,
+        score.current_file_penalty,
+        score.ignored_penalty,
+        score.pin_bonus or 0,
+        score.related_bonus or 0,
+        score.co_access_boost or 0
+      )
+    )
+  end
+
+  for _, match in ipairs({ { 'Path', explanation.path_match }, { 'Filename', explanation.filename_match } }) do
+    local details = match[2]
+    if details then
+      table.insert(
+        lines,
+        string.format(
+          '%s match: score %d, %d/%d typos%s',
+          match[1],
+          details.score,
+          details.typos,
+          explanation.max_typos,
+          details.exact and ', exact' or ''
+        )
+      )
+    end
+  end
+
+  local frecency = explanation.frecency
+  if frecency then
+    table.insert(
+      lines,
+      string.format(
+        'Access: %d accesses in the last 30 days, decayed %.2f, score %d',
+        #frecency.access_timestamps,
+        frecency.decayed_accesses,
+        frecency.access_score
+      )
+    )
+    local step = frecency.modification_step
+    if not frecency.git_modified then
+      table.insert(lines, 'Modification: not modified in git, score 0')
+    elseif step then
+      table.insert(
+        lines,
+        string.format(
+          'Modification: %ds ago, between %d points at %ds and %d points at %ds, score %d',
+          frecency.modified_seconds_ago,
+          step.newer_points,
+          step.newer_seconds,
+          step.older_points,
+          step.older_seconds,
+          frecency.modification_score
+        )
+      )
+    else
+      table.insert(lines, string.format('Modification: %ds ago, score 0', frecency.modified_seconds_ago))
+    end
+  end
+
+  if explanation.directory_distance then
+    table.insert(
+      lines,
+      string.format('Distance: %d directories up from %s', explanation.directory_distance, explanation.current_file)
+    )
+  end
+
+  vim.notify(table.concat(lines, '\n'), vim.log.levels.INFO)
+end
+
 --- Handle input change
 function M.on_input_change()
   if not M.state.active then return end
@@ -858,14 +1005,23 @@ function M.on_input_change()
 
   M.state.query = query
 
-  M.update_results_sync()
+  M.update_results()
 end
 
-function M.update_results() M.update_results_sync() end
-
-function M.update_results_sync()
-  if not M.state.active then return end
+function M.update_results()
+  if M.state.config.mode == 'dirs' then
+    M.update_results_dirs()
+  elseif M.state.config.mode == 'recent' then
+    M.update_results_recent()
+  elseif M.state.config.async_search then
+    M.update_results_async()
+  else
+    M.update_results_sync()
+  end
+end
 
+--- Arguments shared by the sync and async searches
+local function search_args()
   if not M.state.current_file_cache then
     local current_buf = vim.api.nvim_get_current_buf()
     if current_buf and vim.api.nvim_buf_is_valid(current_buf) then
@@ -886,14 +1042,17 @@ function M.update_results_sync()
     dynamic_max_results = M.state.config.max_results or 100
   end
 
-  local results = file_picker.search_files(
-    M.state.query,
+  return M.state.query,
     dynamic_max_results,
     M.state.config.max_threads,
     M.state.current_file_cache,
-    prompt_position == 'bottom'
-  )
+    prompt_position == 'bottom',
+    M.state.include_ignored,
+    nil,
+    M.state.pushed_sources
+end
 
+local function apply_results(results)
   -- Get location from search results
   M.state.location = file_picker.get_search_location()
 
@@ -901,7 +1060,7 @@ function M.update_results_sync()
   M.state.items = results
   M.state.filtered_items = results
 
-  if prompt_position == 'bottom' then
+  if get_prompt_position() == 'bottom' then
     M.state.cursor = #results > 0 and #results or 1
   else
     M.state.cursor = 1
@@ -910,6 +1069,49 @@ function M.update_results_sync()
   M.render_debounced()
 end
 
+function M.update_results_async()
+  if not M.state.active then return end
+
+  local query, max_results, max_threads, current_file, reverse_order, include_ignored, picker, sources =
+    search_args()
+  file_picker.search_files_async(
+    query,
+    max_results,
+    max_threads,
+    current_file,
+    reverse_order,
+    include_ignored,
+    function(results)
+      -- the picker could have been closed while the search was running
+      if M.state.active then apply_results(results) end
+    end,
+    picker,
+    sources
+  )
+end
+
+function M.update_results_sync()
+  if not M.state.active then return end
+
+  apply_results(file_picker.search_files(search_args()))
+end
+
+--- Recent files are few, searching them never blocks the typing
+function M.update_results_recent()
+  if not M.state.active then return end
+
+  local query, max_results, max_threads, _, reverse_order = search_args()
+  apply_results(file_picker.search_recent(query, max_results, max_threads, reverse_order))
+end
+
+--- Directory searches are cheap enough to run synchronously
+function M.update_results_dirs()
+  if not M.state.active then return end
+
+  local query, max_results, max_threads, _, reverse_order = search_args()
+  apply_results(file_picker.search_dirs(query, max_results, max_threads, reverse_order))
+end
+
 function M.update_preview_debounced()
   -- Cancel previous preview timer
   if M.state.preview_timer then
@@ -995,6 +1197,12 @@ local function format_file_display(item, max_width)
   return filename, display_path
 end
 
+--- Tags of the results which don't come from the scanned index
+M.SOURCE_TAGS = {
+  buffer = ' [buffer]',
+  oldfile = ' [recent]',
+}
+
 function M.render_list()
   if not M.state.active then return end
 
@@ -1032,7 +1240,7 @@ function M.render_list()
   for i = 1, display_count do
     local item = items[i]
 
-    local icon, icon_hl_group = icons.get_icon_display(item.name, item.extension, false)
+    local icon, icon_hl_group = icons.get_icon_display(item.name, item.extension, item.is_dir or false)
     icon_data[i] = { icon, icon_hl_group }
 
     local frecency = ''
@@ -1164,6 +1372,12 @@ function M.render_list()
             virt_text = { { ' (current)', virt_text_hl } },
             virt_text_pos = 'right_align',
           })
+        elseif item.pinned or M.SOURCE_TAGS[item.source] then
+          local virt_text_hl = is_cursor_line and M.state.config.hl.active_file or 'Comment'
+          vim.api.nvim_buf_set_extmark(M.state.list_buf, M.state.ns_id, line_idx - 1, 0, {
+            virt_text = { { item.pinned and ' [pinned]' or M.SOURCE_TAGS[item.source], virt_text_hl } },
+            virt_text_pos = 'right_align',
+          })
         end
 
         local border_char = ' '
@@ -1291,10 +1505,12 @@ function M.update_preview()
     title_pos = 'left',
   })
 
-  if M.state.file_info_buf then preview.update_file_info_buffer(item, M.state.file_info_buf, M.state.cursor) end
+  if M.state.file_info_buf and not item.is_dir then
+    preview.update_file_info_buffer(item, M.state.file_info_buf, M.state.cursor)
+  end
 
   preview.set_preview_window(M.state.preview_win)
-  preview.preview(item.path, M.state.preview_buf, M.state.location)
+  preview.preview(item.path, M.state.preview_buf, file_picker.resolve_location(item.path, M.state.location))
 end
 
 --- Clear preview
@@ -1336,6 +1552,8 @@ function M.update_status(progress)
 
   if progress and progress.is_scanning then
     status_info = string.format('Indexing files %d', progress.scanned_files_count)
+  elseif progress and progress.is_scanning_ignored then
+    status_info = 'Indexing ignored files'
   else
     local search_metadata = file_picker.get_search_metadata()
     if #M.state.query < 2 then
@@ -1453,11 +1671,18 @@ function M.select(action)
   action = action or 'edit'
 
   local relative_path = vim.fn.fnamemodify(item.path, ':.')
-  local location = M.state.location -- Capture location before closing
+  local location = file_picker.resolve_location(item.path, M.state.location) -- Capture location before closing
 
   vim.cmd('stopinsert')
   M.close()
 
+  if item.is_dir then
+    -- a directory becomes the working directory, splits open it in the file explorer
+    local commands = { edit = 'cd', split = 'split', vsplit = 'vsplit', tab = 'tabedit' }
+    vim.cmd(commands[action] .. ' ' .. vim.fn.fnameescape(item.path))
+    return
+  end
+
   if action == 'edit' then
     local current_buf = vim.api.nvim_get_current_buf()
     local current_buftype = vim.api.nvim_buf_get_option(current_buf, 'buftype')
@@ -1524,6 +1749,7 @@ function M.close()
     M.state.preview_timer:close()
     M.state.preview_timer = nil
   end
+  file_picker.cancel_search()
 
   M.state.input_win = nil
   M.state.list_win = nil
@@ -1537,6 +1763,8 @@ function M.close()
   M.state.filtered_items = {}
   M.state.cursor = 1
   M.state.query = ''
+  M.state.include_ignored = false
+  M.state.pushed_sources = nil
   M.state.ns_id = nil
   M.state.last_preview_file = nil
   M.state.last_preview_location = nil
@@ -1608,6 +1836,7 @@ local function open_ui_with_state(query, results, location, merged_config, curre
 
   M.state.active = true
   M.state.current_file_cache = current_file_cache
+  M.state.pushed_sources = file_picker.push_sources(merged_config.sources or {})
 
   -- Set up initial state
   if query then
@@ -1666,7 +1895,9 @@ function M.open_with_callback(query, callback, opts)
 
   local max_results = merged_config.max_results or 100
   local max_threads = merged_config.max_threads or 4
-  local results = file_picker.search_files(query, max_results, max_threads, current_file_cache, false)
+  local sources = file_picker.push_sources(merged_config.sources or {})
+  local results =
+    file_picker.search_files(query, max_results, max_threads, current_file_cache, false, nil, nil, sources)
 
   local metadata = file_picker.get_search_metadata()
   local location = file_picker.get_search_location()
@@ -1694,6 +1925,7 @@ end
 --- @param opts.prompt? string Input prompt text (default: "🪿 ")
 --- @param opts.max_results? number Maximum number of results to display (default: 100)
 --- @param opts.max_threads? number Maximum number of threads for file scanning (default: 4)
+--- @param opts.mode? string 'files', 'dirs' to pick a directory or 'recent' for the files opened in any project (default: 'files')
 --- @param opts.layout? table Layout configuration
 --- @param opts.layout.width? number|function Window width as ratio (0.0-1.0) or function(terminal_width, terminal_height): number (default: 0.8)
 --- @param opts.layout.height? number|function Window height as ratio (0.0-1.0) or function(terminal_width, terminal_height): number (default: 0.8)
@@ -1715,7 +1947,7 @@ function M.monitor_scan_progress(iteration)
 
   local progress = file_picker.get_scan_progress()
 
-  if progress.is_scanning then
+  if progress.is_scanning or progress.is_scanning_ignored then
     M.update_status(progress)
 
     local timeout
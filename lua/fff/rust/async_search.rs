@@ -0,0 +1,358 @@
+use crate::error::Error;
+use crate::file_picker::{FilePicker, FileSnapshot, SharedPicker};
+use crate::ranked_results::RankedResults;
+use crate::related_files::{RelatedMatcher, RelatedRules};
+use crate::types::{FileSource, SearchOptions};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Two workers are enough for a single prompt: a stale search that is already running doesn't
+/// delay the next one, every search is parallel on its own anyway.
+const SEARCH_WORKERS: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct SearchRequest {
+    pub picker: SharedPicker,
+    pub query: String,
+    pub max_results: usize,
+    pub max_threads: usize,
+    pub current_file: Option<String>,
+    pub reverse_order: bool,
+    pub include_ignored: bool,
+    /// Pushed sources ranked together with the index, see `FilePicker::set_source_files`
+    pub pushed_sources: Vec<FileSource>,
+    pub related_rules: Arc<RelatedRules>,
+    /// Scores of the files usually opened after the current file, by the absolute path
+    pub co_access: Option<Arc<HashMap<PathBuf, i64>>>,
+}
+
+impl SearchRequest {
+    /// The picker lock is only held to grab the snapshots, the search itself never blocks
+    /// the writers.
+    pub fn run(&self) -> Result<RankedResults, Error> {
+        let (sources, search_cache, current_file, current_root, query) = {
+            let picker = self.picker.read().map_err(|_| Error::AcquireItemLock)?;
+            if self.include_ignored {
+                picker.ensure_ignored_index();
+            }
+
+            // pushed files come first so they take over their scanned copies
+            let mut sources: Vec<(FileSource, FileSnapshot)> = self
+                .pushed_sources
+                .iter()
+                .filter(|source| source.is_pushed())
+                .filter_map(|&source| Some((source, picker.source_snapshot(source)?)))
+                .collect();
+            sources.push((FileSource::Index, picker.files_snapshot()));
+            if self.include_ignored
+                && let Some(ignored_files) = picker.ignored_files_snapshot()
+            {
+                sources.push((FileSource::Ignored, ignored_files));
+            }
+            let (current_file, current_root) = match self.current_file.as_deref() {
+                Some(current_file) => {
+                    let (current_file, current_root) = picker.resolve_current_file(current_file);
+                    (Some(current_file), current_root.map(str::to_owned))
+                }
+                None => (None, None),
+            };
+
+            let query = picker.relative_query(&self.query).into_owned();
+            (
+                sources,
+                picker.search_cache(),
+                current_file,
+                current_root,
+                query,
+            )
+        };
+
+        let sources: Vec<(FileSource, &FileSnapshot)> = sources
+            .iter()
+            .map(|(source, files)| (*source, files))
+            .collect();
+        let related = current_file
+            .as_deref()
+            .and_then(|current_file| RelatedMatcher::new(&self.related_rules, current_file));
+        let options = SearchOptions {
+            max_results: self.max_results,
+            max_threads: self.max_threads,
+            current_file: current_file.as_deref(),
+            current_root: current_root.as_deref(),
+            reverse_order: self.reverse_order,
+            related: related.as_ref(),
+            co_access: self.co_access.as_deref(),
+        };
+
+        Ok(FilePicker::rank_sources(
+            &sources,
+            &query,
+            &options,
+            Some(&search_cache),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct CompletedSearch {
+    pub generation: u64,
+    /// Kept to search again when the index changed before the last page was read
+    pub request: SearchRequest,
+    pub result: Result<RankedResults, Error>,
+}
+
+/// Runs searches off the Neovim main thread. Every submitted search gets a new generation,
+/// searches superseded by a newer one are skipped or their results are dropped, so the
+/// result slot only ever holds the result of the latest query.
+pub struct AsyncSearch {
+    pool: rayon::ThreadPool,
+    latest_generation: Arc<AtomicU64>,
+    completed: Arc<Mutex<Option<CompletedSearch>>>,
+}
+
+impl AsyncSearch {
+    pub fn new() -> Result<Self, Error> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(SEARCH_WORKERS)
+            .thread_name(|index| format!("fff-search-{}", index))
+            .build()?;
+
+        Ok(Self {
+            pool,
+            latest_generation: Arc::new(AtomicU64::new(0)),
+            completed: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn submit(&self, request: SearchRequest) -> u64 {
+        let generation = self.latest_generation.fetch_add(1, Ordering::AcqRel) + 1;
+        let latest_generation = Arc::clone(&self.latest_generation);
+        let completed = Arc::clone(&self.completed);
+
+        self.pool.spawn(move || {
+            if latest_generation.load(Ordering::Acquire) != generation {
+                debug!(generation, "Skipping superseded search");
+                return;
+            }
+
+            let result = request.run();
+            if latest_generation.load(Ordering::Acquire) != generation {
+                debug!(generation, "Dropping result of superseded search");
+                return;
+            }
+
+            if let Ok(mut completed) = completed.lock()
+                && completed
+                    .as_ref()
+                    .is_none_or(|search| search.generation < generation)
+            {
+                *completed = Some(CompletedSearch {
+                    generation,
+                    request,
+                    result,
+                });
+            }
+        });
+
+        generation
+    }
+
+    pub fn latest_generation(&self) -> u64 {
+        self.latest_generation.load(Ordering::Acquire)
+    }
+
+    /// Takes the result of the given search once it's ready. Returns `None` while it's running
+    /// and forever if it was superseded by a newer search.
+    pub fn take_result(&self, generation: u64) -> Option<CompletedSearch> {
+        let mut completed = self.completed.lock().ok()?;
+        if completed
+            .as_ref()
+            .is_some_and(|search| search.generation == generation)
+        {
+            completed.take()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_for_result(search: &AsyncSearch, generation: u64) -> Option<CompletedSearch> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(completed) = search.take_result(generation) {
+                return Some(completed);
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        None
+    }
+
+    #[test]
+    fn test_latest_search_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["main.rs", "lib.rs", "README.md"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+
+        let picker = FilePicker::new(dir.path().to_string_lossy().to_string()).unwrap();
+        crate::file_picker::wait_for_scan(&picker);
+
+        let search = AsyncSearch::new().unwrap();
+        let request = |query: &str| SearchRequest {
+            picker: Arc::clone(&picker),
+            query: query.to_string(),
+            max_results: 10,
+            max_threads: 1,
+            current_file: None,
+            reverse_order: false,
+            include_ignored: false,
+            pushed_sources: vec![],
+            related_rules: Arc::default(),
+            co_access: None,
+        };
+
+        let stale = search.submit(request("main"));
+        let latest = search.submit(request("readme"));
+        assert_eq!(search.latest_generation(), latest);
+
+        let completed = wait_for_result(&search, latest).unwrap();
+        let mut result = completed.result.unwrap();
+        assert_eq!(result.page(0, 10).items[0].relative_path, "README.md");
+
+        // the superseded search never shows up even after the latest one was consumed
+        assert!(search.take_result(stale).is_none());
+        assert!(search.take_result(latest).is_none());
+    }
+
+    #[test]
+    fn test_pushed_files_replace_scanned_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        for file in ["main.rs", "lib.rs"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        std::fs::write(outside.path().join("notes.rs"), "").unwrap();
+
+        let picker = FilePicker::new(dir.path().to_string_lossy().to_string()).unwrap();
+        crate::file_picker::wait_for_scan(&picker);
+
+        let roots = picker.read().unwrap().roots().to_vec();
+        let indexed = picker.read().unwrap().files_snapshot();
+        let buffers = FilePicker::pushed_files(
+            dir.path(),
+            &roots,
+            std::slice::from_ref(&indexed),
+            vec![
+                dir.path().join("main.rs"),
+                outside.path().join("notes.rs"),
+                outside.path().join("deleted.rs"),
+            ],
+            None,
+        );
+        assert_eq!(buffers.len(), 2);
+        // the scanned file is shared with the index, its git status and frecency included
+        let main = buffers.iter().find(|file| file.file_name == "main.rs");
+        assert!(indexed.iter().any(|file| Arc::ptr_eq(file, main.unwrap())));
+        picker
+            .write()
+            .unwrap()
+            .set_source_files(FileSource::Buffer, buffers);
+
+        let request = SearchRequest {
+            picker: Arc::clone(&picker),
+            query: "rs".to_string(),
+            max_results: 10,
+            max_threads: 1,
+            current_file: None,
+            reverse_order: false,
+            include_ignored: false,
+            pushed_sources: vec![FileSource::Buffer],
+            related_rules: Arc::default(),
+            co_access: None,
+        };
+        let mut results = request.run().unwrap();
+        let page = results.page(0, 10);
+        let mut sources: Vec<(&str, FileSource)> = page
+            .items
+            .iter()
+            .zip(&page.sources)
+            .map(|(file, source)| (file.file_name.as_str(), *source))
+            .collect();
+        sources.sort_by_key(|(name, _)| *name);
+
+        assert_eq!(
+            sources,
+            vec![
+                ("lib.rs", FileSource::Index),
+                ("main.rs", FileSource::Buffer),
+                ("notes.rs", FileSource::Buffer),
+            ]
+        );
+        // files of the roots keep the relative path of their scanned copy
+        let main = page.items.iter().find(|file| file.file_name == "main.rs");
+        assert_eq!(main.unwrap().relative_path, "main.rs");
+    }
+
+    #[test]
+    fn test_ignored_files_rank_below_tracked() {
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        for file in ["src/config.rs", "target/config.rs"] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let picker = FilePicker::new(dir.path().to_string_lossy().to_string()).unwrap();
+        crate::file_picker::wait_for_scan(&picker);
+
+        let request = SearchRequest {
+            picker: Arc::clone(&picker),
+            query: "config".to_string(),
+            max_results: 10,
+            max_threads: 1,
+            current_file: None,
+            reverse_order: false,
+            include_ignored: true,
+            pushed_sources: vec![],
+            related_rules: Arc::default(),
+            co_access: None,
+        };
+        // the first search only starts the walk of the ignored files
+        request.run().unwrap();
+        let start = Instant::now();
+        while picker.read().unwrap().ignored_files_snapshot().is_none() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut results = request.run().unwrap();
+        let page = results.page(0, 10);
+        let ranked: Vec<(&str, FileSource)> = page
+            .items
+            .iter()
+            .zip(&page.sources)
+            .map(|(file, source)| (file.relative_path.as_str(), *source))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                ("src/config.rs", FileSource::Index),
+                ("target/config.rs", FileSource::Ignored),
+            ]
+        );
+        assert_eq!(page.items[1].git_status(), Some(git2::Status::IGNORED));
+        assert_eq!(page.scores[0].ignored_penalty, 0);
+        assert!(page.scores[1].ignored_penalty < 0);
+    }
+}
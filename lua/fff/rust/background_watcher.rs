@@ -1,10 +1,14 @@
-use crate::FILE_PICKER;
 use crate::error::Error;
-use crate::file_picker::FilePicker;
+use crate::file_picker::{FilePicker, SharedPicker, WeakPicker};
 use crate::git::GitStatusCache;
+use crate::scan_config::{FFF_IGNORE_FILENAME, ScanFilter};
+use crate::trigram_index::{file_trigrams, modified_secs};
 use git2::Repository;
+use ignore::Match;
 use notify::{EventKind, RecursiveMode};
-use notify_debouncer_full::{DebounceEventResult, DebouncedEvent, RecommendedCache, new_debouncer};
+use notify_debouncer_full::{
+    DebounceEventResult, DebouncedEvent, RecommendedCache, new_debouncer_opt,
+};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -20,13 +24,18 @@ const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
 const MAX_PATHS_THRESHOLD: usize = 50;
 
 impl BackgroundWatcher {
-    pub fn new(base_path: PathBuf, git_workdir: Option<PathBuf>) -> Result<Self, Error> {
+    pub fn new(
+        picker: WeakPicker,
+        base_path: PathBuf,
+        git_workdir: Option<PathBuf>,
+        follow_symlinks: bool,
+    ) -> Result<Self, Error> {
         info!(
             "Initializing background watcher for path: {}",
             base_path.display()
         );
 
-        let debouncer = Self::create_debouncer(base_path, git_workdir)?;
+        let debouncer = Self::create_debouncer(picker, base_path, git_workdir, follow_symlinks)?;
         info!("Background file watcher initialized successfully");
 
         Ok(Self {
@@ -35,17 +44,23 @@ impl BackgroundWatcher {
     }
 
     fn create_debouncer(
+        picker: WeakPicker,
         base_path: PathBuf,
         git_workdir: Option<PathBuf>,
+        follow_symlinks: bool,
     ) -> Result<Debouncer, Error> {
-        let mut debouncer = new_debouncer(
+        // follow exactly the same symlinks as the initial walk otherwise the watcher would
+        // add files from the linked directories the index intentionally skipped
+        let config = notify::Config::default().with_follow_symlinks(follow_symlinks);
+        let root_path = base_path.clone();
+        let mut debouncer = new_debouncer_opt(
             DEBOUNCE_TIMEOUT,
             Some(DEBOUNCE_TIMEOUT / 4), // tick rate for the event span
             {
                 move |result: DebounceEventResult| match result {
                     Ok(events) => {
                         if !events.is_empty() {
-                            handle_debounced_events(events, &git_workdir);
+                            handle_debounced_events(events, &picker, &root_path, &git_workdir);
                         }
                     }
                     Err(errors) => {
@@ -53,6 +68,8 @@ impl BackgroundWatcher {
                     }
                 }
             },
+            RecommendedCache::new(),
+            config,
         )?;
 
         debouncer.watch(base_path.as_path(), RecursiveMode::Recursive)?;
@@ -83,14 +100,35 @@ impl Drop for BackgroundWatcher {
     }
 }
 
-#[tracing::instrument(skip(events), level = Level::DEBUG)]
-fn handle_debounced_events(events: Vec<DebouncedEvent>, git_workdir: &Option<PathBuf>) {
+#[tracing::instrument(skip(events, picker), level = Level::DEBUG)]
+fn handle_debounced_events(
+    events: Vec<DebouncedEvent>,
+    picker: &WeakPicker,
+    root_path: &Path,
+    git_workdir: &Option<PathBuf>,
+) {
+    // the picker was dropped, the watcher is about to be stopped as well
+    let Some(picker) = picker.upgrade() else {
+        return;
+    };
+
+    // the filter is replaced when the ignore files change, see `spawn_full_rescan`
+    let Some(scan_filter) = picker
+        .read()
+        .ok()
+        .and_then(|picker| picker.root_scan_filter(root_path))
+    else {
+        return;
+    };
+    let scan_filter = scan_filter.as_ref();
+
     // this will be called very often, we have to minimiy the lock time for file picker
     let repo = git_workdir.as_ref().and_then(|p| Repository::open(p).ok());
     let mut need_full_rescan = false;
     let mut need_full_git_rescan = false;
     let mut paths_to_remove = Vec::new();
     let mut paths_to_add_or_modify = Vec::new();
+    let mut ignored_paths = Vec::new();
     let mut affected_paths_count = 0usize;
 
     for debounced_event in &events {
@@ -114,7 +152,11 @@ fn handle_debounced_events(events: Vec<DebouncedEvent>, git_workdir: &Option<Pat
                 need_full_git_rescan = true;
             }
 
-            if !should_include_file(path, &repo) {
+            if !should_include_file(path, &repo, scan_filter) {
+                if is_ignored_file(path, &repo, scan_filter) {
+                    ignored_paths.push(path.as_path());
+                }
+
                 continue;
             }
 
@@ -141,8 +183,9 @@ fn handle_debounced_events(events: Vec<DebouncedEvent>, git_workdir: &Option<Pat
         }
     }
 
+    // the walk picks up every other change of the batch as well
     if need_full_rescan {
-        error!("NEED A FULL RESCAN");
+        spawn_full_rescan(picker);
         return;
     }
 
@@ -150,60 +193,103 @@ fn handle_debounced_events(events: Vec<DebouncedEvent>, git_workdir: &Option<Pat
         return;
     };
 
-    if need_full_git_rescan {
-        info!("Triggering full git rescan by the notification results");
-
-        if let Err(e) = FilePicker::refresh_git_status_global() {
-            error!("Failed to refresh git status: {:?}", e);
-        }
-
-        return;
-    }
+    // the statuses are read before locking the picker so the whole batch is applied at once,
+    // a full refresh replaces them anyway
+    let status = if need_full_git_rescan {
+        None
+    } else {
+        let workdir = repo.workdir().unwrap_or(root_path);
+        let pathspecs: Vec<&Path> = paths_to_add_or_modify
+            .iter()
+            .filter_map(|path| path.strip_prefix(workdir).ok())
+            .collect();
+        GitStatusCache::git_status_for_paths(repo, &pathspecs)
+    };
 
-    let files_to_update_git_status = {
-        let Ok(mut file_picker_guard) = FILE_PICKER.write() else {
+    let content_index = {
+        let Ok(mut picker) = picker.write() else {
             error!("Failed to acquire file picker write lock");
             return;
         };
 
-        let Some(ref mut picker) = *file_picker_guard else {
-            error!("File picker not initialized");
-            return;
-        };
-
-        // Apply file removals
-        for path in paths_to_remove {
-            picker.remove_file_by_path(path);
+        if let Err(e) = picker.apply_watcher_changes(
+            &paths_to_remove,
+            &paths_to_add_or_modify,
+            &ignored_paths,
+            status,
+        ) {
+            error!("Failed to apply the file changes: {:?}", e);
         }
+        picker.content_index()
+    };
 
-        // Apply file additions/modifications and collect paths for git status update
-        let mut files_to_update_git_status = Vec::with_capacity(paths_to_add_or_modify.len());
-        for path in paths_to_add_or_modify {
-            if let Some(file) = picker.on_create_or_modify(path) {
-                files_to_update_git_status.push(file.relative_path.clone());
+    // the files are read before locking the index, the greps only skip it for the update itself
+    if let Some(content_index) = content_index {
+        let trigrams: Vec<_> = paths_to_add_or_modify
+            .iter()
+            .map(|path| (path, file_trigrams(path), modified_secs(path)))
+            .collect();
+        if let Ok(mut index) = content_index.write() {
+            for path in &paths_to_remove {
+                index.remove(path);
+            }
+            for (path, trigrams, modified) in trigrams {
+                match trigrams {
+                    Some(trigrams) => index.insert(path, modified, &trigrams),
+                    None => index.remove(path),
+                }
             }
         }
+    }
 
-        files_to_update_git_status
-    };
+    if need_full_git_rescan {
+        info!("Triggering full git rescan by the notification results");
 
-    let status = GitStatusCache::git_status_for_paths(repo, &files_to_update_git_status);
-    // only lock the picker for theshortest possitble time
-    if let Ok(mut file_picker_guard) = FILE_PICKER.write()
-        && let Some(ref mut picker) = *file_picker_guard
-        && let Err(e) = picker.update_git_statuses(status)
-    {
-        error!("Failed to update git statuses: {:?}", e);
+        if let Err(e) = FilePicker::refresh_git_status_shared(&picker) {
+            error!("Failed to refresh git status: {:?}", e);
+        }
     }
 }
 
-fn should_include_file(path: &Path, repo: &Option<Repository>) -> bool {
+/// Rescans the picker with the ignore rules compiled again off the watcher thread, so the
+/// events arriving during the walk are still applied
+fn spawn_full_rescan(picker: SharedPicker) {
+    info!("Triggering full rescan by the notification results");
+
+    std::thread::spawn(move || {
+        let rescanned = picker
+            .write()
+            .map_err(|_| Error::AcquireItemLock)
+            .and_then(|mut picker| picker.reload_scan_filters())
+            .and_then(|()| FilePicker::trigger_rescan_shared(&picker));
+        if let Err(e) = rescanned {
+            error!("Failed to rescan the files: {:?}", e);
+        }
+    });
+}
+
+fn should_include_file(path: &Path, repo: &Option<Repository>, scan_filter: &ScanFilter) -> bool {
     if !path.is_file() || is_git_file(path) {
         return false;
     }
 
-    repo.as_ref()
-        .is_some_and(|repo| repo.is_path_ignored(path) == Ok(false))
+    match scan_filter.matched(path) {
+        Match::Ignore(_) => false,
+        // picker whitelist takes precedence over the repository ignore rules same as in walker
+        Match::Whitelist(_) => repo.is_some(),
+        Match::None => repo
+            .as_ref()
+            .is_some_and(|repo| repo.is_path_ignored(path) == Ok(false)),
+    }
+}
+
+/// Ignored files are only tracked when the ignored index was requested by a search
+fn is_ignored_file(path: &Path, repo: &Option<Repository>, scan_filter: &ScanFilter) -> bool {
+    !is_git_file(path)
+        && !scan_filter.matched(path).is_ignore()
+        && repo
+            .as_ref()
+            .is_some_and(|repo| repo.is_path_ignored(path) == Ok(true))
 }
 
 #[inline]
@@ -249,6 +335,120 @@ pub fn is_dotgit_change_affecting_status(changed: &Path, repo: &Option<Repositor
 fn is_ignore_definition_path(path: &Path) -> bool {
     matches!(
         path.file_name().and_then(|f| f.to_str()),
-        Some(".ignore") | Some(".gitignore")
+        Some(".ignore") | Some(".gitignore") | Some(FFF_IGNORE_FILENAME)
     )
 }
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use crate::file_picker::wait_for_watchers;
+    use crate::scan_config::ScanConfig;
+    use std::time::Instant;
+
+    fn relative_paths(picker: &SharedPicker) -> Vec<String> {
+        picker
+            .read()
+            .unwrap()
+            .get_files()
+            .iter()
+            .map(|file| file.relative_path.clone())
+            .collect()
+    }
+
+    /// Polls the index until it satisfies the condition, the watcher debounces the events
+    fn wait_for_index(picker: &SharedPicker, condition: impl Fn(&[String]) -> bool) {
+        let start = Instant::now();
+        while !condition(&relative_paths(picker)) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "The index didn't change in 5 s: {:?}",
+                relative_paths(picker)
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_fffignore_change_rescans_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        Repository::init(&base_path).unwrap();
+        for file in ["src/main.rs", "data/big.csv"] {
+            let path = base_path.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let picker = FilePicker::new(base_path.to_string_lossy().to_string()).unwrap();
+        wait_for_watchers(&picker);
+        assert_eq!(relative_paths(&picker), vec!["data/big.csv", "src/main.rs"]);
+
+        std::fs::write(base_path.join(FFF_IGNORE_FILENAME), "data/\n").unwrap();
+        wait_for_index(&picker, |paths| {
+            !paths.iter().any(|path| path == "data/big.csv")
+        });
+        assert_eq!(
+            relative_paths(&picker),
+            vec![FFF_IGNORE_FILENAME, "src/main.rs"]
+        );
+
+        // the reloaded rules apply to the files created afterwards as well
+        std::fs::write(base_path.join("data/other.csv"), "").unwrap();
+        std::fs::write(base_path.join("src/lib.rs"), "").unwrap();
+        wait_for_index(&picker, |paths| {
+            paths.iter().any(|path| path == "src/lib.rs")
+        });
+        assert_eq!(
+            relative_paths(&picker),
+            vec![FFF_IGNORE_FILENAME, "src/lib.rs", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn test_symlinked_files_are_indexed_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        Repository::init(&base_path).unwrap();
+        std::fs::create_dir_all(base_path.join("shared/proto")).unwrap();
+        std::fs::write(base_path.join("shared/proto/user.proto"), "").unwrap();
+        std::os::unix::fs::symlink(base_path.join("shared"), base_path.join("linked")).unwrap();
+        std::fs::write(outside.path().join("vendor.proto"), "").unwrap();
+        std::os::unix::fs::symlink(outside.path(), base_path.join("ext")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), base_path.join("ext-copy")).unwrap();
+
+        let config = ScanConfig {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let picker =
+            FilePicker::new_with_config(base_path.to_string_lossy().to_string(), config).unwrap();
+        wait_for_watchers(&picker);
+        let indexed = vec!["ext/vendor.proto", "shared/proto/user.proto"];
+        assert_eq!(relative_paths(&picker), indexed);
+
+        std::fs::write(base_path.join("shared/proto/user.proto"), "message User {}").unwrap();
+        std::fs::write(base_path.join("shared/proto/order.proto"), "").unwrap();
+        wait_for_index(&picker, |paths| paths.len() > 2);
+        // the events of the other path of the same directory come within the same batch
+        std::thread::sleep(DEBOUNCE_TIMEOUT * 2);
+        assert_eq!(
+            relative_paths(&picker),
+            vec![
+                "ext/vendor.proto",
+                "shared/proto/order.proto",
+                "shared/proto/user.proto"
+            ]
+        );
+
+        // a file outside of the roots keeps the path it was indexed by
+        let mut picker = picker.write().unwrap();
+        let file = picker.on_create_or_modify(base_path.join("ext-copy/vendor.proto"));
+        assert_eq!(file.unwrap().relative_path, "ext/vendor.proto");
+        let file = picker.on_create_or_modify(base_path.join("linked/proto/order.proto"));
+        assert_eq!(file.unwrap().relative_path, "shared/proto/order.proto");
+        assert_eq!(picker.get_files().len(), 3);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::file_picker::{FileSnapshot, WeakSnapshot};
+use crate::types::{DirItem, FileItem};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub type DirSnapshot = Arc<Vec<DirItem>>;
+
+/// Directories of the indexed files. The set is derived from the files snapshot and rebuilt by
+/// the first directory search after the files changed: adding or removing files, watcher events
+/// included, moves the snapshot to a new allocation the same way it invalidates `SearchCache`.
+/// Frecency updates don't, they `clear` the set.
+#[derive(Debug, Default)]
+pub struct DirIndex {
+    entry: Mutex<Option<(WeakSnapshot, DirSnapshot)>>,
+}
+
+impl DirIndex {
+    pub fn dirs(&self, files: &FileSnapshot) -> DirSnapshot {
+        let Ok(mut entry) = self.entry.lock() else {
+            return Arc::new(derive_dirs(files));
+        };
+
+        if let Some((snapshot, dirs)) = entry.as_ref()
+            && std::ptr::eq(snapshot.as_ptr(), Arc::as_ptr(files))
+        {
+            return Arc::clone(dirs);
+        }
+
+        let dirs = Arc::new(derive_dirs(files));
+        *entry = Some((Arc::downgrade(files), Arc::clone(&dirs)));
+        dirs
+    }
+
+    /// The frecency of the files is updated in place without a new snapshot, the directories
+    /// have to be derived again
+    pub fn clear(&self) {
+        if let Ok(mut entry) = self.entry.lock() {
+            *entry = None;
+        }
+    }
+}
+
+#[derive(Default)]
+struct DirStats {
+    path: PathBuf,
+    file_count: usize,
+    access_frecency: i64,
+}
+
+/// Every ancestor directory of the files below the roots, sorted by the relative path
+pub fn derive_dirs(files: &[Arc<FileItem>]) -> Vec<DirItem> {
+    let mut dirs: BTreeMap<&str, DirStats> = BTreeMap::new();
+    for file in files {
+        // the relative path can start with a root label, walk both in lockstep
+        let mut relative_dir = Path::new(&file.relative_path).parent();
+        let mut dir = file.path.parent();
+        while let (Some(relative), Some(path)) = (relative_dir, dir) {
+            let Some(relative_str) = relative.to_str().filter(|relative| !relative.is_empty())
+            else {
+                break;
+            };
+
+            let stats = dirs.entry(relative_str).or_insert_with(|| DirStats {
+                path: path.to_path_buf(),
+                ..Default::default()
+            });
+            stats.file_count += 1;
+            stats.access_frecency += file.access_frecency_score();
+
+            relative_dir = relative.parent();
+            dir = path.parent();
+        }
+    }
+
+    dirs.into_iter()
+        .map(|(relative_path, stats)| {
+            let dir_name = Path::new(relative_path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| relative_path.to_string());
+
+            DirItem {
+                path: stats.path,
+                relative_path: relative_path.to_string(),
+                relative_path_lower: relative_path.to_lowercase(),
+                dir_name_lower: dir_name.to_lowercase(),
+                dir_name,
+                file_count: stats.file_count,
+                frecency_score: normalize_frecency(stats.access_frecency),
+            }
+        })
+        .collect()
+}
+
+/// Top level directories contain most of the accessed files, the sum grows slowly past 10 so
+/// they don't outrank every better matching directory
+fn normalize_frecency(total_frecency: i64) -> i64 {
+    if total_frecency <= 10 {
+        total_frecency
+    } else {
+        10 + ((total_frecency - 10) as f64).sqrt().round() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_picker::FilePicker;
+
+    #[test]
+    fn test_dirs_follow_index_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(base_path.join("src/picker")).unwrap();
+        std::fs::create_dir_all(base_path.join("docs")).unwrap();
+        for file in [
+            "src/lib.rs",
+            "src/picker/mod.rs",
+            "docs/guide.md",
+            "README.md",
+        ] {
+            std::fs::write(base_path.join(file), "").unwrap();
+        }
+
+        let picker = FilePicker::new(base_path.to_string_lossy().to_string()).unwrap();
+        crate::file_picker::wait_for_scan(&picker);
+
+        let dirs_of = |picker: &FilePicker| {
+            picker
+                .dir_index()
+                .dirs(&picker.files_snapshot())
+                .iter()
+                .map(|dir| (dir.relative_path.clone(), dir.file_count))
+                .collect::<Vec<_>>()
+        };
+
+        // unchanged files reuse the derived set
+        let dir_index = picker.read().unwrap().dir_index();
+        let snapshot = picker.read().unwrap().files_snapshot();
+        let dirs = dir_index.dirs(&snapshot);
+        assert!(Arc::ptr_eq(&dirs, &dir_index.dirs(&snapshot)));
+        assert_eq!(dirs[1].path, base_path.join("src"));
+        drop(snapshot);
+
+        assert_eq!(
+            dirs_of(&picker.read().unwrap()),
+            vec![
+                ("docs".to_string(), 1),
+                ("src".to_string(), 2),
+                ("src/picker".to_string(), 1)
+            ]
+        );
+
+        assert!(
+            picker
+                .write()
+                .unwrap()
+                .remove_file_by_path(base_path.join("docs/guide.md"))
+        );
+        assert_eq!(
+            dirs_of(&picker.read().unwrap()),
+            vec![("src".to_string(), 2), ("src/picker".to_string(), 1)]
+        );
+    }
+}
@@ -33,6 +33,24 @@ pub enum Error {
     DbCommit(#[source] heed::Error),
     #[error("Failed to start file system watcher: {0}")]
     FileSystemWatch(#[from] notify::Error),
+    #[error("Invalid indexing glob: {0}")]
+    InvalidGlob(#[from] ignore::Error),
+    #[error("Unknown file source {0}")]
+    InvalidFileSource(String),
+    #[error("Failed to start search workers: {0}")]
+    SearchWorkers(#[from] rayon::ThreadPoolBuildError),
+    #[error("Failed to read file: {0}")]
+    ReadFile(#[source] std::io::Error),
+    #[error("Invalid location pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+    #[error("Invalid grep pattern: {0}")]
+    InvalidGrepPattern(#[source] regex::Error),
+    #[error("Unknown grep mode {0}, expected \"literal\" or \"regex\"")]
+    InvalidGrepMode(String),
+    #[error("Failed to write content index: {0}")]
+    ContentIndexWrite(#[source] std::io::Error),
+    #[error("Invalid content index file {0}")]
+    InvalidContentIndex(std::path::PathBuf),
 }
 
 impl From<Error> for mlua::Error {
@@ -0,0 +1,281 @@
+use crate::file_picker::{FilePicker, FileSnapshot};
+use crate::frecency::{FrecencyExplanation, FrecencyTracker};
+use crate::path_utils::directory_distance;
+use crate::score::score_candidates;
+use crate::search_cache::IndexedPaths;
+use crate::types::{FileItem, Score, SearchOptions};
+use mlua::prelude::*;
+use neo_frizbee::smith_waterman::reference::{
+    char_indices_from_score_matrix, smith_waterman, typos_from_score_matrix,
+};
+use std::path::MAIN_SEPARATOR;
+use std::sync::Arc;
+
+/// How the query aligned with the path or the file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchDetails {
+    pub score: u16,
+    pub exact: bool,
+    pub typos: u16,
+    /// Positions of the matched characters
+    pub indices: Vec<usize>,
+}
+
+/// Why a file got its score and where it ranks for the query
+#[derive(Debug, Clone)]
+pub struct ScoreExplanation {
+    /// The query as it's matched, without the location suffix
+    pub query: String,
+    pub relative_path: String,
+    pub max_typos: u16,
+    /// `None` when the file doesn't match the query
+    pub score: Option<Score>,
+    /// 1-based position among all the matches in the order of the search
+    pub rank: Option<usize>,
+    pub total_matched: usize,
+    pub path_match: Option<MatchDetails>,
+    pub filename_match: Option<MatchDetails>,
+    /// `None` without the frecency database
+    pub frecency: Option<FrecencyExplanation>,
+    pub current_file: Option<String>,
+    pub current_root: Option<String>,
+    /// Directories between the current file and the file, see `directory_distance`
+    pub directory_distance: Option<usize>,
+}
+
+/// Re-runs the search scoring for a single file and collects the inputs of every component of
+/// its score. Returns `None` when the file is not indexed. The `indexed_paths` must be built
+/// from the `sources`, see `SearchCache::indexed_paths`.
+pub fn explain_score(
+    sources: &[&FileSnapshot],
+    indexed_paths: &IndexedPaths,
+    relative_path: &str,
+    query: &str,
+    options: &SearchOptions,
+    frecency: Option<&FrecencyTracker>,
+) -> Option<ScoreExplanation> {
+    let (source, index) = sources.iter().enumerate().find_map(|(source, files)| {
+        files
+            .iter()
+            .position(|file| file.relative_path == relative_path)
+            .map(|index| (source, index))
+    })?;
+    let file = &sources[source][index];
+    let files: Vec<&[Arc<FileItem>]> = sources.iter().map(|files| files.as_slice()).collect();
+    let (context, _) = FilePicker::scoring_context(query, options, &files, Some(indexed_paths));
+
+    // the rank needs every match anyway, the score of the file comes from the same pass
+    let mut score = None;
+    let mut ranked = Vec::new();
+    for (source_index, files) in sources.iter().enumerate() {
+        let (matches, _) = score_candidates(files, None, &context);
+        for (file_index, file_score) in matches {
+            ranked.push((file_score.total, files[file_index as usize].modified));
+            if source_index == source && file_index as usize == index {
+                score = Some(file_score);
+            }
+        }
+    }
+
+    // same order as the search: higher score first, then the most recently modified
+    let rank = score.as_ref().map(|score| {
+        let position = (score.total, file.modified);
+        1 + ranked.iter().filter(|&&other| other > position).count()
+    });
+
+    let is_fuzzy_match = context.query.len() >= 2;
+    let path_match = is_fuzzy_match
+        .then(|| match_details(context.query, &file.relative_path_lower, context.max_typos))
+        .flatten();
+    let filename_match = (is_fuzzy_match && !context.query.contains(MAIN_SEPARATOR))
+        .then(|| match_details(context.query, &file.file_name_lower, context.max_typos))
+        .flatten();
+
+    Some(ScoreExplanation {
+        query: context.query.to_string(),
+        relative_path: file.relative_path.clone(),
+        max_typos: context.max_typos,
+        score,
+        rank,
+        total_matched: ranked.len(),
+        path_match,
+        filename_match,
+        frecency: frecency
+            .map(|tracker| tracker.explain(&file.path, file.modified, file.git_status())),
+        current_file: options.current_file.map(str::to_string),
+        current_root: options.current_root.map(str::to_string),
+        directory_distance: options
+            .current_file
+            .map(|current_file| directory_distance(current_file, &file.relative_path)),
+    })
+}
+
+/// The search uses the SIMD matcher which doesn't report the typos and matched characters,
+/// the reference implementation gives the same alignment with every detail
+fn match_details(query: &str, haystack: &str, max_typos: u16) -> Option<MatchDetails> {
+    let (score, score_matrix, exact) = smith_waterman(query, haystack);
+    let score_matrix: Vec<&[u16]> = score_matrix.iter().map(Vec::as_slice).collect();
+    let typos = typos_from_score_matrix(&score_matrix);
+    if score == 0 || typos > max_typos {
+        return None;
+    }
+
+    Some(MatchDetails {
+        score,
+        exact,
+        typos,
+        indices: char_indices_from_score_matrix(&score_matrix),
+    })
+}
+
+impl IntoLua for MatchDetails {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("score", self.score)?;
+        table.set("exact", self.exact)?;
+        table.set("typos", self.typos)?;
+        table.set("indices", self.indices)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+impl IntoLua for FrecencyExplanation {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("access_timestamps", self.access_timestamps)?;
+        table.set("decayed_accesses", self.decayed_accesses)?;
+        table.set("access_score", self.access_score)?;
+        table.set("modified_seconds_ago", self.modified_seconds_ago)?;
+        table.set("git_modified", self.git_modified)?;
+        if let Some(step) = self.modification_step {
+            let step_table = lua.create_table()?;
+            step_table.set("newer_points", step.newer_points)?;
+            step_table.set("newer_seconds", step.newer_seconds)?;
+            step_table.set("older_points", step.older_points)?;
+            step_table.set("older_seconds", step.older_seconds)?;
+            table.set("modification_step", step_table)?;
+        }
+        table.set("modification_score", self.modification_score)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+impl IntoLua for ScoreExplanation {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("query", self.query)?;
+        table.set("relative_path", self.relative_path)?;
+        table.set("max_typos", self.max_typos)?;
+        table.set("score", self.score)?;
+        table.set("rank", self.rank)?;
+        table.set("total_matched", self.total_matched)?;
+        table.set("path_match", self.path_match)?;
+        table.set("filename_match", self.filename_match)?;
+        table.set("frecency", self.frecency)?;
+        table.set("current_file", self.current_file)?;
+        table.set("current_root", self.current_root)?;
+        table.set("directory_distance", self.directory_distance)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FileSource;
+
+    #[test]
+    fn test_explanation_matches_search_rank() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/picker")).unwrap();
+        for file in [
+            "src/picker/file_picker.rs",
+            "src/file_pickr.rs",
+            "README.md",
+        ] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+
+        let picker = FilePicker::new(dir.path().to_string_lossy().to_string()).unwrap();
+        crate::file_picker::wait_for_scan(&picker);
+
+        let snapshot = picker.read().unwrap().files_snapshot();
+        let options = SearchOptions {
+            max_results: 10,
+            max_threads: 1,
+            current_file: Some("src/picker/mod.rs"),
+            ..Default::default()
+        };
+        let indexed_paths = IndexedPaths::new(&[&snapshot]);
+        let sources = [(FileSource::Index, &snapshot)];
+        let mut results = FilePicker::rank_sources(&sources, "file_picker", &options, None);
+        let page = results.page(0, 10);
+
+        for (position, file) in page.items.iter().enumerate() {
+            let explanation = explain_score(
+                &[&snapshot],
+                &indexed_paths,
+                &file.relative_path,
+                "file_picker",
+                &options,
+                None,
+            )
+            .unwrap();
+            assert_eq!(explanation.rank, Some(position + 1));
+            assert_eq!(explanation.total_matched, page.total_matched);
+            assert_eq!(
+                explanation.score.map(|score| score.total),
+                Some(page.scores[position].total)
+            );
+        }
+
+        let exact = explain_score(
+            &[&snapshot],
+            &indexed_paths,
+            "src/picker/file_picker.rs",
+            "file_picker",
+            &options,
+            None,
+        )
+        .unwrap();
+        assert_eq!(exact.directory_distance, Some(0));
+        assert!(
+            exact
+                .filename_match
+                .is_some_and(|m| m.typos == 0 && m.indices == (0..11).collect::<Vec<_>>())
+        );
+
+        let typo = explain_score(
+            &[&snapshot],
+            &indexed_paths,
+            "src/file_pickr.rs",
+            "file_picker",
+            &options,
+            None,
+        )
+        .unwrap();
+        assert_eq!(typo.directory_distance, Some(1));
+        assert_eq!(typo.filename_match.map(|m| m.typos), Some(1));
+
+        let unmatched = explain_score(
+            &[&snapshot],
+            &indexed_paths,
+            "README.md",
+            "file_picker",
+            &options,
+            None,
+        );
+        assert!(unmatched.is_some_and(|explanation| explanation.rank.is_none()));
+        assert!(
+            explain_score(
+                &[&snapshot],
+                &indexed_paths,
+                "missing.rs",
+                "file",
+                &options,
+                None
+            )
+            .is_none()
+        );
+    }
+}
@@ -1,49 +1,116 @@
 use crate::background_watcher::BackgroundWatcher;
+use crate::dir_index::DirIndex;
 use crate::error::Error;
 use crate::frecency::FrecencyTracker;
 use crate::git::GitStatusCache;
-use crate::location::parse_location;
-use crate::score::match_and_score_files;
-use crate::types::{FileItem, ScoringContext, SearchResult};
+use crate::location::{Location, decode_file_uri, parse_location, parse_location_in};
+use crate::path_utils::display_path;
+use crate::ranked_results::RankedResults;
+use crate::scan_config::{RootConfig, ScanConfig, ScanFilter};
+use crate::score::{match_and_score_dirs, score_candidates, sort_and_truncate};
+use crate::search_cache::{IndexedPaths, SearchCache};
+use crate::trigram_index::{SharedTrigramIndex, TrigramIndex, index_file_path, save_index_file};
+use crate::types::{
+    DirItem, DirSearchResult, FileItem, FileSource, Score, ScoringContext, SearchOptions,
+    SearchResult,
+};
 use git2::{Repository, Status, StatusOptions};
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{
-    Arc,
+    Arc, RwLock, Weak,
     atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 use std::time::SystemTime;
 use tracing::{debug, error, info, warn};
 
-use crate::{FILE_PICKER, FRECENCY};
+use crate::FRECENCY;
+
+/// Pickers are shared between the Lua calls and their own background threads, the background
+/// threads only keep a weak reference so dropping the last strong one stops them.
+pub type SharedPicker = Arc<RwLock<FilePicker>>;
+pub type WeakPicker = Weak<RwLock<FilePicker>>;
+
+/// Immutable view of the indexed files. Searches clone the `Arc` under a short read lock and
+/// never wait for the writers. The snapshots share the items: adding or removing a file copies
+/// the list of pointers if a search still holds it, the frecency, git status and pin of a file
+/// are updated in place without any copy.
+pub type FileSnapshot = Arc<Vec<Arc<FileItem>>>;
+pub type WeakSnapshot = Weak<Vec<Arc<FileItem>>>;
 
 #[derive(Debug, Clone)]
 struct FileSync {
-    pub files: Vec<FileItem>,
-    pub git_workdir: Option<PathBuf>,
+    pub files: FileSnapshot,
+    /// Git working directory of every root in the same order as `FilePicker::roots`
+    pub git_workdirs: Vec<Option<PathBuf>>,
+    /// Files indexed by a path going through a symlink keyed by their real path, only filled
+    /// when the symlinks are followed
+    pub symlinked_files: HashMap<PathBuf, PathBuf>,
 }
 
 impl FileSync {
     fn new() -> Self {
         Self {
-            files: Vec::new(),
-            git_workdir: None,
+            files: Arc::default(),
+            git_workdirs: Vec::new(),
+            symlinked_files: HashMap::new(),
         }
     }
 
+    fn files_mut(&mut self) -> &mut Vec<Arc<FileItem>> {
+        Arc::make_mut(&mut self.files)
+    }
+
     fn find_file_index(&self, path: &Path) -> Result<usize, usize> {
         self.files
             .binary_search_by(|file| file.path.as_path().cmp(path))
     }
 }
 
+/// One of the directories indexed by the picker, files of all the roots share a single list
+#[derive(Debug, Clone)]
+pub struct IndexRoot {
+    pub path: PathBuf,
+    /// Prefix of the relative paths, only set when the picker indexes more than one root
+    pub label: Option<String>,
+    scan_filter: Arc<ScanFilter>,
+}
+
+impl IndexRoot {
+    fn new(path: PathBuf, label: Option<String>, scan_config: &ScanConfig) -> Result<Self, Error> {
+        Ok(Self {
+            scan_filter: Arc::new(ScanFilter::new(&path, scan_config)?),
+            path,
+            label,
+        })
+    }
+
+    pub fn relative_path(&self, path: &Path) -> String {
+        let relative_path =
+            pathdiff::diff_paths(path, &self.path).unwrap_or_else(|| path.to_path_buf());
+
+        match &self.label {
+            Some(label) => Path::new(label).join(relative_path),
+            None => relative_path,
+        }
+        .to_string_lossy()
+        .into_owned()
+    }
+}
+
 impl FileItem {
-    pub fn new(path: PathBuf, base_path: &Path, git_status: Option<Status>) -> Self {
-        let relative_path = pathdiff::diff_paths(&path, base_path)
-            .unwrap_or_else(|| path.clone())
-            .to_string_lossy()
-            .into_owned();
+    pub fn new(path: PathBuf, root: &IndexRoot, git_status: Option<Status>) -> Self {
+        let relative_path = root.relative_path(&path);
+        Self::with_relative_path(path, relative_path, git_status)
+    }
 
+    pub fn with_relative_path(
+        path: PathBuf,
+        relative_path: String,
+        git_status: Option<Status>,
+    ) -> Self {
         let name = path
             .file_name()
             .unwrap_or_default()
@@ -64,93 +131,322 @@ impl FileItem {
             Err(_) => (0, 0),
         };
 
-        Self {
-            path,
-            relative_path_lower: relative_path.to_lowercase(),
-            relative_path,
-            file_name_lower: name.to_lowercase(),
-            file_name: name,
-            size,
-            modified,
-            access_frecency_score: 0,
-            modification_frecency_score: 0,
-            total_frecency_score: 0,
-            git_status,
-        }
+        Self::from_parts(path, relative_path, name, size, modified, git_status)
     }
 
-    pub fn update_frecency_scores(&mut self, tracker: &FrecencyTracker) -> Result<(), Error> {
-        self.access_frecency_score = tracker.get_access_score(&self.path);
-        self.modification_frecency_score =
-            tracker.get_modification_score(self.modified, self.git_status);
-        self.total_frecency_score = self.access_frecency_score + self.modification_frecency_score;
+    /// The pin is the one of the `project_root`, the base path of the picker
+    pub fn update_frecency_scores(
+        &self,
+        tracker: &FrecencyTracker,
+        project_root: &Path,
+    ) -> Result<(), Error> {
+        self.set_frecency_scores(
+            tracker.get_access_score(&self.path),
+            tracker.get_modification_score(self.modified, self.git_status()),
+        );
+        self.set_pinned(tracker.is_pinned(project_root, &self.path));
 
         Ok(())
     }
 
     /// Locks the tracker and updates frecensy score for one file. If need multiple files updates
     /// use `update_frecency_scores` instead.
-    pub fn update_frecency_scores_global(&mut self) -> Result<(), Error> {
+    pub fn update_frecency_scores_global(&self, project_root: &Path) -> Result<(), Error> {
         let Some(ref frecency) = *FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)? else {
             return Ok(());
         };
 
-        self.update_frecency_scores(frecency)
+        self.update_frecency_scores(frecency, project_root)
     }
 }
 
 pub struct FilePicker {
+    this: WeakPicker,
     base_path: PathBuf,
+    scan_config: ScanConfig,
+    /// The base path always comes first followed by the additional roots from the config
+    roots: Vec<IndexRoot>,
     sync_data: FileSync,
+    /// Files hidden by the ignore rules, only walked when the first search asks for them
+    ignored_files: Option<FileSnapshot>,
     is_scanning: Arc<AtomicBool>,
+    /// Set when a rescan is triggered during another one, the running scan walks again
+    rescan_requested: Arc<AtomicBool>,
+    /// Paths the watcher changed while a rescan was walking, see `swap_scanned_files`
+    changed_during_scan: Vec<PathBuf>,
+    is_scanning_ignored: Arc<AtomicBool>,
     scanned_files_count: Arc<AtomicUsize>,
-    background_watcher: Option<BackgroundWatcher>,
+    background_watchers: Vec<BackgroundWatcher>,
+    search_cache: Arc<SearchCache>,
+    dir_index: Arc<DirIndex>,
+    /// Open buffers pushed from Lua, sorted like the index
+    buffer_files: FileSnapshot,
+    /// Neovim oldfiles pushed from Lua, sorted like the index
+    oldfiles: FileSnapshot,
+    /// Trigram index of the file contents, `None` until built or when not configured
+    content_index: Option<SharedTrigramIndex>,
 }
 
 impl std::fmt::Debug for FilePicker {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("FilePicker")
             .field("base_path", &self.base_path)
+            .field("scan_config", &self.scan_config)
+            .field("roots", &self.roots)
             .field("sync_data", &self.sync_data)
+            .field(
+                "ignored_files_count",
+                &self.ignored_files.as_ref().map(|files| files.len()),
+            )
             .field("is_scanning", &self.is_scanning.load(Ordering::Relaxed))
             .field(
                 "scanned_files_count",
                 &self.scanned_files_count.load(Ordering::Relaxed),
             )
+            .field("buffer_files_count", &self.buffer_files.len())
+            .field("oldfiles_count", &self.oldfiles.len())
+            .field(
+                "content_index_files",
+                &self
+                    .content_index
+                    .as_ref()
+                    .and_then(|index| index.read().ok().map(|index| index.len())),
+            )
             .finish_non_exhaustive()
     }
 }
 
 impl FilePicker {
     pub fn git_root(&self) -> Option<&Path> {
-        self.sync_data.git_workdir.as_deref()
+        self.sync_data.git_workdirs.first()?.as_deref()
+    }
+
+    pub fn roots(&self) -> &[IndexRoot] {
+        &self.roots
+    }
+
+    /// Returns the most nested root containing the path
+    pub fn root_for_path(&self, path: impl AsRef<Path>) -> Option<&IndexRoot> {
+        let path = path.as_ref();
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.as_os_str().len())
+    }
+
+    /// Lua passes the current file relative to the base path or absolute when it's outside of it.
+    /// Converts it to the relative form used by the index and returns the label of its root.
+    pub fn resolve_current_file<'a>(&'a self, current_file: &'a str) -> (String, Option<&'a str>) {
+        let path = Path::new(current_file);
+        let root = if path.is_absolute() {
+            self.root_for_path(path)
+        } else {
+            self.roots.first()
+        };
+
+        match root {
+            Some(root) if path.is_absolute() || root.label.is_some() => (
+                root.relative_path(&root.path.join(path)),
+                root.label.as_deref(),
+            ),
+            _ => (current_file.to_string(), None),
+        }
     }
 
-    pub fn get_files(&self) -> &[FileItem] {
+    /// Queries pasted as absolute paths, e.g. `file://` URIs, are searched by the path relative
+    /// to their root keeping the location suffix. The URIs are percent-decoded.
+    pub fn relative_query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        let query = decode_file_uri(query);
+        let (path, _) = parse_location(&query);
+        match self.root_for_path(path) {
+            Some(root) if Path::new(path).is_absolute() => {
+                Cow::Owned(query.replacen(path, &root.relative_path(Path::new(path)), 1))
+            }
+            _ => query,
+        }
+    }
+
+    pub fn get_files(&self) -> &[Arc<FileItem>] {
         &self.sync_data.files
     }
 
-    pub fn new(base_path: String) -> Result<Self, Error> {
-        info!("Initializing FilePicker with base_path: {}", base_path);
+    /// Returns the ignored files index, empty until `ensure_ignored_index` finished the walk
+    pub fn get_ignored_files(&self) -> &[Arc<FileItem>] {
+        self.ignored_files
+            .as_deref()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Current files which stay valid after the lock is released
+    pub fn files_snapshot(&self) -> FileSnapshot {
+        Arc::clone(&self.sync_data.files)
+    }
+
+    /// Trigram index of the file contents once it is built in the background
+    pub fn content_index(&self) -> Option<SharedTrigramIndex> {
+        self.content_index.clone()
+    }
+
+    pub fn ignored_files_snapshot(&self) -> Option<FileSnapshot> {
+        self.ignored_files.clone()
+    }
+
+    /// Snapshot of any source, `None` for the ignored files until their index is built and for
+    /// the recent files which don't belong to a picker
+    pub fn source_snapshot(&self, source: FileSource) -> Option<FileSnapshot> {
+        match source {
+            FileSource::Index => Some(self.files_snapshot()),
+            FileSource::Ignored => self.ignored_files_snapshot(),
+            FileSource::Buffer => Some(Arc::clone(&self.buffer_files)),
+            FileSource::Oldfile => Some(Arc::clone(&self.oldfiles)),
+            FileSource::Recent => None,
+        }
+    }
+
+    /// Replaces the files of a pushed source, build them with `pushed_files` outside the lock
+    pub fn set_source_files(&mut self, source: FileSource, files: Vec<Arc<FileItem>>) {
+        match source {
+            FileSource::Buffer => self.buffer_files = Arc::new(files),
+            FileSource::Oldfile => self.oldfiles = Arc::new(files),
+            FileSource::Index | FileSource::Ignored | FileSource::Recent => {
+                warn!(?source, "Only buffers and oldfiles can be pushed")
+            }
+        }
+    }
+
+    /// Files of a pushed source, sorted the same way as the index so the scanned copies can be
+    /// found. The paths can be outside of the roots: they are shown relative to the home
+    /// directory or absolute. Paths which are not files anymore are skipped.
+    ///
+    /// A file found by the scan is the same item as in the `scanned` snapshots (the index and
+    /// the ignored files), it keeps its git status and frecency.
+    pub fn pushed_files(
+        base_path: &Path,
+        roots: &[IndexRoot],
+        scanned: &[FileSnapshot],
+        paths: Vec<PathBuf>,
+        frecency: Option<&FrecencyTracker>,
+    ) -> Vec<Arc<FileItem>> {
+        let mut files: Vec<Arc<FileItem>> = paths
+            .into_iter()
+            .filter(|path| path.is_absolute() && path.is_file())
+            .map(|path| {
+                if let Some(file) = scanned.iter().find_map(|files| {
+                    find_by_path(files, &path)
+                        .ok()
+                        .map(|index| Arc::clone(&files[index]))
+                }) {
+                    return file;
+                }
+
+                let relative_path = match roots
+                    .iter()
+                    .filter(|root| path.starts_with(&root.path))
+                    .max_by_key(|root| root.path.as_os_str().len())
+                {
+                    Some(root) => root.relative_path(&path),
+                    None => display_path(&path),
+                };
+
+                let file = FileItem::with_relative_path(path, relative_path, None);
+                if let Some(frecency) = frecency {
+                    let _ = file.update_frecency_scores(frecency, base_path);
+                }
+                Arc::new(file)
+            })
+            .collect();
+
+        files.sort_unstable_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+        files.dedup_by(|a, b| a.path == b.path);
+        files
+    }
+
+    pub fn search_cache(&self) -> Arc<SearchCache> {
+        Arc::clone(&self.search_cache)
+    }
+
+    /// Directories are derived on demand, get them with `dirs` outside of the picker lock
+    pub fn dir_index(&self) -> Arc<DirIndex> {
+        Arc::clone(&self.dir_index)
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    pub fn scan_config(&self) -> &ScanConfig {
+        &self.scan_config
+    }
+
+    /// Current filter of the root at the path, it is replaced when the ignore files change
+    pub fn root_scan_filter(&self, root_path: &Path) -> Option<Arc<ScanFilter>> {
+        self.roots
+            .iter()
+            .find(|root| root.path == root_path)
+            .map(|root| Arc::clone(&root.scan_filter))
+    }
+
+    /// Compiles the filters of the roots again, e.g. after a `.fffignore` was edited. The index
+    /// keeps the files of the previous rules until the next scan.
+    pub fn reload_scan_filters(&mut self) -> Result<(), Error> {
+        for root in &mut self.roots {
+            root.scan_filter = Arc::new(ScanFilter::new(&root.path, &self.scan_config)?);
+        }
+
+        Ok(())
+    }
+
+    pub fn new(base_path: String) -> Result<SharedPicker, Error> {
+        Self::new_with_config(base_path, ScanConfig::default())
+    }
+
+    pub fn new_with_config(
+        base_path: String,
+        scan_config: ScanConfig,
+    ) -> Result<SharedPicker, Error> {
+        info!(
+            ?scan_config,
+            "Initializing FilePicker with base_path: {}", base_path
+        );
         let path = PathBuf::from(&base_path);
         if !path.exists() {
             error!("Base path does not exist: {}", base_path);
             return Err(Error::InvalidPath(path));
         }
 
+        let roots = build_roots(&path, &scan_config)?;
         let scan_signal = Arc::new(AtomicBool::new(false));
         let synced_files_count = Arc::new(AtomicUsize::new(0));
 
-        let picker = Self {
-            base_path: path.clone(),
-            sync_data: FileSync::new(),
-            is_scanning: Arc::clone(&scan_signal),
-            scanned_files_count: Arc::clone(&synced_files_count),
-            background_watcher: None,
-        };
+        let picker = Arc::new_cyclic(|this: &WeakPicker| {
+            RwLock::new(Self {
+                this: this.clone(),
+                base_path: path.clone(),
+                scan_config,
+                roots: roots.clone(),
+                sync_data: FileSync::new(),
+                ignored_files: None,
+                is_scanning: Arc::clone(&scan_signal),
+                rescan_requested: Arc::new(AtomicBool::new(false)),
+                changed_during_scan: Vec::new(),
+                is_scanning_ignored: Arc::new(AtomicBool::new(false)),
+                scanned_files_count: Arc::clone(&synced_files_count),
+                background_watchers: Vec::new(),
+                search_cache: Arc::default(),
+                dir_index: Arc::default(),
+                buffer_files: Arc::default(),
+                oldfiles: Arc::default(),
+                content_index: None,
+            })
+        });
 
+        // the scan is marked as active before returning so nobody sees an idle empty picker
+        scan_signal.store(true, Ordering::Relaxed);
         spawn_scan_and_watcher(
-            path.clone(),
+            Arc::downgrade(&picker),
+            path,
+            roots,
             Arc::clone(&scan_signal),
             Arc::clone(&synced_files_count),
         );
@@ -159,53 +455,188 @@ impl FilePicker {
     }
 
     pub fn fuzzy_search<'a>(
-        files: &'a [FileItem],
+        files: &'a [Arc<FileItem>],
         query: &'a str,
         max_results: usize,
         max_threads: usize,
         current_file: Option<&'a str>,
         reverse_order: bool,
     ) -> SearchResult<'a> {
-        let max_threads = max_threads.max(1);
-        debug!(
-            ?query,
-            ?max_results,
-            ?max_threads,
-            ?current_file,
-            "Fuzzy search",
-        );
+        let options = SearchOptions {
+            max_results,
+            max_threads,
+            current_file,
+            current_root: None,
+            related: None,
+            co_access: None,
+            reverse_order,
+        };
 
+        let (context, location) = Self::scoring_context(query, &options, &[files], None);
         let total_files = files.len();
-        let (query, location) = parse_location(query);
+        let matches = Self::score_sources(&[files], &context, None).remove(0);
+        let results = matches
+            .into_iter()
+            .map(|(index, score)| (files[index as usize].as_ref(), score))
+            .collect();
+        let (items, scores, total_matched) = sort_and_truncate(results, &context);
 
-        // small queries with a large number of results can match absolutely everything
-        let max_typos = (query.len() as u16 / 4).clamp(2, 6);
+        SearchResult {
+            sources: vec![FileSource::Index; items.len()],
+            items,
+            scores,
+            total_matched,
+            total_files,
+            location,
+        }
+    }
+
+    pub fn fuzzy_search_dirs<'a>(
+        dirs: &'a [DirItem],
+        query: &'a str,
+        max_results: usize,
+        max_threads: usize,
+        reverse_order: bool,
+    ) -> DirSearchResult<'a> {
+        // a location suffix makes no sense for a directory, the whole query is matched
         let context = ScoringContext {
             query,
-            max_typos,
-            max_threads,
-            current_file,
+            max_typos: max_typos(query),
+            max_threads: max_threads.max(1),
+            current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
             max_results,
             reverse_order,
         };
 
         let time = std::time::Instant::now();
-        let (items, scores, total_matched) = match_and_score_files(files, &context);
+        let (items, scores, total_matched) = match_and_score_dirs(dirs, &context);
+        debug!(?query, completed_in = ?time.elapsed(), "Directory search completed");
 
-        debug!(
-            ?query,
-            completed_in = ?time.elapsed(),
-            top_position = ?items.first(),
-            "Fuzzy search completed",
-        );
-
-        SearchResult {
+        DirSearchResult {
             items,
             scores,
             total_matched,
-            total_files,
+            total_dirs: dirs.len(),
+        }
+    }
+
+    /// Ranks the matches of every snapshot together, e.g. to search tracked and ignored files
+    /// at once. With a `cache` the matches of the previous query are re-scored instead of the
+    /// whole index when the query extends it.
+    ///
+    /// A file listed by a pushed source is ranked once, see `shadowed_files`.
+    pub fn rank_sources(
+        sources: &[(FileSource, &FileSnapshot)],
+        query: &str,
+        options: &SearchOptions,
+        cache: Option<&SearchCache>,
+    ) -> RankedResults {
+        let snapshots: Vec<&FileSnapshot> = sources.iter().map(|(_, files)| *files).collect();
+        let files: Vec<&[Arc<FileItem>]> = snapshots.iter().map(|files| files.as_slice()).collect();
+        let indexed_paths = cache.map(|cache| cache.indexed_paths(&snapshots));
+        let (context, location) =
+            Self::scoring_context(query, options, &files, indexed_paths.as_deref());
+        let shadowed = shadowed_files(sources);
+        let matches = Self::score_sources(
+            &files,
+            &context,
+            cache.map(|cache| (cache, snapshots.as_slice())),
+        )
+        .into_iter()
+        .zip(shadowed)
+        .enumerate()
+        .flat_map(|(source, (matches, shadowed))| {
+            matches
+                .into_iter()
+                .filter(move |(index, _)| !shadowed.contains(index))
+                .map(move |(index, score)| (source as u32, index, score))
+        })
+        .collect();
+
+        RankedResults::new(
+            sources
+                .iter()
+                .map(|(source, files)| (*source, Arc::clone(files)))
+                .collect(),
+            matches,
+            options.max_results,
+            options.reverse_order,
             location,
+        )
+    }
+
+    /// The location suffix is split off the query where the path before it is a file of the
+    /// `sources`, see `parse_location_in`. The `indexed_paths` must be built from the `sources`,
+    /// without them they are built once the query has several readings.
+    pub(crate) fn scoring_context<'a>(
+        query: &'a str,
+        options: &SearchOptions<'a>,
+        sources: &[&[Arc<FileItem>]],
+        indexed_paths: Option<&IndexedPaths>,
+    ) -> (ScoringContext<'a>, Option<Location>) {
+        debug!(?query, ?options, "Fuzzy search");
+        let built_paths = std::cell::OnceCell::new();
+        let (query, location) = parse_location_in(query, |path| {
+            indexed_paths
+                .unwrap_or_else(|| built_paths.get_or_init(|| IndexedPaths::new(sources)))
+                .contains(sources, path)
+        });
+
+        let context = ScoringContext {
+            query,
+            max_typos: max_typos(query),
+            max_threads: options.max_threads.max(1),
+            current_file: options.current_file,
+            current_root: options.current_root,
+            related: options.related,
+            co_access: options.co_access,
+            max_results: options.max_results,
+            reverse_order: options.reverse_order,
+        };
+
+        (context, location)
+    }
+
+    /// Unsorted matches of every source, see `score_candidates`
+    fn score_sources(
+        sources: &[&[Arc<FileItem>]],
+        context: &ScoringContext,
+        cache: Option<(&SearchCache, &[&FileSnapshot])>,
+    ) -> Vec<Vec<(u32, Score)>> {
+        let time = std::time::Instant::now();
+        let candidates = cache.and_then(|(cache, snapshots)| {
+            cache.candidates(snapshots, context.query, context.max_typos)
+        });
+
+        let (scored, matched): (Vec<_>, Vec<_>) = sources
+            .iter()
+            .enumerate()
+            .map(|(index, files)| {
+                let candidates = candidates
+                    .as_ref()
+                    .map(|candidates| candidates[index].as_slice());
+                score_candidates(files, candidates, context)
+            })
+            .unzip();
+
+        if let Some((cache, snapshots)) = cache {
+            match matched.into_iter().collect::<Option<Vec<_>>>() {
+                Some(matched) => cache.store(snapshots, context.query, context.max_typos, matched),
+                None => cache.clear(),
+            }
         }
+
+        debug!(
+            query = ?context.query,
+            refined = candidates.is_some(),
+            completed_in = ?time.elapsed(),
+            "Fuzzy search completed",
+        );
+
+        scored
     }
 
     pub fn get_scan_progress(&self) -> ScanProgress {
@@ -214,13 +645,94 @@ impl FilePicker {
         ScanProgress {
             scanned_files_count: scanned_count,
             is_scanning,
+            is_scanning_ignored: self.is_scanning_ignored.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Starts the background walk of the ignored files unless it is already built or running.
+    /// The index is built only on demand because ignored directories (`target/`, `node_modules/`)
+    /// are usually much larger than the rest of the repository.
+    pub fn ensure_ignored_index(&self) {
+        if self.ignored_files.is_some() || self.is_scan_active() {
+            return;
+        }
+
+        // searches only hold the read lock so several of them can race to start the walk
+        if self
+            .is_scanning_ignored
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        spawn_ignored_scan(
+            self.this.clone(),
+            self.base_path.clone(),
+            self.roots.clone(),
+            Arc::clone(&self.is_scanning_ignored),
+        );
+    }
+
+    /// Keeps the ignored index in sync with the watcher, no-op if it was never requested
+    pub fn on_ignored_path_change(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let Some(root) = self.root_for_path(path).cloned() else {
+            return;
+        };
+        let Some(ignored_files) = self.ignored_files.as_mut().map(Arc::make_mut) else {
+            return;
+        };
+
+        let position = ignored_files.binary_search_by(|f| f.path.as_os_str().cmp(path.as_os_str()));
+        match (position, path.is_file()) {
+            (Ok(index), true) => {
+                ignored_files[index] = Arc::new(FileItem::new(
+                    path.to_path_buf(),
+                    &root,
+                    Some(Status::IGNORED),
+                ));
+            }
+            (Ok(index), false) => {
+                ignored_files.remove(index);
+            }
+            (Err(index), true) => {
+                let file = FileItem::new(path.to_path_buf(), &root, Some(Status::IGNORED));
+                if let Err(e) = file.update_frecency_scores_global(&self.base_path) {
+                    error!("Failed to update frecency for ignored file: {:?}", e);
+                }
+
+                ignored_files.insert(index, Arc::new(file));
+            }
+            (Err(_), false) => {}
         }
     }
 
-    pub fn update_git_statuses(
+    /// Applies one batch of the watcher events with the git statuses of the changed files. The
+    /// list is copied only by the first change when a search still holds the snapshot, every
+    /// change of the batch has to be applied under the same lock to keep it a single copy.
+    pub fn apply_watcher_changes(
         &mut self,
-        status_cache: Option<GitStatusCache>,
+        removed: &[&Path],
+        changed: &[&Path],
+        ignored: &[&Path],
+        git_status: Option<GitStatusCache>,
     ) -> Result<(), Error> {
+        for path in removed {
+            self.remove_file_by_path(path);
+        }
+        for path in ignored {
+            self.on_ignored_path_change(path);
+        }
+        for path in changed {
+            self.on_create_or_modify(path);
+        }
+
+        self.update_git_statuses(git_status)
+    }
+
+    /// The statuses are updated in place, the read lock of the picker is enough
+    pub fn update_git_statuses(&self, status_cache: Option<GitStatusCache>) -> Result<(), Error> {
         let Some(status_cache) = status_cache else {
             return Ok(());
         };
@@ -234,11 +746,11 @@ impl FilePicker {
         status_cache
             .into_iter()
             .try_for_each(|(path, status)| -> Result<(), Error> {
-                if let Some(file) = self.get_mut_file_by_path(&path) {
-                    file.git_status = Some(status);
+                if let Some(file) = self.get_file_by_path(&path) {
+                    file.set_git_status(Some(status));
 
                     if let Some(frecency) = frecency.as_ref() {
-                        file.update_frecency_scores(frecency)?;
+                        file.update_frecency_scores(frecency, &self.base_path)?;
                     }
                 }
 
@@ -248,55 +760,71 @@ impl FilePicker {
         Ok(())
     }
 
-    /// Fetches all the git statuses first and updates the global FILE_PICKER
+    /// Fetches all the git statuses first and updates the picker
     /// with the new statuses with the smallest possible lock time.
-    pub fn refresh_git_status_global() -> Result<usize, Error> {
-        let git_status = {
-            let Some(ref picker) = *FILE_PICKER.read().map_err(|_| Error::AcquireItemLock)? else {
-                return Err(Error::FilePickerMissing)?;
-            };
-
-            debug!(
-                "Refreshing git statuses for picker: {:?}",
-                picker.git_root()
-            );
-
-            // we keep here readonly lock but allowing querying the index while it scan lasts
-            GitStatusCache::read_git_status(
-                picker.git_root(),
-                StatusOptions::new()
-                    .include_untracked(true)
-                    .recurse_untracked_dirs(true)
-                    // when manually refreshing git status we want to include all unmodified file
-                    // to make sure that their status is correctly updated when user
-                    // commited/stashed/removed changes
-                    .include_unmodified(true)
-                    .exclude_submodules(true),
-            )
+    pub fn refresh_git_status_shared(picker: &RwLock<FilePicker>) -> Result<usize, Error> {
+        let git_workdirs = {
+            let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+
+            let mut git_workdirs: Vec<PathBuf> = picker
+                .sync_data
+                .git_workdirs
+                .iter()
+                .flatten()
+                .cloned()
+                .collect();
+            // several roots can live in the same repository
+            git_workdirs.sort_unstable();
+            git_workdirs.dedup();
+            git_workdirs
         };
 
-        let mut file_picker = FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)?;
-        let picker = file_picker
-            .as_mut()
-            .ok_or_else(|| Error::FilePickerMissing)?;
+        debug!("Refreshing git statuses for workdirs: {:?}", git_workdirs);
+        // reading without any lock allows querying the index while the status lasts
+        let git_statuses: Vec<_> = git_workdirs
+            .iter()
+            .map(|git_workdir| {
+                GitStatusCache::read_git_status(
+                    Some(git_workdir),
+                    StatusOptions::new()
+                        .include_untracked(true)
+                        .recurse_untracked_dirs(true)
+                        // when manually refreshing git status we want to include all unmodified file
+                        // to make sure that their status is correctly updated when user
+                        // commited/stashed/removed changes
+                        .include_unmodified(true)
+                        .exclude_submodules(true),
+                )
+            })
+            .collect();
 
-        let statuses_count = git_status.as_ref().map_or(0, |cache| cache.statuses_len());
-        picker.update_git_statuses(git_status)?;
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        let mut statuses_count = 0;
+        for git_status in git_statuses {
+            statuses_count += git_status.as_ref().map_or(0, |cache| cache.statuses_len());
+            picker.update_git_statuses(git_status)?;
+        }
 
         Ok(statuses_count)
     }
 
+    /// The scores are updated in place, the read lock of the picker is enough
     pub fn update_single_file_frecency(
-        &mut self,
+        &self,
         file_path: impl AsRef<Path>,
         frecency_tracker: &FrecencyTracker,
     ) -> Result<(), Error> {
-        if let Ok(index) = self.sync_data.find_file_index(file_path.as_ref())
-            && let Some(file) = self.sync_data.files.get_mut(index)
-        {
-            file.update_frecency_scores(frecency_tracker)?;
+        if let Some(file) = self.get_file_by_path(file_path.as_ref()) {
+            file.update_frecency_scores(frecency_tracker, &self.base_path)?;
         }
 
+        for pushed in [&self.buffer_files, &self.oldfiles] {
+            if let Ok(index) = find_by_path(pushed, file_path.as_ref()) {
+                pushed[index].update_frecency_scores(frecency_tracker, &self.base_path)?;
+            }
+        }
+
+        self.dir_index.clear();
         Ok(())
     }
 
@@ -305,13 +833,7 @@ impl FilePicker {
             .find_file_index(path.as_ref())
             .ok()
             .and_then(|index| self.sync_data.files.get(index))
-    }
-
-    pub fn get_mut_file_by_path(&mut self, path: impl AsRef<Path>) -> Option<&mut FileItem> {
-        self.sync_data
-            .find_file_index(path.as_ref())
-            .ok()
-            .and_then(|index| self.sync_data.files.get_mut(index))
+            .map(Arc::as_ref)
     }
 
     /// Add a file to the picker's files in sorted order (used by background watcher)
@@ -327,22 +849,24 @@ impl FilePicker {
                     file.relative_path
                 );
 
-                self.sync_data.files.get(position)
+                self.sync_data.files.get(position).map(Arc::as_ref)
             }
             Err(position) => {
-                self.sync_data.files.insert(position, file);
-                self.sync_data.files.get(position)
+                self.sync_data.files_mut().insert(position, Arc::new(file));
+                self.sync_data.files.get(position).map(Arc::as_ref)
             }
         }
     }
 
     pub fn on_create_or_modify(&mut self, path: impl AsRef<Path>) -> Option<&FileItem> {
+        let path = self.symlinked_file_path(path.as_ref());
         let path = path.as_ref();
+        if self.is_scan_active() {
+            self.changed_during_scan.push(path.to_path_buf());
+        }
+
         match self.sync_data.find_file_index(path) {
             Ok(pos) => {
-                // safe to read because we are in lock and binary search returned valid position
-                let file = &mut self.sync_data.files[pos];
-
                 let modified = match std::fs::metadata(path) {
                     Ok(metadata) => metadata
                         .modified()
@@ -354,28 +878,69 @@ impl FilePicker {
                     }
                 };
 
-                if let Some(modified) = modified {
-                    let modified = modified.as_secs();
-                    if file.modified < modified {
-                        file.modified = modified;
-                    }
+                // the snapshots held by the searches keep the previous item
+                if let Some(modified) = modified.map(|modified| modified.as_secs())
+                    && self.sync_data.files[pos].modified < modified
+                {
+                    let mut file = FileItem::clone(&self.sync_data.files[pos]);
+                    file.modified = modified;
+                    self.sync_data.files_mut()[pos] = Arc::new(file);
                 }
 
-                Some(file)
+                self.sync_data.files.get(pos).map(Arc::as_ref)
             }
             Err(pos) => {
-                let file_item = FileItem::new(path.to_path_buf(), &self.base_path, None);
-                self.sync_data.files.insert(pos, file_item);
+                let root = self.root_for_path(path)?;
+                let file_item = FileItem::new(path.to_path_buf(), root, None);
+                self.sync_data.files_mut().insert(pos, Arc::new(file_item));
 
-                self.sync_data.files.get(pos)
+                self.sync_data.files.get(pos).map(Arc::as_ref)
+            }
+        }
+    }
+
+    /// Path the index uses for a file the watcher reported. With the symlinks followed the same
+    /// file can be reachable by several paths, it is indexed once like in `dedup_symlinked_files`:
+    /// by the path already indexed or by its real path when it is within the roots.
+    fn symlinked_file_path<'a>(&mut self, path: &'a Path) -> Cow<'a, Path> {
+        let follow_symlinks = self
+            .root_for_path(path)
+            .is_some_and(|root| root.scan_filter.follow_symlinks());
+        if !follow_symlinks || self.sync_data.find_file_index(path).is_ok() {
+            return Cow::Borrowed(path);
+        }
+        let Ok(canonical) = path.canonicalize() else {
+            return Cow::Borrowed(path);
+        };
+
+        match self.sync_data.symlinked_files.get(&canonical) {
+            Some(indexed) if self.sync_data.find_file_index(indexed).is_ok() => {
+                Cow::Owned(indexed.clone())
+            }
+            _ if self.sync_data.find_file_index(&canonical).is_ok()
+                || (canonical != path && self.root_for_path(&canonical).is_some()) =>
+            {
+                Cow::Owned(canonical)
+            }
+            _ => {
+                if canonical != path {
+                    self.sync_data
+                        .symlinked_files
+                        .insert(canonical, path.to_path_buf());
+                }
+                Cow::Borrowed(path)
             }
         }
     }
 
     pub fn remove_file_by_path(&mut self, path: impl AsRef<Path>) -> bool {
+        if self.is_scan_active() {
+            self.changed_during_scan.push(path.as_ref().to_path_buf());
+        }
+
         match self.sync_data.find_file_index(path.as_ref()) {
             Ok(index) => {
-                self.sync_data.files.remove(index);
+                self.sync_data.files_mut().remove(index);
                 true
             }
             Err(_) => false,
@@ -386,43 +951,121 @@ impl FilePicker {
     pub fn remove_all_files_in_dir(&mut self, dir: impl AsRef<Path>) -> usize {
         let dir_path = dir.as_ref();
         let initial_len = self.sync_data.files.len();
+        if self.is_scan_active() {
+            let removed = self.sync_data.files.iter().map(|file| &file.path);
+            self.changed_during_scan
+                .extend(removed.filter(|path| path.starts_with(dir_path)).cloned());
+        }
 
         self.sync_data
-            .files
+            .files_mut()
             .retain(|file| !file.path.starts_with(dir_path));
 
         initial_len - self.sync_data.files.len()
     }
 
     pub fn stop_background_monitor(&mut self) {
-        if let Some(watcher) = self.background_watcher.take() {
+        for watcher in self.background_watchers.drain(..) {
             watcher.stop();
         }
     }
 
-    pub fn trigger_rescan(&mut self) -> Result<(), Error> {
-        if self.is_scanning.load(Ordering::Relaxed) {
-            debug!("Scan already in progress, skipping trigger_rescan");
+    /// Rescans the roots without holding the picker lock, searches keep using the previous
+    /// snapshot until the new one is swapped in. A rescan triggered while another one is
+    /// running makes that one walk again, it might have read the ignore rules before they changed.
+    pub fn trigger_rescan_shared(picker: &RwLock<FilePicker>) -> Result<(), Error> {
+        let (this, base_path, is_scanning, rescan_requested, scanned_files_count) = {
+            let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+            (
+                picker.this.clone(),
+                picker.base_path.clone(),
+                Arc::clone(&picker.is_scanning),
+                Arc::clone(&picker.rescan_requested),
+                Arc::clone(&picker.scanned_files_count),
+            )
+        };
+
+        rescan_requested.store(true, Ordering::SeqCst);
+        if is_scanning
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            debug!("Scan already in progress, it will walk the roots again");
             return Ok(());
         }
 
-        self.is_scanning.store(true, Ordering::Relaxed);
-        self.scanned_files_count.store(0, Ordering::Relaxed);
+        loop {
+            rescan_requested.store(false, Ordering::SeqCst);
+            let roots = {
+                let mut picker = picker.write().map_err(|_| Error::AcquireItemLock)?;
+                picker.changed_during_scan.clear();
+                picker.roots.clone()
+            };
 
-        if let Ok(sync) = scan_filesystem(&self.base_path, &self.scanned_files_count) {
-            info!(
-                "Filesystem scan completed: found {} files",
-                sync.files.len()
-            );
-            self.sync_data = sync
-        } else {
-            warn!("Filesystem scan failed");
+            scanned_files_count.store(0, Ordering::Relaxed);
+            let swapped = match scan_roots(&base_path, &roots, &scanned_files_count) {
+                Ok(sync) => {
+                    info!(
+                        "Filesystem scan completed: found {} files",
+                        sync.files.len()
+                    );
+
+                    picker
+                        .write()
+                        .map(|mut picker| picker.swap_scanned_files(sync))
+                }
+                Err(e) => {
+                    warn!("Filesystem scan failed: {:?}", e);
+                    Ok(())
+                }
+            };
+
+            is_scanning.store(false, Ordering::SeqCst);
+            swapped.map_err(|_| Error::AcquireItemLock)?;
+            if !rescan_requested.load(Ordering::SeqCst)
+                || is_scanning
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+            {
+                break;
+            }
         }
 
-        self.is_scanning.store(false, Ordering::Relaxed);
+        spawn_content_index(this);
         Ok(())
     }
 
+    /// Replaces the index with the result of a rescan. The files the watcher changed meanwhile
+    /// keep their state from the current index: the walk could have passed them before.
+    fn swap_scanned_files(&mut self, mut sync: FileSync) {
+        let changed_paths = std::mem::take(&mut self.changed_during_scan);
+        if !changed_paths.is_empty() {
+            let current_files = &self.sync_data.files;
+            let files = sync.files_mut();
+            for path in &changed_paths {
+                let current = find_by_path(current_files, path)
+                    .ok()
+                    .map(|index| &current_files[index]);
+                match (current, find_by_path(files, path)) {
+                    (Some(file), Ok(index)) => files[index] = Arc::clone(file),
+                    (Some(file), Err(index)) => files.insert(index, Arc::clone(file)),
+                    (None, Ok(index)) => {
+                        files.remove(index);
+                    }
+                    (None, Err(_)) => {}
+                }
+            }
+            debug!(
+                "Applied {} watcher changes made during the scan",
+                changed_paths.len()
+            );
+        }
+
+        self.sync_data = sync;
+        // ignore rules could have changed, the ignored index is rebuilt on the next request
+        self.ignored_files = None;
+    }
+
     pub fn is_scan_active(&self) -> bool {
         self.is_scanning.load(Ordering::Relaxed)
     }
@@ -433,30 +1076,124 @@ impl FilePicker {
 pub struct ScanProgress {
     pub scanned_files_count: usize,
     pub is_scanning: bool,
+    pub is_scanning_ignored: bool,
+}
+
+/// The base path becomes the first root, additional roots that don't exist or overlap with
+/// already added ones are skipped. Labels are only assigned when there is more than one root.
+fn build_roots(base_path: &Path, scan_config: &ScanConfig) -> Result<Vec<IndexRoot>, Error> {
+    let mut root_configs = vec![RootConfig {
+        path: base_path.to_path_buf(),
+        label: None,
+    }];
+
+    for root_config in &scan_config.roots {
+        let Ok(path) = root_config.path.canonicalize() else {
+            warn!("Skipping root that does not exist: {:?}", root_config.path);
+            continue;
+        };
+
+        if root_configs
+            .iter()
+            .any(|root| path.starts_with(&root.path) || root.path.starts_with(&path))
+        {
+            warn!("Skipping root overlapping with another root: {:?}", path);
+            continue;
+        }
+
+        root_configs.push(RootConfig {
+            path,
+            label: root_config.label.clone(),
+        });
+    }
+
+    if root_configs.len() == 1 {
+        return Ok(vec![IndexRoot::new(
+            base_path.to_path_buf(),
+            None,
+            scan_config,
+        )?]);
+    }
+
+    let mut labels: Vec<String> = Vec::with_capacity(root_configs.len());
+    root_configs
+        .into_iter()
+        .map(|root_config| {
+            let base_label = root_config.label.unwrap_or_else(|| {
+                root_config
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "root".to_string())
+            });
+
+            let mut label = base_label.clone();
+            let mut suffix = 2;
+            while labels.contains(&label) {
+                label = format!("{}-{}", base_label, suffix);
+                suffix += 1;
+            }
+            labels.push(label.clone());
+
+            IndexRoot::new(root_config.path, Some(label), scan_config)
+        })
+        .collect()
+}
+
+/// Position of the file in a list sorted the way the scan sorts the index
+fn find_by_path(files: &[Arc<FileItem>], path: &Path) -> Result<usize, usize> {
+    files.binary_search_by(|file| file.path.as_os_str().cmp(path.as_os_str()))
+}
+
+/// Positions of the files every source has to leave out of the ranking. A file is shown once:
+/// from the first pushed source listing it, the copy found by the scan is dropped.
+fn shadowed_files(sources: &[(FileSource, &FileSnapshot)]) -> Vec<HashSet<u32>> {
+    sources
+        .iter()
+        .enumerate()
+        .map(|(index, (source, files))| {
+            sources
+                .iter()
+                .enumerate()
+                .filter(|(pushed_index, (pushed_source, _))| {
+                    pushed_source.is_pushed() && (!source.is_pushed() || *pushed_index < index)
+                })
+                .flat_map(|(_, (_, pushed))| pushed.iter())
+                .filter_map(|pushed_file| find_by_path(files, &pushed_file.path).ok())
+                .map(|position| position as u32)
+                .collect()
+        })
+        .collect()
+}
+
+/// Small queries with a large number of results can match absolutely everything
+fn max_typos(query: &str) -> u16 {
+    (query.len() as u16 / 4).clamp(2, 6)
 }
 
 fn spawn_scan_and_watcher(
+    picker: WeakPicker,
     base_path: PathBuf,
+    roots: Vec<IndexRoot>,
     scan_signal: Arc<AtomicBool>,
     synced_files_count: Arc<AtomicUsize>,
 ) {
     std::thread::spawn(move || {
-        scan_signal.store(true, Ordering::Relaxed);
         info!("Starting initial file scan");
 
-        let mut git_workdir = None;
-        match scan_filesystem(&base_path, &synced_files_count) {
+        let mut git_workdirs = vec![None; roots.len()];
+        match scan_roots(&base_path, &roots, &synced_files_count) {
             Ok(sync) => {
                 info!(
                     "Initial filesystem scan completed: found {} files",
                     sync.files.len()
                 );
 
-                git_workdir = sync.git_workdir.clone();
-                if let Ok(mut file_picker_guard) = crate::FILE_PICKER.write()
-                    && let Some(ref mut picker) = *file_picker_guard
+                git_workdirs = sync.git_workdirs.clone();
+                if let Some(picker) = picker.upgrade()
+                    && let Ok(mut picker) = picker.write()
                 {
-                    picker.sync_data = sync;
+                    picker.swap_scanned_files(sync);
                 }
             }
             Err(e) => {
@@ -464,19 +1201,32 @@ fn spawn_scan_and_watcher(
             }
         }
         scan_signal.store(false, Ordering::Relaxed);
+        spawn_content_index(picker.clone());
 
-        match BackgroundWatcher::new(base_path, git_workdir) {
-            Ok(watcher) => {
-                info!("Background file watcher initialized successfully");
+        for (root, git_workdir) in roots.into_iter().zip(git_workdirs) {
+            // the picker was dropped while scanning, nothing to watch for
+            if picker.strong_count() == 0 {
+                break;
+            }
 
-                if let Ok(mut file_picker_guard) = crate::FILE_PICKER.write()
-                    && let Some(ref mut picker) = *file_picker_guard
-                {
-                    picker.background_watcher = Some(watcher);
+            match BackgroundWatcher::new(
+                picker.clone(),
+                root.path,
+                git_workdir,
+                root.scan_filter.follow_symlinks(),
+            ) {
+                Ok(watcher) => {
+                    info!("Background file watcher initialized successfully");
+
+                    if let Some(picker) = picker.upgrade()
+                        && let Ok(mut picker) = picker.write()
+                    {
+                        picker.background_watchers.push(watcher);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to initialize background file watcher: {:?}", e);
                 }
-            }
-            Err(e) => {
-                error!("Failed to initialize background file watcher: {:?}", e);
             }
         }
 
@@ -484,13 +1234,210 @@ fn spawn_scan_and_watcher(
     });
 }
 
-fn scan_filesystem(
+/// Brings the content index up to date with the scanned files when it is configured. The first
+/// build loads the index persisted by a previous session and only reads the changed files.
+fn spawn_content_index(picker: WeakPicker) {
+    let Some((index_dir, base_path, index)) = picker.upgrade().and_then(|picker| {
+        let picker = picker.read().ok()?;
+        let index_dir = picker.scan_config.content_index.clone()?;
+        Some((
+            index_dir,
+            picker.base_path.clone(),
+            picker.content_index.clone(),
+        ))
+    }) else {
+        return;
+    };
+
+    std::thread::spawn(move || {
+        let index_file = index_file_path(&index_dir, &base_path);
+        let index = index.unwrap_or_else(|| {
+            let index = match TrigramIndex::load(&index_file) {
+                Ok(index) => index,
+                Err(Error::ReadFile(_)) => TrigramIndex::default(),
+                Err(e) => {
+                    warn!(?e, "Failed to load the content index, rebuilding it");
+                    TrigramIndex::default()
+                }
+            };
+            Arc::new(RwLock::new(index))
+        });
+
+        // the index is published right after the first sync, the watchers keep it updated from
+        // then on and the files they changed meanwhile are caught by the second sync
+        for _ in 0..2 {
+            let Some(files) = picker
+                .upgrade()
+                .and_then(|picker| picker.read().ok().map(|picker| picker.files_snapshot()))
+            else {
+                return;
+            };
+            match index.write() {
+                Ok(mut index) => index.sync_files(&files),
+                Err(_) => return,
+            }
+
+            if let Some(picker) = picker.upgrade()
+                && let Ok(mut picker) = picker.write()
+            {
+                picker.content_index = Some(Arc::clone(&index));
+            }
+        }
+
+        // serialized under the read lock, the greps and the watchers go on while it's written
+        let Ok(bytes) = index.read().map(|index| index.to_bytes()) else {
+            return;
+        };
+        if let Err(e) = save_index_file(&index_file, &bytes) {
+            error!(?e, "Failed to save the content index");
+        }
+    });
+}
+
+fn spawn_ignored_scan(
+    picker: WeakPicker,
+    base_path: PathBuf,
+    roots: Vec<IndexRoot>,
+    scan_signal: Arc<AtomicBool>,
+) {
+    std::thread::spawn(move || {
+        info!("Starting ignored files scan");
+        let time = std::time::Instant::now();
+        let mut files: Vec<FileItem> = roots.iter().flat_map(scan_ignored_files).collect();
+        files.par_sort_unstable_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+
+        // the tracked files are filtered out of a snapshot and the ignored ones scored without
+        // any lock, the picker is only locked to publish them
+        let indexed = picker
+            .upgrade()
+            .and_then(|picker| picker.read().ok().map(|picker| picker.files_snapshot()));
+        if let Some(indexed) = indexed {
+            files.retain(|file| find_by_path(&indexed, &file.path).is_err());
+            drop(indexed);
+
+            if let Ok(frecency) = FRECENCY.read()
+                && let Some(frecency) = frecency.as_ref()
+            {
+                files.par_iter_mut().for_each(|file| {
+                    if let Err(e) = file.update_frecency_scores(frecency, &base_path) {
+                        error!("Failed to update frecency for ignored file: {:?}", e);
+                    }
+                });
+            }
+
+            info!(
+                "Ignored files scan completed in {:?}: found {} files",
+                time.elapsed(),
+                files.len()
+            );
+            if let Some(picker) = picker.upgrade()
+                && let Ok(mut picker) = picker.write()
+            {
+                picker.ignored_files = Some(Arc::new(files.into_iter().map(Arc::new).collect()));
+            }
+        }
+
+        scan_signal.store(false, Ordering::Relaxed);
+    });
+}
+
+/// Walks every file under the root with the repository ignore rules disabled, the picker
+/// specific rules still apply. The result includes the tracked files as well, the caller is
+/// responsible for filtering them out.
+fn scan_ignored_files(root: &IndexRoot) -> Vec<FileItem> {
+    use ignore::{WalkBuilder, WalkState};
+
+    let mut builder = WalkBuilder::new(&root.path);
+    builder
+        .parents(false)
+        .git_ignore(false)
+        .git_exclude(false)
+        .git_global(false)
+        .ignore(false);
+    root.scan_filter.configure_walker(&mut builder);
+    let walker = builder.build_parallel();
+
+    let files = Arc::new(std::sync::Mutex::new(Vec::new()));
+    walker.run(|| {
+        let files = Arc::clone(&files);
+
+        Box::new(move |result| {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log_walk_error(e);
+                    return WalkState::Continue;
+                }
+            };
+
+            if entry.file_type().is_some_and(|ft| ft.is_dir()) && entry.file_name() == ".git" {
+                return WalkState::Skip;
+            }
+
+            if entry.file_type().is_some_and(|ft| ft.is_file())
+                && let Ok(mut files_vec) = files.lock()
+            {
+                files_vec.push(FileItem::new(
+                    entry.path().to_path_buf(),
+                    root,
+                    Some(Status::IGNORED),
+                ));
+            }
+
+            WalkState::Continue
+        })
+    });
+
+    let files = Arc::try_unwrap(files)
+        .map(|files| files.into_inner().unwrap_or_default())
+        .unwrap_or_default();
+    if root.scan_filter.follow_symlinks() {
+        dedup_symlinked_files(files).0
+    } else {
+        files
+    }
+}
+
+/// Scans every root one by one (each walk is parallel on its own) and merges the results
+fn scan_roots(
     base_path: &Path,
+    roots: &[IndexRoot],
     synced_files_count: &Arc<AtomicUsize>,
 ) -> Result<FileSync, Error> {
+    let mut files = Vec::new();
+    let mut git_workdirs = Vec::with_capacity(roots.len());
+    let mut symlinked_files = HashMap::new();
+    for root in roots {
+        let (root_files, root_symlinked_files, git_workdir) =
+            scan_filesystem(base_path, root, synced_files_count)?;
+        files.extend(root_files);
+        symlinked_files.extend(root_symlinked_files);
+        git_workdirs.push(git_workdir);
+    }
+
+    // Sort by OsStr instead of Path to avoid expensive component-by-component comparison
+    files.par_sort_unstable_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
+    Ok(FileSync {
+        files: Arc::new(files.into_iter().map(Arc::new).collect()),
+        git_workdirs,
+        symlinked_files,
+    })
+}
+
+/// Files of the root, the files indexed through a symlink by their real path and the git
+/// working directory of the root
+type RootScan = (Vec<FileItem>, HashMap<PathBuf, PathBuf>, Option<PathBuf>);
+
+/// The frecency of the files is scored for the project of the picker, its `project_root`
+fn scan_filesystem(
+    project_root: &Path,
+    root: &IndexRoot,
+    synced_files_count: &Arc<AtomicUsize>,
+) -> Result<RootScan, Error> {
     use ignore::{WalkBuilder, WalkState};
     use std::thread;
 
+    let base_path = root.path.as_path();
     let scan_start = std::time::Instant::now();
     info!("SCAN: Starting parallel filesystem scan and git status");
 
@@ -520,14 +1467,14 @@ fn scan_filesystem(
             (git_workdir, status_cache)
         });
 
-        let walker = WalkBuilder::new(base_path)
-            .hidden(false)
+        let mut builder = WalkBuilder::new(base_path);
+        builder
             .git_ignore(true)
             .git_exclude(true)
             .git_global(true)
-            .ignore(true)
-            .follow_links(false)
-            .build_parallel();
+            .ignore(true);
+        root.scan_filter.configure_walker(&mut builder);
+        let walker = builder.build_parallel();
 
         let walker_start = std::time::Instant::now();
         info!("SCAN: Starting file walker");
@@ -536,12 +1483,17 @@ fn scan_filesystem(
         walker.run(|| {
             let files = Arc::clone(&files);
             let counter = Arc::clone(synced_files_count);
-            let base_path = base_path.to_path_buf();
 
             Box::new(move |result| {
-                if let Ok(entry) = result
-                    && entry.file_type().is_some_and(|ft| ft.is_file())
-                {
+                let entry = match result {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        log_walk_error(e);
+                        return WalkState::Continue;
+                    }
+                };
+
+                if entry.file_type().is_some_and(|ft| ft.is_file()) {
                     let path = entry.path();
 
                     if is_git_file(path) {
@@ -550,7 +1502,7 @@ fn scan_filesystem(
 
                     let file_item = FileItem::new(
                         path.to_path_buf(),
-                        &base_path,
+                        root,
                         None, // Git status will be added after join
                     );
 
@@ -564,6 +1516,11 @@ fn scan_filesystem(
         });
 
         let mut files = Arc::try_unwrap(files).unwrap().into_inner().unwrap();
+        let mut symlinked_files = HashMap::new();
+        if root.scan_filter.follow_symlinks() {
+            (files, symlinked_files) = dedup_symlinked_files(files);
+        }
+
         let walker_time = walker_start.elapsed();
         info!("SCAN: File walking completed in {:?}", walker_time);
 
@@ -577,11 +1534,11 @@ fn scan_filesystem(
             .par_iter_mut()
             .try_for_each(|file| -> Result<(), Error> {
                 if let Some(git_cache) = &git_cache {
-                    file.git_status = git_cache.lookup_status(&file.path);
+                    file.set_git_status(git_cache.lookup_status(&file.path));
                 }
 
                 if let Some(frecency) = frecency.as_ref() {
-                    file.update_frecency_scores(frecency)?;
+                    file.update_frecency_scores(frecency, project_root)?;
                 }
 
                 Ok(())
@@ -594,12 +1551,70 @@ fn scan_filesystem(
             files.len()
         );
 
-        // Sort by OsStr instead of Path to avoid expensive component-by-component comparison
-        files.par_sort_unstable_by(|a, b| a.path.as_os_str().cmp(b.path.as_os_str()));
-        Ok(FileSync { files, git_workdir })
+        Ok((files, symlinked_files, git_workdir))
     })
 }
 
+fn log_walk_error(error: ignore::Error) {
+    // the walker detects symlink loops by itself, the looping directory is simply skipped
+    if is_symlink_loop(&error) {
+        debug!("Skipping symlink loop: {}", error);
+    } else {
+        debug!("Failed to walk entry: {}", error);
+    }
+}
+
+fn is_symlink_loop(error: &ignore::Error) -> bool {
+    match error {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithPath { err, .. } => {
+            is_symlink_loop(err)
+        }
+        _ => false,
+    }
+}
+
+/// When symlinks are followed the same file can be reachable by several paths, keeps only one
+/// (the real path if it was walked, otherwise the shortest one) so every file shows up once.
+/// Also returns the kept files which are not at their real path, keyed by the real path.
+fn dedup_symlinked_files(files: Vec<FileItem>) -> (Vec<FileItem>, HashMap<PathBuf, PathBuf>) {
+    let initial_len = files.len();
+    let mut canonical_files: Vec<(PathBuf, FileItem)> = files
+        .into_par_iter()
+        .map(|file| {
+            let canonical = file
+                .path
+                .canonicalize()
+                .unwrap_or_else(|_| file.path.clone());
+            (canonical, file)
+        })
+        .collect();
+
+    canonical_files.par_sort_unstable_by(|(a_path, a), (b_path, b)| {
+        a_path
+            .cmp(b_path)
+            .then_with(|| (&a.path != a_path).cmp(&(&b.path != b_path)))
+            .then_with(|| a.relative_path.len().cmp(&b.relative_path.len()))
+            .then_with(|| a.relative_path.cmp(&b.relative_path))
+    });
+    canonical_files.dedup_by(|(a_path, _), (b_path, _)| a_path == b_path);
+
+    let symlinked_files = canonical_files
+        .iter()
+        .filter(|(canonical, file)| *canonical != file.path)
+        .map(|(canonical, file)| (canonical.clone(), file.path.clone()))
+        .collect();
+    let files: Vec<FileItem> = canonical_files.into_iter().map(|(_, file)| file).collect();
+    if files.len() < initial_len {
+        debug!(
+            "Removed {} files reachable through multiple symlinks",
+            initial_len - files.len()
+        );
+    }
+
+    (files, symlinked_files)
+}
+
 #[inline]
 fn is_git_file(path: &Path) -> bool {
     path.to_str().is_some_and(|path| {
@@ -610,3 +1625,307 @@ fn is_git_file(path: &Path) -> bool {
         }
     })
 }
+
+/// Blocks until the scan of the picker completes, fails the test when it takes over 5 s
+#[cfg(test)]
+pub(crate) fn wait_for_scan(picker: &SharedPicker) {
+    let start = std::time::Instant::now();
+    while picker.read().unwrap().is_scan_active() {
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(5),
+            "The scan didn't complete in 5 s"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+}
+
+/// Blocks until the watchers of every root are running, the changes made before are missed
+#[cfg(test)]
+pub(crate) fn wait_for_watchers(picker: &SharedPicker) {
+    wait_for_scan(picker);
+    let start = std::time::Instant::now();
+    loop {
+        let picker = picker.read().unwrap();
+        if picker.background_watchers.len() == picker.roots.len() {
+            break;
+        }
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(5),
+            "The watchers didn't start in 5 s"
+        );
+        drop(picker);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follow_symlinks_skips_loops_and_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(base_path.join("shared/proto")).unwrap();
+        std::fs::write(base_path.join("shared/proto/user.proto"), "").unwrap();
+        std::os::unix::fs::symlink(base_path.join("shared"), base_path.join("linked")).unwrap();
+        std::os::unix::fs::symlink(&base_path, base_path.join("shared/loop")).unwrap();
+
+        let scan = |follow_symlinks| {
+            let config = ScanConfig {
+                follow_symlinks,
+                ..Default::default()
+            };
+            let roots = build_roots(&base_path, &config).unwrap();
+            let sync = scan_roots(&base_path, &roots, &Arc::new(AtomicUsize::new(0))).unwrap();
+
+            sync.files
+                .iter()
+                .map(|file| file.relative_path.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(scan(false), vec!["shared/proto/user.proto"]);
+        assert_eq!(scan(true), vec!["shared/proto/user.proto"]);
+    }
+
+    #[test]
+    fn test_multiple_roots_are_labeled() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        for file in [
+            "app/src/main.rs",
+            "proto/user.proto",
+            "vendor/proto/user.proto",
+        ] {
+            let path = base_path.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let config = ScanConfig {
+            roots: vec![
+                RootConfig {
+                    path: base_path.join("proto"),
+                    label: None,
+                },
+                RootConfig {
+                    path: base_path.join("vendor/proto"),
+                    label: None,
+                },
+                // nested in the base path, skipped
+                RootConfig {
+                    path: base_path.join("app/src"),
+                    label: Some("src".to_string()),
+                },
+            ],
+            ..Default::default()
+        };
+        let app_path = base_path.join("app");
+        let roots = build_roots(&app_path, &config).unwrap();
+        let labels: Vec<_> = roots.iter().map(|root| root.label.as_deref()).collect();
+        assert_eq!(labels, vec![Some("app"), Some("proto"), Some("proto-2")]);
+
+        let sync = scan_roots(&base_path, &roots, &Arc::new(AtomicUsize::new(0))).unwrap();
+        let mut relative_paths: Vec<_> = sync
+            .files
+            .iter()
+            .map(|file| file.relative_path.clone())
+            .collect();
+        relative_paths.sort();
+        assert_eq!(
+            relative_paths,
+            vec!["app/src/main.rs", "proto-2/user.proto", "proto/user.proto"]
+        );
+        assert_eq!(sync.git_workdirs.len(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_outlives_index_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        std::fs::write(base_path.join("a.rs"), "").unwrap();
+        std::fs::write(base_path.join("b.rs"), "").unwrap();
+
+        let picker = FilePicker::new(base_path.to_string_lossy().to_string()).unwrap();
+        wait_for_scan(&picker);
+
+        let snapshot = picker.read().unwrap().files_snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        // the writer doesn't wait for the search holding the snapshot and doesn't affect it
+        assert!(
+            picker
+                .write()
+                .unwrap()
+                .remove_file_by_path(base_path.join("a.rs"))
+        );
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(picker.read().unwrap().get_files().len(), 1);
+
+        std::fs::write(base_path.join("c.rs"), "").unwrap();
+        FilePicker::trigger_rescan_shared(&picker).unwrap();
+        let relative_paths: Vec<_> = picker
+            .read()
+            .unwrap()
+            .get_files()
+            .iter()
+            .map(|file| file.relative_path.clone())
+            .collect();
+        assert_eq!(relative_paths, vec!["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(snapshot.len(), 2);
+    }
+
+    #[test]
+    fn test_state_updates_are_seen_by_held_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("project");
+        std::fs::create_dir(&base_path).unwrap();
+        let base_path = base_path.canonicalize().unwrap();
+        let repo = Repository::init(&base_path).unwrap();
+        for file in ["a.rs", "b.rs"] {
+            std::fs::write(base_path.join(file), "").unwrap();
+        }
+        let tracker = FrecencyTracker::new(dir.path().join("db").to_str().unwrap(), true).unwrap();
+
+        let picker = FilePicker::new(base_path.to_string_lossy().to_string()).unwrap();
+        wait_for_scan(&picker);
+        let snapshot = picker.read().unwrap().files_snapshot();
+
+        tracker.track_access(&base_path.join("b.rs")).unwrap();
+        let status = GitStatusCache::git_status_for_paths(&repo, &["a.rs"]);
+        {
+            let picker = picker.read().unwrap();
+            picker
+                .update_single_file_frecency(base_path.join("b.rs"), &tracker)
+                .unwrap();
+            picker.update_git_statuses(status).unwrap();
+        }
+
+        // no copy of the files, the search holding the snapshot ranks by the new state
+        assert!(Arc::ptr_eq(
+            &snapshot,
+            &picker.read().unwrap().files_snapshot()
+        ));
+        assert_eq!(snapshot[0].git_status(), Some(Status::WT_NEW));
+        assert!(snapshot[1].access_frecency_score() > 0);
+    }
+
+    #[test]
+    fn test_refined_search_matches_full_search() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "file_picker.rs",
+            "file_key.rs",
+            "picker_ui.lua",
+            "fuzzy_finder.rs",
+            "README.md",
+        ] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+
+        let picker = FilePicker::new(dir.path().to_string_lossy().to_string()).unwrap();
+        wait_for_scan(&picker);
+
+        let snapshot = picker.read().unwrap().files_snapshot();
+        let cache = SearchCache::default();
+        let options = SearchOptions {
+            max_results: 10,
+            max_threads: 1,
+            ..Default::default()
+        };
+        let relative_paths = |result: SearchResult| -> Vec<String> {
+            result
+                .items
+                .iter()
+                .map(|file| file.relative_path.clone())
+                .collect()
+        };
+
+        for query in ["fi", "fil", "file", "file_", "file_p", "file_pick"] {
+            let sources = [(FileSource::Index, &snapshot)];
+            let mut refined = FilePicker::rank_sources(&sources, query, &options, Some(&cache));
+            let mut full = FilePicker::rank_sources(&sources, query, &options, None);
+            let (refined, full) = (refined.page(0, 10), full.page(0, 10));
+            assert_eq!(refined.total_matched, full.total_matched, "{query}");
+            let totals = |result: &SearchResult| -> Vec<i32> {
+                result.scores.iter().map(|score| score.total).collect()
+            };
+            assert_eq!(totals(&refined), totals(&full), "{query}");
+            assert_eq!(relative_paths(refined), relative_paths(full), "{query}");
+        }
+    }
+
+    #[test]
+    fn test_rescan_keeps_the_changes_made_during_the_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        for file in ["a.rs", "b.rs"] {
+            std::fs::write(base_path.join(file), "").unwrap();
+        }
+
+        let picker = FilePicker::new(base_path.to_string_lossy().to_string()).unwrap();
+        wait_for_scan(&picker);
+
+        // the walk has passed the directory before the watcher reports the changes
+        let roots = picker.read().unwrap().roots.clone();
+        let sync = scan_roots(&base_path, &roots, &Arc::new(AtomicUsize::new(0))).unwrap();
+        std::fs::remove_file(base_path.join("a.rs")).unwrap();
+        std::fs::write(base_path.join("c.rs"), "").unwrap();
+
+        let mut picker = picker.write().unwrap();
+        picker.is_scanning.store(true, Ordering::Relaxed);
+        picker.remove_file_by_path(base_path.join("a.rs"));
+        picker.on_create_or_modify(base_path.join("c.rs"));
+        picker.swap_scanned_files(sync);
+        picker.is_scanning.store(false, Ordering::Relaxed);
+
+        let relative_paths: Vec<_> = picker
+            .get_files()
+            .iter()
+            .map(|file| file.relative_path.as_str())
+            .collect();
+        assert_eq!(relative_paths, vec!["b.rs", "c.rs"]);
+    }
+
+    #[test]
+    fn test_watcher_batch_is_applied_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        let repo = Repository::init(&base_path).unwrap();
+        for file in ["a.rs", "b.rs"] {
+            std::fs::write(base_path.join(file), "").unwrap();
+        }
+
+        let picker = FilePicker::new(base_path.to_string_lossy().to_string()).unwrap();
+        wait_for_scan(&picker);
+        let snapshot = picker.read().unwrap().files_snapshot();
+
+        std::fs::remove_file(base_path.join("a.rs")).unwrap();
+        std::fs::write(base_path.join("c.rs"), "").unwrap();
+        let (removed, changed) = (base_path.join("a.rs"), base_path.join("c.rs"));
+        let status = GitStatusCache::git_status_for_paths(&repo, &["c.rs"]);
+        picker
+            .write()
+            .unwrap()
+            .apply_watcher_changes(&[&removed], &[&changed], &[], status)
+            .unwrap();
+
+        let picker = picker.read().unwrap();
+        let files: Vec<_> = picker
+            .get_files()
+            .iter()
+            .map(|file| (file.relative_path.as_str(), file.git_status()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("b.rs", Some(Status::WT_NEW)),
+                ("c.rs", Some(Status::WT_NEW))
+            ]
+        );
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].relative_path, "a.rs");
+    }
+}
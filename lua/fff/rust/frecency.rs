@@ -1,21 +1,78 @@
 use crate::{error::Error, git::is_modified_status};
-use heed::{Database, Env, EnvOpenOptions};
+use heed::{Database, Env, EnvOpenOptions, RoTxn};
 use heed::{
     EnvFlags,
-    types::{Bytes, SerdeBincode},
+    types::{Bytes, SerdeBincode, Str},
 };
 use std::fs;
+use std::sync::{Mutex, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::{collections::VecDeque, path::Path};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
 
 const DECAY_CONSTANT: f64 = 0.0693; // ln(2)/10 for 10-day half-life
 const SECONDS_PER_DAY: f64 = 86400.0;
 const MAX_HISTORY_DAYS: f64 = 30.0; // Only consider accesses within 30 days
+/// Named databases next to the unnamed one holding the accesses
+const MAX_NAMED_DBS: u32 = 4;
+const PATHS_DB_NAME: &str = "paths";
+const PINS_DB_NAME: &str = "pins";
+const TRANSITIONS_DB_NAME: &str = "transitions";
+/// Accesses further apart than this don't belong to the same sequence of opened files
+const MAX_TRANSITION_GAP_SECONDS: u64 = 30 * 60;
 
 #[derive(Debug)]
 pub struct FrecencyTracker {
     env: Env,
     db: Database<Bytes, SerdeBincode<VecDeque<u64>>>,
+    /// The accesses are keyed by the hash of the path, this maps the hashes back to the paths.
+    /// Only files accessed since the paths are stored can be listed.
+    paths: Database<Bytes, Str>,
+    /// Pinned files of every project keyed by the project root, in the order they were pinned
+    pins: Database<Str, SerdeBincode<Vec<String>>>,
+    /// Pinned paths by the project root, read by the scoring of every file
+    pinned_paths: RwLock<HashMap<String, HashSet<PathBuf>>>,
+    /// Times a file was accessed right after another one, keyed by the hashes of both paths
+    /// (previous then next) so the files following a file share the key prefix
+    transitions: Database<Bytes, SerdeBincode<VecDeque<u64>>>,
+    /// The previously accessed file and when, the start of the next transition
+    last_access: Mutex<Option<(PathBuf, u64)>>,
+}
+
+/// A file of the frecency database with its access score
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentFile {
+    pub path: PathBuf,
+    /// Sum of the decayed accesses, orders files the rounded score can't tell apart
+    pub decayed_accesses: f64,
+    pub access_score: i64,
+}
+
+/// Inputs of the frecency scores of a single file, see `FrecencyTracker::explain`
+#[derive(Debug, Clone, Default)]
+pub struct FrecencyExplanation {
+    /// Accesses within the history window, oldest first (unix seconds)
+    pub access_timestamps: Vec<u64>,
+    /// Sum of the decayed accesses before the normalization
+    pub decayed_accesses: f64,
+    pub access_score: i64,
+    pub modified_seconds_ago: u64,
+    /// Only files modified according to git get a modification score
+    pub git_modified: bool,
+    pub modification_step: Option<ModificationStep>,
+    pub modification_score: i64,
+}
+
+/// The pair of modification thresholds the score was interpolated between, both are the same
+/// when the modification time falls on the first one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModificationStep {
+    pub newer_points: i64,
+    pub newer_seconds: u64,
+    pub older_points: i64,
+    pub older_seconds: u64,
 }
 
 const MODIFICATION_THRESHOLDS: [(i64, u64); 5] = [
@@ -31,6 +88,7 @@ impl FrecencyTracker {
         fs::create_dir_all(db_path).map_err(Error::CreateDir)?;
         let env = unsafe {
             let mut opts = EnvOpenOptions::new();
+            opts.max_dbs(MAX_NAMED_DBS);
             if use_unsafe_no_lock {
                 opts.flags(EnvFlags::NO_LOCK | EnvFlags::NO_SYNC | EnvFlags::NO_META_SYNC);
             }
@@ -44,11 +102,74 @@ impl FrecencyTracker {
         let db = env
             .create_database(&mut wtxn, None)
             .map_err(Error::DbCreate)?;
+        let paths = env
+            .create_database(&mut wtxn, Some(PATHS_DB_NAME))
+            .map_err(Error::DbCreate)?;
+        let pins: Database<Str, SerdeBincode<Vec<String>>> = env
+            .create_database(&mut wtxn, Some(PINS_DB_NAME))
+            .map_err(Error::DbCreate)?;
+        let transitions = env
+            .create_database(&mut wtxn, Some(TRANSITIONS_DB_NAME))
+            .map_err(Error::DbCreate)?;
+        let pinned_paths = load_pinned_paths(&pins, &wtxn)?;
+        wtxn.commit().map_err(Error::DbCommit)?;
 
-        Ok(FrecencyTracker {
+        let tracker = FrecencyTracker {
             db,
+            paths,
+            pins,
+            pinned_paths: RwLock::new(pinned_paths),
+            transitions,
+            last_access: Mutex::new(None),
             env: env.clone(),
-        })
+        };
+        tracker.prune_expired()?;
+        Ok(tracker)
+    }
+
+    /// Deletes the files and the transitions without any access in the history window, the
+    /// accesses are only dropped by the next access otherwise. Runs when the database is opened.
+    fn prune_expired(&self) -> Result<(), Error> {
+        let cutoff_time = self
+            .get_now()
+            .saturating_sub((MAX_HISTORY_DAYS * SECONDS_PER_DAY) as u64);
+        let is_expired =
+            |accesses: &VecDeque<u64>| accesses.back().is_none_or(|&last| last < cutoff_time);
+
+        let mut wtxn = self.env.write_txn().map_err(Error::DbStartWriteTxn)?;
+        // every file has its path stored, the default database also lists the named ones
+        let mut expired_files = Vec::new();
+        for entry in self.paths.iter(&wtxn).map_err(Error::DbRead)? {
+            let (key_hash, _) = entry.map_err(Error::DbRead)?;
+            let accesses = self.db.get(&wtxn, key_hash).map_err(Error::DbRead)?;
+            if accesses.as_ref().is_none_or(is_expired) {
+                expired_files.push(key_hash.to_vec());
+            }
+        }
+        for key_hash in &expired_files {
+            self.db
+                .delete(&mut wtxn, key_hash)
+                .map_err(Error::DbWrite)?;
+            self.paths
+                .delete(&mut wtxn, key_hash)
+                .map_err(Error::DbWrite)?;
+        }
+
+        let mut expired_transitions = Vec::new();
+        for entry in self.transitions.iter(&wtxn).map_err(Error::DbRead)? {
+            let (transition_key, transitions) = entry.map_err(Error::DbRead)?;
+            if is_expired(&transitions) {
+                expired_transitions.push(transition_key.to_vec());
+            }
+        }
+        for transition_key in &expired_transitions {
+            self.transitions
+                .delete(&mut wtxn, transition_key)
+                .map_err(Error::DbWrite)?;
+        }
+
+        wtxn.commit().map_err(Error::DbCommit)?;
+        Ok(())
     }
 
     fn get_accesses(&self, path: &Path) -> Result<Option<VecDeque<u64>>, Error> {
@@ -80,27 +201,72 @@ impl FrecencyTracker {
         let mut accesses = self.get_accesses(path)?.unwrap_or_default();
 
         let now = self.get_now();
-        let cutoff_time = now.saturating_sub((MAX_HISTORY_DAYS * SECONDS_PER_DAY) as u64);
-        while let Some(&front_time) = accesses.front() {
-            if front_time < cutoff_time {
-                accesses.pop_front();
-            } else {
-                break;
-            }
-        }
-
-        accesses.push_back(now);
+        push_access(&mut accesses, now);
         tracing::debug!(?path, accesses = accesses.len(), "Tracking access");
 
         self.db
             .put(&mut wtxn, &key_hash, &accesses)
             .map_err(Error::DbWrite)?;
+        if let Some(path) = path.to_str() {
+            self.paths
+                .put(&mut wtxn, &key_hash, path)
+                .map_err(Error::DbWrite)?;
+        }
+
+        let previous_access = self
+            .last_access
+            .lock()
+            .ok()
+            .and_then(|mut last_access| last_access.replace((path.to_path_buf(), now)));
+        if let Some((previous_path, previous_time)) = previous_access
+            && previous_path != path
+            && now.saturating_sub(previous_time) <= MAX_TRANSITION_GAP_SECONDS
+        {
+            let mut transition_key = Self::path_to_hash_bytes(&previous_path)?.to_vec();
+            transition_key.extend_from_slice(&key_hash);
+            let mut transitions = self
+                .transitions
+                .get(&wtxn, &transition_key)
+                .map_err(Error::DbRead)?
+                .unwrap_or_default();
+            push_access(&mut transitions, now);
+            self.transitions
+                .put(&mut wtxn, &transition_key, &transitions)
+                .map_err(Error::DbWrite)?;
+        }
 
         wtxn.commit().map_err(Error::DbCommit)?;
 
         Ok(())
     }
 
+    /// Files accessed right after `path` with the score of those transitions, decayed the same
+    /// way as the accesses. Files which haven't followed it within the history window are left out.
+    pub fn co_access_scores(&self, path: &Path) -> Result<HashMap<PathBuf, i64>, Error> {
+        let rtxn = self.env.read_txn().map_err(Error::DbStartReadTxn)?;
+        let prefix = Self::path_to_hash_bytes(path)?;
+        let now = self.get_now();
+
+        let mut scores = HashMap::new();
+        for entry in self
+            .transitions
+            .prefix_iter(&rtxn, &prefix)
+            .map_err(Error::DbRead)?
+        {
+            let (transition_key, transitions) = entry.map_err(Error::DbRead)?;
+            let (_, score) = access_score_at(&transitions, now);
+            if score == 0 {
+                continue;
+            }
+
+            let next_hash = &transition_key[prefix.len()..];
+            if let Some(next_path) = self.paths.get(&rtxn, next_hash).map_err(Error::DbRead)? {
+                scores.insert(PathBuf::from(next_path), score);
+            }
+        }
+        Ok(scores)
+    }
+
     pub fn get_access_score(&self, file_path: &Path) -> i64 {
         let accesses = self
             .get_accesses(file_path)
@@ -112,28 +278,134 @@ impl FrecencyTracker {
             return 0;
         }
 
-        let now = self.get_now();
-        let mut total_frecency = 0.0;
+        access_score_at(&accesses, self.get_now()).1
+    }
 
-        let cutoff_time = now.saturating_sub((MAX_HISTORY_DAYS * SECONDS_PER_DAY) as u64);
+    /// Pinned files of the project in the order they were pinned
+    pub fn pins(&self, project_root: &Path) -> Result<Vec<PathBuf>, Error> {
+        let rtxn = self.env.read_txn().map_err(Error::DbStartReadTxn)?;
+        let key = Self::project_key(project_root)?;
+        let pins = self.pins.get(&rtxn, key).map_err(Error::DbRead)?;
+        Ok(pins
+            .unwrap_or_default()
+            .into_iter()
+            .map(PathBuf::from)
+            .collect())
+    }
 
-        for &access_time in accesses.iter().rev() {
-            if access_time < cutoff_time {
-                break; // All remaining entries are older, stop processing
+    /// Returns false when the file was already pinned
+    pub fn add_pin(&self, project_root: &Path, path: &Path) -> Result<bool, Error> {
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?;
+        self.update_pins(project_root, |pins| {
+            if pins.iter().any(|pin| pin == path_str) {
+                return false;
             }
+            pins.push(path_str.to_string());
+            true
+        })
+    }
 
-            let days_ago = (now.saturating_sub(access_time) as f64) / SECONDS_PER_DAY;
-            let decay_factor = (-DECAY_CONSTANT * days_ago).exp();
-            total_frecency += decay_factor;
+    /// Returns false when the file was not pinned
+    pub fn remove_pin(&self, project_root: &Path, path: &Path) -> Result<bool, Error> {
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?;
+        self.update_pins(project_root, |pins| {
+            let pins_count = pins.len();
+            pins.retain(|pin| pin != path_str);
+            pins.len() != pins_count
+        })
+    }
+
+    /// A file is pinned in a project only, nested projects index the same files
+    pub fn is_pinned(&self, project_root: &Path, path: &Path) -> bool {
+        let Some(key) = project_root.to_str() else {
+            return false;
+        };
+        self.pinned_paths.read().is_ok_and(|pinned_paths| {
+            pinned_paths
+                .get(key)
+                .is_some_and(|project_pins| project_pins.contains(path))
+        })
+    }
+
+    fn update_pins(
+        &self,
+        project_root: &Path,
+        update: impl FnOnce(&mut Vec<String>) -> bool,
+    ) -> Result<bool, Error> {
+        let key = Self::project_key(project_root)?;
+        let mut wtxn = self.env.write_txn().map_err(Error::DbStartWriteTxn)?;
+        let mut pins = self
+            .pins
+            .get(&wtxn, key)
+            .map_err(Error::DbRead)?
+            .unwrap_or_default();
+        if !update(&mut pins) {
+            return Ok(false);
         }
 
-        let normalized_frecency = if total_frecency <= 10.0 {
-            total_frecency
+        if pins.is_empty() {
+            self.pins.delete(&mut wtxn, key).map_err(Error::DbWrite)?;
         } else {
-            10.0 + (total_frecency - 10.0).sqrt() // Diminishing: >10 accesses grow slowly
-        };
+            self.pins
+                .put(&mut wtxn, key, &pins)
+                .map_err(Error::DbWrite)?;
+        }
 
-        normalized_frecency.round() as i64
+        wtxn.commit().map_err(Error::DbCommit)?;
+
+        if let Ok(mut pinned_paths) = self.pinned_paths.write() {
+            if pins.is_empty() {
+                pinned_paths.remove(key);
+            } else {
+                pinned_paths.insert(
+                    key.to_string(),
+                    pins.into_iter().map(PathBuf::from).collect(),
+                );
+            }
+        }
+        Ok(true)
+    }
+
+    fn project_key(project_root: &Path) -> Result<&str, Error> {
+        project_root
+            .to_str()
+            .ok_or_else(|| Error::InvalidPath(project_root.to_path_buf()))
+    }
+
+    /// Every stored path accessed within the history window, most frecent first. Paths which
+    /// are not files anymore are skipped.
+    pub fn recent_files(&self) -> Result<Vec<RecentFile>, Error> {
+        let rtxn = self.env.read_txn().map_err(Error::DbStartReadTxn)?;
+        let now = self.get_now();
+
+        let mut recent_files = Vec::new();
+        for entry in self.paths.iter(&rtxn).map_err(Error::DbRead)? {
+            let (key_hash, path) = entry.map_err(Error::DbRead)?;
+            let Some(accesses) = self.db.get(&rtxn, key_hash).map_err(Error::DbRead)? else {
+                continue;
+            };
+
+            let (decayed_accesses, access_score) = access_score_at(&accesses, now);
+            let path = PathBuf::from(path);
+            if decayed_accesses > 0.0 && path.is_file() {
+                recent_files.push(RecentFile {
+                    path,
+                    decayed_accesses,
+                    access_score,
+                });
+            }
+        }
+
+        recent_files.sort_by(|a, b| {
+            b.decayed_accesses
+                .total_cmp(&a.decayed_accesses)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        Ok(recent_files)
     }
 
     /// Calculating modification score but only if the file is modified in the current git dir
@@ -148,31 +420,128 @@ impl FrecencyTracker {
         }
 
         let now = self.get_now();
-        let duration_since = now.saturating_sub(modified_time);
+        modification_score_since(now.saturating_sub(modified_time)).0
+    }
 
-        for i in 0..MODIFICATION_THRESHOLDS.len() {
-            let (current_points, current_threshold) = MODIFICATION_THRESHOLDS[i];
+    /// Recomputes both frecency scores of the file keeping every input
+    pub fn explain(
+        &self,
+        path: &Path,
+        modified_time: u64,
+        git_status: Option<git2::Status>,
+    ) -> FrecencyExplanation {
+        let now = self.get_now();
+        let cutoff_time = now.saturating_sub((MAX_HISTORY_DAYS * SECONDS_PER_DAY) as u64);
+        let mut accesses = self.get_accesses(path).ok().flatten().unwrap_or_default();
+        accesses.retain(|&access_time| access_time >= cutoff_time);
+        let (decayed_accesses, access_score) = access_score_at(&accesses, now);
+
+        let modified_seconds_ago = now.saturating_sub(modified_time);
+        let git_modified = git_status.is_some_and(is_modified_status);
+        let (modification_score, modification_step) = if git_modified {
+            modification_score_since(modified_seconds_ago)
+        } else {
+            (0, None)
+        };
 
-            if duration_since <= current_threshold {
-                if i == 0 || duration_since == current_threshold {
-                    return current_points;
-                }
+        FrecencyExplanation {
+            access_timestamps: accesses.into(),
+            decayed_accesses,
+            access_score,
+            modified_seconds_ago,
+            git_modified,
+            modification_step,
+            modification_score,
+        }
+    }
+}
 
-                let (prev_points, prev_threshold) = MODIFICATION_THRESHOLDS[i - 1];
+fn load_pinned_paths(
+    pins: &Database<Str, SerdeBincode<Vec<String>>>,
+    txn: &RoTxn,
+) -> Result<HashMap<String, HashSet<PathBuf>>, Error> {
+    let mut pinned_paths = HashMap::new();
+    for entry in pins.iter(txn).map_err(Error::DbRead)? {
+        let (project_root, project_pins) = entry.map_err(Error::DbRead)?;
+        let project_pins = project_pins.into_iter().map(PathBuf::from).collect();
+        pinned_paths.insert(project_root.to_string(), project_pins);
+    }
+    Ok(pinned_paths)
+}
 
-                let time_range = current_threshold - prev_threshold;
-                let time_offset = duration_since - prev_threshold;
-                let points_diff = prev_points - current_points;
+/// Appends the access at `now` dropping the ones older than the history window
+fn push_access(accesses: &mut VecDeque<u64>, now: u64) {
+    let cutoff_time = now.saturating_sub((MAX_HISTORY_DAYS * SECONDS_PER_DAY) as u64);
+    while let Some(&front_time) = accesses.front() {
+        if front_time < cutoff_time {
+            accesses.pop_front();
+        } else {
+            break;
+        }
+    }
 
-                let interpolated_score =
-                    prev_points - (points_diff * time_offset as i64) / time_range as i64;
+    accesses.push_back(now);
+}
 
-                return interpolated_score;
-            }
+/// Sum of the decayed accesses and the normalized access score
+fn access_score_at(accesses: &VecDeque<u64>, now: u64) -> (f64, i64) {
+    let mut total_frecency = 0.0;
+
+    let cutoff_time = now.saturating_sub((MAX_HISTORY_DAYS * SECONDS_PER_DAY) as u64);
+
+    for &access_time in accesses.iter().rev() {
+        if access_time < cutoff_time {
+            break; // All remaining entries are older, stop processing
         }
 
-        0
+        let days_ago = (now.saturating_sub(access_time) as f64) / SECONDS_PER_DAY;
+        let decay_factor = (-DECAY_CONSTANT * days_ago).exp();
+        total_frecency += decay_factor;
     }
+
+    let normalized_frecency = if total_frecency <= 10.0 {
+        total_frecency
+    } else {
+        10.0 + (total_frecency - 10.0).sqrt() // Diminishing: >10 accesses grow slowly
+    };
+
+    (total_frecency, normalized_frecency.round() as i64)
+}
+
+/// Modification score of a file modified `duration_since` seconds ago with the thresholds
+/// it was interpolated between, no step once it's older than the last threshold
+fn modification_score_since(duration_since: u64) -> (i64, Option<ModificationStep>) {
+    for i in 0..MODIFICATION_THRESHOLDS.len() {
+        let (current_points, current_threshold) = MODIFICATION_THRESHOLDS[i];
+
+        if duration_since <= current_threshold {
+            let (prev_points, prev_threshold) = match i {
+                0 => (current_points, current_threshold),
+                _ => MODIFICATION_THRESHOLDS[i - 1],
+            };
+            let step = ModificationStep {
+                newer_points: prev_points,
+                newer_seconds: prev_threshold,
+                older_points: current_points,
+                older_seconds: current_threshold,
+            };
+
+            if i == 0 || duration_since == current_threshold {
+                return (current_points, Some(step));
+            }
+
+            let time_range = current_threshold - prev_threshold;
+            let time_offset = duration_since - prev_threshold;
+            let points_diff = prev_points - current_points;
+
+            let interpolated_score =
+                prev_points - (points_diff * time_offset as i64) / time_range as i64;
+
+            return (interpolated_score, Some(step));
+        }
+    }
+
+    (0, None)
 }
 
 #[cfg(test)]
@@ -244,6 +613,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_expired_accesses_are_pruned_on_open() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let tracker = FrecencyTracker::new(db_path.to_str().unwrap(), true).unwrap();
+        let (old, recent) = (Path::new("/work/old.rs"), Path::new("/work/recent.rs"));
+        tracker.track_access(old).unwrap();
+        tracker.track_access(recent).unwrap();
+
+        // the accesses of the old file and its transition to the recent one left the window
+        let expired = VecDeque::from([tracker.get_now() - 31 * 86400]);
+        let old_hash = FrecencyTracker::path_to_hash_bytes(old).unwrap();
+        let mut transition_key = old_hash.to_vec();
+        transition_key.extend_from_slice(&FrecencyTracker::path_to_hash_bytes(recent).unwrap());
+        let mut wtxn = tracker.env.write_txn().unwrap();
+        tracker.db.put(&mut wtxn, &old_hash, &expired).unwrap();
+        tracker
+            .transitions
+            .put(&mut wtxn, &transition_key, &expired)
+            .unwrap();
+        wtxn.commit().unwrap();
+        drop(tracker);
+
+        let tracker = FrecencyTracker::new(db_path.to_str().unwrap(), true).unwrap();
+        let rtxn = tracker.env.read_txn().unwrap();
+        assert!(tracker.get_accesses(old).unwrap().is_none());
+        assert!(tracker.paths.get(&rtxn, &old_hash).unwrap().is_none());
+        assert!(
+            tracker
+                .transitions
+                .get(&rtxn, &transition_key)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(tracker.get_accesses(recent).unwrap().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_recent_files_list_stored_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tracker =
+            FrecencyTracker::new(temp_dir.path().join("db").to_str().unwrap(), true).unwrap();
+
+        let frequent = temp_dir.path().join("frequent.rs");
+        let once = temp_dir.path().join("once.rs");
+        let deleted = temp_dir.path().join("deleted.rs");
+        for path in [&frequent, &once, &deleted] {
+            std::fs::write(path, "").unwrap();
+            tracker.track_access(path).unwrap();
+        }
+        tracker.track_access(&frequent).unwrap();
+        std::fs::remove_file(&deleted).unwrap();
+
+        let recent_files = tracker.recent_files().unwrap();
+        let paths: Vec<&Path> = recent_files
+            .iter()
+            .map(|file| file.path.as_path())
+            .collect();
+        assert_eq!(paths, vec![frequent.as_path(), once.as_path()]);
+        assert_eq!(recent_files[0].access_score, 2);
+    }
+
+    #[test]
+    fn test_pins_are_kept_per_project() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let tracker = FrecencyTracker::new(db_path.to_str().unwrap(), true).unwrap();
+
+        let (project, other_project) = (Path::new("/work/app"), Path::new("/work/lib"));
+        let (main, config) = (
+            Path::new("/work/app/src/main.rs"),
+            Path::new("/work/app/config.toml"),
+        );
+        assert!(tracker.add_pin(project, main).unwrap());
+        assert!(tracker.add_pin(project, config).unwrap());
+        assert!(!tracker.add_pin(project, main).unwrap());
+
+        assert_eq!(tracker.pins(project).unwrap(), vec![main, config]);
+        assert!(tracker.pins(other_project).unwrap().is_empty());
+        assert!(tracker.is_pinned(project, main));
+        // a parent project indexes the file too, the pin is not its own
+        assert!(!tracker.is_pinned(Path::new("/work"), main));
+
+        assert!(tracker.remove_pin(project, main).unwrap());
+        assert!(!tracker.remove_pin(project, main).unwrap());
+        assert!(!tracker.is_pinned(project, main));
+        drop(tracker);
+
+        // the pins survive reopening the database
+        let tracker = FrecencyTracker::new(db_path.to_str().unwrap(), true).unwrap();
+        assert_eq!(tracker.pins(project).unwrap(), vec![config]);
+        assert!(tracker.is_pinned(project, config));
+    }
+
+    #[test]
+    fn test_co_access_follows_the_access_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tracker =
+            FrecencyTracker::new(temp_dir.path().join("db").to_str().unwrap(), true).unwrap();
+
+        let (model, view, test) = (
+            Path::new("/work/app/model.rs"),
+            Path::new("/work/app/view.rs"),
+            Path::new("/work/app/model_test.rs"),
+        );
+        for _ in 0..3 {
+            tracker.track_access(model).unwrap();
+            tracker.track_access(view).unwrap();
+        }
+        tracker.track_access(model).unwrap();
+        tracker.track_access(test).unwrap();
+        // reopening the same file is not a transition
+        tracker.track_access(test).unwrap();
+
+        let scores = tracker.co_access_scores(model).unwrap();
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[view], 3);
+        assert_eq!(scores[test], 1);
+        assert_eq!(
+            tracker.co_access_scores(view).unwrap(),
+            HashMap::from([(model.to_path_buf(), 3)])
+        );
+        assert!(tracker.co_access_scores(test).unwrap().is_empty());
+    }
+
     #[test]
     fn test_modification_score_interpolation() {
         let temp_dir = std::env::temp_dir().join("fff_test_interpolation");
@@ -0,0 +1,560 @@
+use crate::error::Error;
+use crate::git::is_modified_status;
+use crate::location::{ColumnEncoding, Location};
+use crate::types::FileItem;
+use mlua::prelude::*;
+use rayon::prelude::*;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::ast::{self, Ast};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tracing::debug;
+
+/// Files with a NUL byte in their first bytes are binary, the same heuristic as git and ripgrep
+const BINARY_CHECK_BYTES: usize = 8192;
+/// Longer lines are skipped by the fuzzy matching, minified code matches almost any query
+const MAX_FUZZY_LINE_LENGTH: usize = 512;
+
+/// How the pattern of `grep_files` is matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrepMode {
+    Literal,
+    Regex,
+    /// The characters of the pattern in order with a few typos, for lines remembered roughly.
+    /// The matches are ranked by the score instead of the file order.
+    Fuzzy,
+}
+
+impl std::str::FromStr for GrepMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "literal" => Ok(GrepMode::Literal),
+            "regex" => Ok(GrepMode::Regex),
+            "fuzzy" => Ok(GrepMode::Fuzzy),
+            _ => Err(Error::InvalidGrepMode(mode.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepOptions {
+    pub mode: GrepMode,
+    /// Ignores the case unless the pattern has an uppercase letter, otherwise the case matters.
+    /// The fuzzy matching always ignores it and prefers the lines with the same case.
+    pub smart_case: bool,
+    pub max_results: usize,
+    /// Lines of a single file kept at most, a minified or generated file would crowd out the rest
+    pub max_matches_per_file: usize,
+    /// Larger files are skipped, usually generated or data files
+    pub max_file_size: u64,
+}
+
+impl Default for GrepOptions {
+    fn default() -> Self {
+        Self {
+            mode: GrepMode::Literal,
+            smart_case: true,
+            max_results: 1000,
+            max_matches_per_file: 100,
+            max_file_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// Finds the matches of the pattern in the lines of a file
+#[derive(Debug, Clone)]
+pub(crate) enum LineMatcher {
+    Regex(Regex),
+    Fuzzy {
+        query: String,
+        config: neo_frizbee::Config,
+    },
+}
+
+impl GrepOptions {
+    /// Regex matching the pattern and whether it ignores the case, `None` for the fuzzy mode
+    pub(crate) fn regex_pattern(&self, pattern: &str) -> Option<(String, bool)> {
+        let has_uppercase = match self.mode {
+            GrepMode::Literal => pattern.chars().any(char::is_uppercase),
+            GrepMode::Regex => has_uppercase_literal(pattern),
+            GrepMode::Fuzzy => return None,
+        };
+        let pattern = match self.mode {
+            GrepMode::Literal => regex::escape(pattern),
+            _ => pattern.to_string(),
+        };
+        Some((pattern, self.smart_case && !has_uppercase))
+    }
+
+    pub(crate) fn matcher(&self, pattern: &str) -> Result<LineMatcher, Error> {
+        let Some((pattern, case_insensitive)) = self.regex_pattern(pattern) else {
+            return Ok(fuzzy_matcher(pattern));
+        };
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .crlf(true)
+            .build()
+            .map(LineMatcher::Regex)
+            .map_err(Error::InvalidGrepPattern)
+    }
+}
+
+/// Whether the regex matches an uppercase letter literally, escapes such as `\W` or `\p{Lu}`
+/// don't count for the smart case
+fn has_uppercase_literal(pattern: &str) -> bool {
+    struct UppercaseLiterals;
+
+    impl ast::Visitor for UppercaseLiterals {
+        type Output = ();
+        /// Stops the walk at the first uppercase literal
+        type Err = ();
+
+        fn finish(self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+            match ast {
+                Ast::Literal(literal) if literal.c.is_uppercase() => Err(()),
+                _ => Ok(()),
+            }
+        }
+
+        fn visit_class_set_item_pre(&mut self, item: &ast::ClassSetItem) -> Result<(), ()> {
+            match item {
+                ast::ClassSetItem::Literal(literal) if literal.c.is_uppercase() => Err(()),
+                ast::ClassSetItem::Range(range)
+                    if range.start.c.is_uppercase() || range.end.c.is_uppercase() =>
+                {
+                    Err(())
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    match ast::parse::Parser::new().parse(pattern) {
+        Ok(ast) => ast::visit(&ast, UppercaseLiterals).is_err(),
+        // the regex doesn't build anyway
+        Err(_) => pattern.chars().any(char::is_uppercase),
+    }
+}
+
+fn fuzzy_matcher(query: &str) -> LineMatcher {
+    let has_uppercase_letter = query.chars().any(|c| c.is_uppercase());
+    LineMatcher::Fuzzy {
+        query: query.to_string(),
+        config: neo_frizbee::Config {
+            prefilter: true,
+            // lines are longer than paths, typos would match nearly every one of them
+            max_typos: Some((query.len() as u16 / 6).min(3)),
+            sort: false,
+            scoring: neo_frizbee::Scoring {
+                capitalization_bonus: if has_uppercase_letter { 8 } else { 0 },
+                matching_case_bonus: if has_uppercase_letter { 4 } else { 0 },
+                ..Default::default()
+            },
+        },
+    }
+}
+
+/// A line of a file matching the pattern
+#[derive(Debug, Clone)]
+pub struct GrepMatch<'a> {
+    pub file: &'a FileItem,
+    /// 1-based
+    pub line: i32,
+    /// The line without the line break, invalid UTF-8 is replaced
+    pub text: String,
+    /// Byte ranges of every match within the line, 0-based with an exclusive end
+    pub spans: Vec<(usize, usize)>,
+    /// Fuzzy match score weighted by the frecency of the file, `None` for the other modes
+    pub score: Option<i32>,
+}
+
+impl GrepMatch<'_> {
+    /// Range of the first match on the line, in byte columns as Neovim expects them
+    pub fn location(&self) -> Location {
+        let (start, end) = self.spans.first().copied().unwrap_or_default();
+        Location::Range {
+            start: (self.line, start as i32 + 1),
+            end: (self.line, end as i32 + 1),
+            encoding: ColumnEncoding::Byte,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrepResult<'a> {
+    /// In the order of the files, the lines of a file in order. Fuzzy matches are sorted by
+    /// the score, the highest first.
+    pub matches: Vec<GrepMatch<'a>>,
+    /// Files read, the binary and the large ones are not
+    pub files_searched: usize,
+    /// Whether matching stopped at `max_results`, the matches then come from the files finishing
+    /// first and not necessarily from the first files
+    pub truncated: bool,
+}
+
+/// Searches the contents of the files in parallel, one match per matching line. Only the files
+/// flagged in `candidates` are read, see `TrigramIndex::file_filter`.
+pub fn grep_files<'a>(
+    files: &'a [Arc<FileItem>],
+    pattern: &str,
+    options: &GrepOptions,
+    candidates: Option<&[bool]>,
+) -> Result<GrepResult<'a>, Error> {
+    if pattern.is_empty() {
+        return Ok(GrepResult {
+            matches: Vec::new(),
+            files_searched: 0,
+            truncated: false,
+        });
+    }
+
+    let matcher = options.matcher(pattern)?;
+    // the best fuzzy matches can be in any file, every file is matched before truncating
+    let stop_early = options.mode != GrepMode::Fuzzy;
+    let time = std::time::Instant::now();
+    let matched = AtomicUsize::new(0);
+    let searched = AtomicUsize::new(0);
+    let skipped = AtomicBool::new(false);
+
+    let mut matches: Vec<GrepMatch> = files
+        .par_iter()
+        .enumerate()
+        .filter(|(index, _)| candidates.is_none_or(|candidates| candidates[*index]))
+        .flat_map_iter(|(_, file)| {
+            if stop_early && matched.load(Ordering::Relaxed) >= options.max_results {
+                skipped.store(true, Ordering::Relaxed);
+                return Vec::new();
+            }
+
+            let Some(file_matches) = grep_file(file, &matcher, options) else {
+                return Vec::new();
+            };
+            searched.fetch_add(1, Ordering::Relaxed);
+            matched.fetch_add(file_matches.len(), Ordering::Relaxed);
+            file_matches
+        })
+        .collect();
+    if !stop_early {
+        // stable, ties keep the order of the files and lines
+        matches.sort_by_key(|found| std::cmp::Reverse(found.score));
+    }
+
+    let truncated = matches.len() > options.max_results || skipped.into_inner();
+    matches.truncate(options.max_results);
+    debug!(?pattern, matches = matches.len(), completed_in = ?time.elapsed(), "Grep completed");
+
+    Ok(GrepResult {
+        matches,
+        files_searched: searched.into_inner(),
+        truncated,
+    })
+}
+
+/// Matching lines of the file, `None` when it is not searched: too large, binary or unreadable
+pub(crate) fn grep_file<'a>(
+    file: &'a FileItem,
+    matcher: &LineMatcher,
+    options: &GrepOptions,
+) -> Option<Vec<GrepMatch<'a>>> {
+    if file.size > options.max_file_size {
+        return None;
+    }
+
+    let bytes = std::fs::read(&file.path).ok()?;
+    if bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0) {
+        return None;
+    }
+
+    Some(match matcher {
+        LineMatcher::Regex(regex) => {
+            grep_content(file, &bytes, regex, options.max_matches_per_file)
+        }
+        LineMatcher::Fuzzy { query, config } => {
+            fuzzy_grep_content(file, &bytes, query, config, options.max_matches_per_file)
+        }
+    })
+}
+
+fn grep_content<'a>(
+    file: &'a FileItem,
+    bytes: &[u8],
+    regex: &Regex,
+    max_matches: usize,
+) -> Vec<GrepMatch<'a>> {
+    // most files don't match at all, a single pass over the whole file rules them out
+    if !regex.is_match(bytes) {
+        return Vec::new();
+    }
+
+    bytes
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let spans: Vec<(usize, usize)> = regex
+                .find_iter(line)
+                .map(|found| (found.start(), found.end()))
+                .collect();
+            (!spans.is_empty()).then(|| GrepMatch {
+                file,
+                line: index as i32 + 1,
+                text: String::from_utf8_lossy(line).into_owned(),
+                spans,
+                score: None,
+            })
+        })
+        .take(max_matches)
+        .collect()
+}
+
+/// The best fuzzy matching lines of the file, scored as the paths: the frecency of the file
+/// adds to the score of the line, and the uncommitted changes are what is usually looked for
+fn fuzzy_grep_content<'a>(
+    file: &'a FileItem,
+    bytes: &[u8],
+    query: &str,
+    config: &neo_frizbee::Config,
+    max_matches: usize,
+) -> Vec<GrepMatch<'a>> {
+    let content = String::from_utf8_lossy(bytes);
+    let (line_numbers, lines): (Vec<usize>, Vec<&str>) = content
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .enumerate()
+        .filter(|(_, line)| line.len() <= MAX_FUZZY_LINE_LENGTH && !line.trim().is_empty())
+        .unzip();
+
+    let mut line_matches = neo_frizbee::match_list(query, &lines, config);
+    line_matches.sort_by_key(|found| (std::cmp::Reverse(found.score), found.index));
+    line_matches.truncate(max_matches);
+
+    let is_modified = file.git_status().is_some_and(is_modified_status);
+    line_matches
+        .into_iter()
+        .map(|found| {
+            let line = lines[found.index as usize];
+            let line_score = found.score as i32;
+            let frecency_boost =
+                line_score.saturating_mul(file.total_frecency_score() as i32) / 100;
+            let modified_bonus = if is_modified { line_score / 4 } else { 0 };
+            GrepMatch {
+                file,
+                line: line_numbers[found.index as usize] as i32 + 1,
+                text: line.to_string(),
+                spans: fuzzy_spans(query, line, config),
+                score: Some(line_score + frecency_boost + modified_bonus),
+            }
+        })
+        .collect()
+}
+
+/// Byte ranges of the matched characters, the adjacent ones merged
+fn fuzzy_spans(query: &str, line: &str, config: &neo_frizbee::Config) -> Vec<(usize, usize)> {
+    let Some(matched) = neo_frizbee::match_indices(query, line, config) else {
+        return Vec::new();
+    };
+
+    let mut indices = matched.indices;
+    indices.sort_unstable();
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for index in indices {
+        let Some(c) = line.get(index..).and_then(|rest| rest.chars().next()) else {
+            continue;
+        };
+        let end = index + c.len_utf8();
+        match spans.last_mut() {
+            Some((_, last_end)) if *last_end == index => *last_end = end,
+            _ => spans.push((index, end)),
+        }
+    }
+    spans
+}
+
+impl FromLua for GrepOptions {
+    fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let table = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(table) => table,
+            other => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: other.type_name(),
+                    to: "GrepOptions".to_string(),
+                    message: Some("expected a table with grep options".to_string()),
+                });
+            }
+        };
+
+        let default = Self::default();
+        Ok(Self {
+            mode: match table.get::<Option<String>>("mode")? {
+                Some(mode) => mode.parse()?,
+                None => default.mode,
+            },
+            smart_case: table
+                .get::<Option<bool>>("smart_case")?
+                .unwrap_or(default.smart_case),
+            max_results: table
+                .get::<Option<usize>>("max_results")?
+                .unwrap_or(default.max_results),
+            max_matches_per_file: table
+                .get::<Option<usize>>("max_matches_per_file")?
+                .unwrap_or(default.max_matches_per_file),
+            max_file_size: table
+                .get::<Option<u64>>("max_file_size")?
+                .unwrap_or(default.max_file_size),
+        })
+    }
+}
+
+impl IntoLua for GrepMatch<'_> {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("path", self.file.path.to_string_lossy())?;
+        table.set("relative_path", self.file.relative_path.as_str())?;
+        table.set("line", self.line)?;
+        table.set(
+            "col",
+            self.spans.first().map_or(1, |(start, _)| *start as i32 + 1),
+        )?;
+        table.set("location", self.location())?;
+        // 1-based byte columns with an exclusive end, as the columns of the ranges
+        let spans = lua.create_sequence_from(
+            self.spans
+                .iter()
+                .map(|(start, end)| [*start as i32 + 1, *end as i32 + 1]),
+        )?;
+        table.set("spans", spans)?;
+        table.set("text", self.text)?;
+        if let Some(score) = self.score {
+            table.set("score", score)?;
+        }
+        Ok(LuaValue::Table(table))
+    }
+}
+
+impl IntoLua for GrepResult<'_> {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("matches", lua.create_sequence_from(self.matches)?)?;
+        table.set("files_searched", self.files_searched)?;
+        table.set("truncated", self.truncated)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(dir: &std::path::Path, relative_path: &str, content: &[u8]) -> Arc<FileItem> {
+        let path = dir.join(relative_path);
+        std::fs::write(&path, content).unwrap();
+        let mut file = FileItem::with_relative_path(path, relative_path.to_string(), None);
+        file.size = content.len() as u64;
+        Arc::new(file)
+    }
+
+    #[test]
+    fn test_grep_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            file(
+                dir.path(),
+                "a.rs",
+                b"fn parse() {}\r\nlet Parse = parse(a.b);\n",
+            ),
+            file(dir.path(), "b.rs", b"no match here\n"),
+            file(dir.path(), "image.png", b"parse\0binary"),
+        ];
+        let grep = |pattern, options: GrepOptions| {
+            grep_files(&files, pattern, &options, None)
+                .unwrap()
+                .matches
+                .into_iter()
+                .map(|found| (found.file.relative_path.clone(), found.line, found.spans))
+                .collect::<Vec<_>>()
+        };
+
+        // smart case: lowercase ignores the case, binaries are skipped
+        assert_eq!(
+            grep("parse", GrepOptions::default()),
+            vec![
+                ("a.rs".to_string(), 1, vec![(3, 8)]),
+                ("a.rs".to_string(), 2, vec![(4, 9), (12, 17)]),
+            ]
+        );
+        assert_eq!(
+            grep("Parse", GrepOptions::default()),
+            vec![("a.rs".to_string(), 2, vec![(4, 9)])]
+        );
+        // a literal dot is not a wildcard
+        assert_eq!(grep("a.b", GrepOptions::default()).len(), 1);
+        assert_eq!(grep("e.a", GrepOptions::default()).len(), 0);
+        let regex = GrepOptions {
+            mode: GrepMode::Regex,
+            ..Default::default()
+        };
+        assert_eq!(
+            grep(r"^fn \w+\(\) \{\}$", regex.clone()),
+            vec![("a.rs".to_string(), 1, vec![(0, 13)])]
+        );
+        // only the uppercase literals make the case matter, not the escapes
+        assert_eq!(
+            grep(r"\Wparse =", regex.clone()),
+            vec![("a.rs".to_string(), 2, vec![(3, 11)])]
+        );
+        assert_eq!(
+            grep(r"[P]arse\b", regex.clone()),
+            vec![("a.rs".to_string(), 2, vec![(4, 9)])]
+        );
+
+        let result = grep_files(&files, "parse", &GrepOptions::default(), None).unwrap();
+        assert_eq!(result.files_searched, 2);
+        assert_eq!(
+            result.matches[1].location(),
+            Location::Range {
+                start: (2, 5),
+                end: (2, 10),
+                encoding: ColumnEncoding::Byte
+            }
+        );
+        assert!(grep_files(&files, "(", &regex, None).is_err());
+    }
+
+    #[test]
+    fn test_fuzzy_lines_ranked_by_frecency() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = b"fn main() {\n    let user_count = users.len();\n}\n";
+        let files = vec![
+            file(dir.path(), "old.rs", content),
+            file(dir.path(), "recent.rs", content),
+            file(dir.path(), "modified.rs", content),
+        ];
+        files[1].set_frecency_scores(50, 0);
+        files[2].set_git_status(Some(git2::Status::WT_MODIFIED));
+
+        let options = GrepOptions {
+            mode: GrepMode::Fuzzy,
+            ..Default::default()
+        };
+        let result = grep_files(&files, "usercount", &options, None).unwrap();
+        let ranked: Vec<(&str, i32)> = result
+            .matches
+            .iter()
+            .map(|found| (found.file.relative_path.as_str(), found.line))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![("recent.rs", 2), ("modified.rs", 2), ("old.rs", 2)]
+        );
+        assert_eq!(result.matches[0].spans, vec![(8, 12), (13, 18)]);
+    }
+}
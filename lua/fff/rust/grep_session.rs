@@ -0,0 +1,413 @@
+use crate::error::Error;
+use crate::file_picker::FileSnapshot;
+use crate::grep::{GrepMatch, GrepMode, GrepOptions, grep_file};
+use mlua::prelude::*;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Files grepped before their matches are pushed to the buffer, small enough for the first
+/// matches to show up right away
+const BATCH_FILES: usize = 64;
+
+/// A match of the running grep, the file is an index into the grepped snapshot
+#[derive(Debug, Clone)]
+struct FileMatch {
+    file_index: usize,
+    line: i32,
+    text: String,
+    spans: Vec<(usize, usize)>,
+    score: Option<i32>,
+}
+
+/// Matches pushed by the workers and not polled yet. A ranked (fuzzy) grep keeps the best
+/// matches found so far instead, polled or not.
+#[derive(Debug)]
+struct GrepBuffer {
+    generation: u64,
+    files: FileSnapshot,
+    matches: Vec<FileMatch>,
+    max_results: usize,
+    total_matched: usize,
+    files_searched: usize,
+    truncated: bool,
+    done: bool,
+    ranked: bool,
+    /// The best matches changed since the previous poll of a ranked grep
+    changed: bool,
+}
+
+impl GrepBuffer {
+    /// Keeps the matches up to `max_results`, the workers only stop at the next file. A ranked
+    /// grep keeps the best `max_results` of them and reads every file.
+    fn push(&mut self, mut matches: Vec<FileMatch>, files_searched: usize) {
+        self.files_searched += files_searched;
+        if self.ranked {
+            self.total_matched += matches.len();
+            if matches.is_empty() {
+                return;
+            }
+
+            self.matches.extend(matches);
+            // stable, ties keep the order they were found in
+            self.matches
+                .sort_by_key(|found| std::cmp::Reverse(found.score));
+            if self.matches.len() > self.max_results {
+                self.matches.truncate(self.max_results);
+                self.truncated = true;
+            }
+            self.changed = true;
+            return;
+        }
+
+        let remaining = self.max_results - self.total_matched;
+        if matches.len() > remaining {
+            matches.truncate(remaining);
+            self.truncated = true;
+        }
+
+        self.total_matched += matches.len();
+        self.matches.extend(matches);
+    }
+
+    /// The best fuzzy matches can be in any file, a ranked grep never stops early
+    fn is_stopped(&self) -> bool {
+        self.truncated && !self.ranked
+    }
+
+    fn take_matches(&mut self) -> Vec<FileMatch> {
+        if !self.ranked {
+            return std::mem::take(&mut self.matches);
+        }
+
+        // the last batch always has them all
+        if !std::mem::take(&mut self.changed) && !self.done {
+            return Vec::new();
+        }
+        self.matches.clone()
+    }
+}
+
+/// New matches since the previous poll of a grep
+#[derive(Debug)]
+pub struct GrepBatch {
+    pub generation: u64,
+    files: FileSnapshot,
+    matches: Vec<FileMatch>,
+    /// Matches of the whole grep so far, the polled ones included
+    pub total_matched: usize,
+    pub files_searched: usize,
+    /// Whether the grep stopped at `max_results`, a ranked one dropped the matches past it
+    pub truncated: bool,
+    /// The grep is over, no batch follows this one
+    pub done: bool,
+    /// A fuzzy grep: the matches of a batch are the best ones so far and replace the matches of
+    /// the previous batches, empty when they didn't change before the last batch
+    pub ranked: bool,
+}
+
+impl GrepBatch {
+    /// In the order the workers found them, the lines of a file are in order. Ranked matches are
+    /// sorted by the score, the highest first.
+    pub fn matches(&self) -> impl Iterator<Item = GrepMatch<'_>> {
+        self.matches.iter().map(|found| GrepMatch {
+            file: &self.files[found.file_index],
+            line: found.line,
+            text: found.text.clone(),
+            spans: found.spans.clone(),
+            score: found.score,
+        })
+    }
+}
+
+/// Greps the file contents off the Neovim main thread, the matches are pushed to a buffer in
+/// batches as the workers find them. Every grep gets a new generation, starting a grep cancels
+/// the running one: its workers stop at the next file and its batches are never delivered.
+pub struct GrepSession {
+    pool: rayon::ThreadPool,
+    latest_generation: Arc<AtomicU64>,
+    buffer: Arc<Mutex<Option<GrepBuffer>>>,
+}
+
+impl GrepSession {
+    pub fn new() -> Result<Self, Error> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(std::thread::available_parallelism().map_or(4, usize::from))
+            .thread_name(|index| format!("fff-grep-{}", index))
+            .build()?;
+
+        Ok(Self {
+            pool,
+            latest_generation: Arc::new(AtomicU64::new(0)),
+            buffer: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Starts grepping the files for the pattern, an invalid pattern doesn't cancel the
+    /// running grep. Only the files flagged in `candidates` are read.
+    pub fn start(
+        &self,
+        files: FileSnapshot,
+        pattern: &str,
+        options: GrepOptions,
+        candidates: Option<Vec<bool>>,
+    ) -> Result<u64, Error> {
+        let matcher = options.matcher(pattern)?;
+        let generation = self.latest_generation.fetch_add(1, Ordering::AcqRel) + 1;
+        *self.buffer.lock().map_err(|_| Error::AcquireItemLock)? = Some(GrepBuffer {
+            generation,
+            files: FileSnapshot::clone(&files),
+            matches: Vec::new(),
+            max_results: options.max_results,
+            total_matched: 0,
+            files_searched: 0,
+            truncated: false,
+            // an empty pattern matches every line, nothing is grepped for it
+            done: pattern.is_empty(),
+            ranked: options.mode == GrepMode::Fuzzy,
+            changed: false,
+        });
+        if pattern.is_empty() {
+            return Ok(generation);
+        }
+
+        let latest_generation = Arc::clone(&self.latest_generation);
+        let buffer = Arc::clone(&self.buffer);
+        let pattern = pattern.to_string();
+        self.pool.spawn(move || {
+            let time = std::time::Instant::now();
+            let is_cancelled = || latest_generation.load(Ordering::Acquire) != generation;
+            let stopped = AtomicBool::new(false);
+
+            files
+                .par_chunks(BATCH_FILES)
+                .enumerate()
+                .for_each(|(chunk_index, chunk)| {
+                    let mut matches = Vec::new();
+                    let mut files_searched = 0;
+                    for (offset, file) in chunk.iter().enumerate() {
+                        let file_index = chunk_index * BATCH_FILES + offset;
+                        if candidates
+                            .as_ref()
+                            .is_some_and(|candidates| !candidates[file_index])
+                        {
+                            continue;
+                        }
+                        if stopped.load(Ordering::Relaxed) || is_cancelled() {
+                            return;
+                        }
+
+                        let Some(file_matches) = grep_file(file, &matcher, &options) else {
+                            continue;
+                        };
+                        files_searched += 1;
+                        matches.extend(file_matches.into_iter().map(|found| FileMatch {
+                            file_index,
+                            line: found.line,
+                            text: found.text,
+                            spans: found.spans,
+                            score: found.score,
+                        }));
+                    }
+
+                    if !update_buffer(&buffer, generation, |buffer| {
+                        buffer.push(matches, files_searched)
+                    }) {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                });
+
+            update_buffer(&buffer, generation, |buffer| buffer.done = true);
+            debug!(?pattern, generation, cancelled = is_cancelled(), completed_in = ?time.elapsed(), "Grep session completed");
+        });
+
+        Ok(generation)
+    }
+
+    /// Stops the running grep, polling it returns `None` from now on
+    pub fn cancel(&self) {
+        self.latest_generation.fetch_add(1, Ordering::AcqRel);
+        if let Ok(mut buffer) = self.buffer.lock() {
+            *buffer = None;
+        }
+    }
+
+    /// Takes the matches pushed since the previous poll, empty while the workers are busy.
+    /// Returns `None` once the grep was cancelled or superseded by a newer one.
+    pub fn poll(&self, generation: u64) -> Option<GrepBatch> {
+        let mut buffer = self.buffer.lock().ok()?;
+        let buffer = buffer
+            .as_mut()
+            .filter(|buffer| buffer.generation == generation)?;
+
+        Some(GrepBatch {
+            generation,
+            files: FileSnapshot::clone(&buffer.files),
+            matches: buffer.take_matches(),
+            total_matched: buffer.total_matched,
+            files_searched: buffer.files_searched,
+            truncated: buffer.truncated,
+            done: buffer.done,
+            ranked: buffer.ranked,
+        })
+    }
+}
+
+/// Updates the buffer when it still belongs to the grep of the generation, returns whether
+/// the grep goes on
+fn update_buffer(
+    buffer: &Mutex<Option<GrepBuffer>>,
+    generation: u64,
+    update: impl FnOnce(&mut GrepBuffer),
+) -> bool {
+    let Ok(mut buffer) = buffer.lock() else {
+        return false;
+    };
+    match buffer.as_mut() {
+        Some(buffer) if buffer.generation == generation => {
+            update(buffer);
+            !buffer.is_stopped()
+        }
+        _ => false,
+    }
+}
+
+impl IntoLua for GrepBatch {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("generation", self.generation)?;
+        table.set("matches", lua.create_sequence_from(self.matches())?)?;
+        table.set("total_matched", self.total_matched)?;
+        table.set("files_searched", self.files_searched)?;
+        table.set("truncated", self.truncated)?;
+        table.set("done", self.done)?;
+        table.set("ranked", self.ranked)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FileItem;
+    use std::time::{Duration, Instant};
+
+    /// Every batch of the grep until the last one
+    fn poll_until_done(session: &GrepSession, generation: u64) -> Vec<GrepBatch> {
+        let start = Instant::now();
+        let mut batches = Vec::new();
+        while start.elapsed() < Duration::from_secs(5) {
+            let batch = session.poll(generation).unwrap();
+            let done = batch.done;
+            batches.push(batch);
+            if done {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        batches
+    }
+
+    #[test]
+    fn test_streamed_matches_are_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<Arc<FileItem>> = (0..300)
+            .map(|index| {
+                let relative_path = format!("file_{index:03}.txt");
+                let path = dir.path().join(&relative_path);
+                std::fs::write(&path, "needle\nhay\nneedle needle\nneedle\n").unwrap();
+                let mut file = FileItem::with_relative_path(path, relative_path, None);
+                file.size = 32;
+                Arc::new(file)
+            })
+            .collect();
+        let files = FileSnapshot::new(files);
+        let session = GrepSession::new().unwrap();
+
+        let options = GrepOptions {
+            max_results: 250,
+            max_matches_per_file: 2,
+            ..Default::default()
+        };
+        let cancelled = session
+            .start(FileSnapshot::clone(&files), "needle", options.clone(), None)
+            .unwrap();
+        let generation = session
+            .start(FileSnapshot::clone(&files), "needle", options, None)
+            .unwrap();
+        assert!(session.poll(cancelled).is_none());
+
+        let batches = poll_until_done(&session, generation);
+        let last = batches.last().unwrap();
+        assert!(last.done && last.truncated);
+        assert_eq!(last.total_matched, 250);
+
+        let mut lines_per_file = std::collections::HashMap::new();
+        for batch in &batches {
+            for found in batch.matches() {
+                *lines_per_file
+                    .entry(found.file.relative_path.clone())
+                    .or_insert(0) += 1;
+                // the third line is the second match of the file
+                assert!(matches!(found.line, 1 | 3));
+            }
+        }
+        assert_eq!(lines_per_file.values().sum::<usize>(), 250);
+        assert!(lines_per_file.values().all(|&lines| lines <= 2));
+
+        session.cancel();
+        assert!(session.poll(generation).is_none());
+        let regex = GrepOptions {
+            mode: crate::grep::GrepMode::Regex,
+            ..Default::default()
+        };
+        assert!(session.start(files, "(", regex, None).is_err());
+    }
+
+    #[test]
+    fn test_fuzzy_grep_keeps_the_best_matches_of_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<Arc<FileItem>> = (0..300)
+            .map(|index| {
+                let relative_path = format!("file_{index:03}.txt");
+                let path = dir.path().join(&relative_path);
+                // the exact lines are in the files read last
+                let content = if index >= 295 {
+                    "let user_count = 1;\n"
+                } else {
+                    "let user_session_count = 1;\n"
+                };
+                std::fs::write(&path, content).unwrap();
+                Arc::new(FileItem::with_relative_path(path, relative_path, None))
+            })
+            .collect();
+        let session = GrepSession::new().unwrap();
+
+        let options = GrepOptions {
+            mode: GrepMode::Fuzzy,
+            max_results: 5,
+            ..Default::default()
+        };
+        let generation = session
+            .start(FileSnapshot::new(files), "user_count", options, None)
+            .unwrap();
+        let batches = poll_until_done(&session, generation);
+        let last = batches.last().unwrap();
+        assert!(last.done && last.truncated && last.ranked);
+        assert_eq!((last.total_matched, last.files_searched), (300, 300));
+
+        let mut paths: Vec<String> = last
+            .matches()
+            .map(|found| found.file.relative_path.clone())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            (295..300)
+                .map(|index| format!("file_{index:03}.txt"))
+                .collect::<Vec<_>>()
+        );
+    }
+}
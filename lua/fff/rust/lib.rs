@@ -1,22 +1,48 @@
+use crate::async_search::{AsyncSearch, SearchRequest};
 use crate::error::Error;
-use crate::file_picker::FilePicker;
+use crate::explain::explain_score;
+use crate::file_picker::{FilePicker, FileSnapshot, SharedPicker};
 use crate::frecency::FrecencyTracker;
+use crate::grep::GrepOptions;
+use crate::grep_session::GrepSession;
+use crate::location::Location;
+use crate::path_utils::resolve_project_path;
+use crate::picker_registry::PickerRegistry;
+use crate::ranked_results::RankedResults;
+use crate::related_files::{RelatedMatcher, RelatedRules, related_files};
+use crate::scan_config::ScanConfig;
+use crate::search_cache::IndexedPaths;
+use crate::types::{FileSource, SearchOptions};
 use mlua::prelude::*;
-use once_cell::sync::Lazy;
-use std::path::PathBuf;
-use std::sync::RwLock;
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
+pub mod async_search;
 mod background_watcher;
+pub mod dir_index;
 mod error;
+pub mod explain;
 pub mod file_picker;
 mod frecency;
 pub mod git;
+pub mod grep;
+pub mod grep_session;
 mod location;
+mod location_resolver;
 mod path_utils;
+pub mod picker_registry;
+pub mod ranked_results;
+pub mod recent_files;
+pub mod related_files;
+pub mod scan_config;
 pub mod score;
+mod search_cache;
 mod sort_buffer;
 mod tracing;
+pub mod trigram_index;
 pub mod types;
 use mimalloc::MiMalloc;
 
@@ -24,7 +50,21 @@ use mimalloc::MiMalloc;
 static GLOBAL: MiMalloc = MiMalloc;
 
 pub static FRECENCY: Lazy<RwLock<Option<FrecencyTracker>>> = Lazy::new(|| RwLock::new(None));
-pub static FILE_PICKER: Lazy<RwLock<Option<FilePicker>>> = Lazy::new(|| RwLock::new(None));
+static ASYNC_SEARCH: OnceCell<AsyncSearch> = OnceCell::new();
+static GREP_SESSION: OnceCell<GrepSession> = OnceCell::new();
+/// Every match of the last search delivered to Lua with its request, paged with `get_results`
+static LAST_RESULTS: Lazy<Mutex<Option<(SearchRequest, RankedResults)>>> =
+    Lazy::new(|| Mutex::new(None));
+/// Files of the frecency database with their paths, loaded by the first recent files search
+/// after an access
+static RECENT_FILES: Lazy<Mutex<Option<RecentFiles>>> = Lazy::new(|| Mutex::new(None));
+/// Co-access scores of the files following the current file by its absolute path, the same
+/// file is searched on every keystroke
+static CO_ACCESS: Lazy<Mutex<Option<(PathBuf, CoAccessScores)>>> = Lazy::new(|| Mutex::new(None));
+/// Naming rules of the related files, shared by every picker
+static RELATED_RULES: Lazy<RwLock<Arc<RelatedRules>>> = Lazy::new(RwLock::default);
+pub static FILE_PICKERS: Lazy<RwLock<PickerRegistry>> =
+    Lazy::new(|| RwLock::new(PickerRegistry::default()));
 
 pub fn init_db(_: &Lua, (db_path, use_unsafe_no_lock): (String, bool)) -> LuaResult<bool> {
     let mut frecency = FRECENCY.write().map_err(|_| Error::AcquireFrecencyLock)?;
@@ -32,40 +72,87 @@ pub fn init_db(_: &Lua, (db_path, use_unsafe_no_lock): (String, bool)) -> LuaRes
         return Ok(false);
     }
     *frecency = Some(FrecencyTracker::new(&db_path, use_unsafe_no_lock)?);
+    invalidate_access_caches()?;
     Ok(true)
 }
 
 pub fn destroy_db(_: &Lua, _: ()) -> LuaResult<bool> {
     let mut frecency = FRECENCY.write().map_err(|_| Error::AcquireFrecencyLock)?;
     *frecency = None;
+    invalidate_access_caches()?;
     Ok(true)
 }
 
-pub fn init_file_picker(_: &Lua, base_path: String) -> LuaResult<bool> {
-    let mut file_picker = FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)?;
-    if file_picker.is_some() {
-        return Ok(false);
+type CoAccessScores = Arc<HashMap<PathBuf, i64>>;
+type RecentFiles = (FileSnapshot, Arc<IndexedPaths>);
+
+fn invalidate_access_caches() -> Result<(), Error> {
+    *RECENT_FILES.lock().map_err(|_| Error::AcquireItemLock)? = None;
+    *CO_ACCESS.lock().map_err(|_| Error::AcquireItemLock)? = None;
+    Ok(())
+}
+
+/// Files usually opened after the current file, `None` without the frecency database. Lua
+/// passes the current file relative to the base path while the accesses are tracked by the
+/// real path of the file.
+fn co_access_scores(
+    picker: &SharedPicker,
+    current_file: &str,
+) -> Result<Option<CoAccessScores>, Error> {
+    let current_file = picker
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .base_path()
+        .join(current_file);
+    if let Some((cached_file, scores)) = CO_ACCESS
+        .lock()
+        .map_err(|_| Error::AcquireItemLock)?
+        .as_ref()
+        && *cached_file == current_file
+    {
+        return Ok(Some(Arc::clone(scores)));
     }
 
-    let picker = FilePicker::new(base_path)?;
-    *file_picker = Some(picker);
-    Ok(true)
+    // never hold both locks, accesses take them in the other order to invalidate the cache
+    let scores = {
+        let Some(ref frecency) = *FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)? else {
+            return Ok(None);
+        };
+        let real_path = current_file.canonicalize();
+        Arc::new(frecency.co_access_scores(real_path.as_deref().unwrap_or(&current_file))?)
+    };
+    *CO_ACCESS.lock().map_err(|_| Error::AcquireItemLock)? =
+        Some((current_file, Arc::clone(&scores)));
+    Ok(Some(scores))
 }
 
-fn reinit_file_picker_internal(path: std::path::PathBuf) -> Result<(), Error> {
-    let mut file_picker = FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)?;
+/// Picker of the handle (its base path) or the most recently activated one
+fn get_picker(handle: Option<&str>) -> Result<SharedPicker, Error> {
+    FILE_PICKERS
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .get(handle)
+        .ok_or(Error::FilePickerMissing)
+}
 
-    // drop should clean it anyway but just to be extra sure
-    if let Some(mut picker) = file_picker.take() {
-        picker.stop_background_monitor();
+pub fn init_file_picker(
+    _: &Lua,
+    (base_path, scan_config, max_pickers): (String, ScanConfig, Option<usize>),
+) -> LuaResult<bool> {
+    let mut registry = FILE_PICKERS.write().map_err(|_| Error::AcquireItemLock)?;
+    if let Some(max_pickers) = max_pickers {
+        registry.set_max_pickers(max_pickers);
+    }
+    if !registry.is_empty() {
+        return Ok(false);
     }
 
-    let new_picker = FilePicker::new(path.to_string_lossy().to_string())?;
-    *file_picker = Some(new_picker);
-
-    Ok(())
+    registry.activate(Path::new(&base_path), scan_config)?;
+    Ok(true)
 }
 
+/// Switches to the picker of the path keeping the previous one warm, the new picker reuses
+/// the indexing config of the active one.
 pub fn restart_index_in_path(_: &Lua, new_path: String) -> LuaResult<bool> {
     let path = std::path::PathBuf::from(&new_path);
     if !path.exists() {
@@ -79,141 +166,589 @@ pub fn restart_index_in_path(_: &Lua, new_path: String) -> LuaResult<bool> {
         LuaError::RuntimeError(format!("Failed to canonicalize path '{}': {}", new_path, e))
     })?;
 
-    reinit_file_picker_internal(canonical_path)?;
+    let scan_config = match get_picker(None) {
+        Ok(picker) => picker
+            .read()
+            .map_err(|_| Error::AcquireItemLock)?
+            .scan_config()
+            .clone(),
+        Err(_) => ScanConfig::default(),
+    };
+
+    let mut registry = FILE_PICKERS.write().map_err(|_| Error::AcquireItemLock)?;
+    registry.activate(&canonical_path, scan_config)?;
     Ok(true)
 }
 
-pub fn scan_files(_: &Lua, _: ()) -> LuaResult<()> {
-    let mut file_picker = FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)?;
-    let picker = file_picker
-        .as_mut()
-        .ok_or_else(|| Error::FilePickerMissing)?;
+pub fn list_pickers(_: &Lua, _: ()) -> LuaResult<Vec<String>> {
+    let registry = FILE_PICKERS.read().map_err(|_| Error::AcquireItemLock)?;
+    Ok(registry
+        .handles()
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
 
-    picker.trigger_rescan()?;
+pub fn scan_files(_: &Lua, handle: Option<String>) -> LuaResult<()> {
+    let picker = get_picker(handle.as_deref())?;
+    FilePicker::trigger_rescan_shared(&picker)?;
     ::tracing::info!("scan_files trigger_rescan completed");
     Ok(())
 }
 
-pub fn fuzzy_search_files(
+type SearchArgs = (
+    String,
+    usize,
+    usize,
+    Option<String>,
+    bool,
+    Option<bool>,
+    Option<String>,
+    Option<Vec<String>>,
+);
+
+fn search_request(
+    (
+        query,
+        max_results,
+        max_threads,
+        current_file,
+        reverse_order,
+        include_ignored,
+        handle,
+        pushed_sources,
+    ): SearchArgs,
+) -> Result<SearchRequest, Error> {
+    let pushed_sources = pushed_sources
+        .unwrap_or_default()
+        .iter()
+        .map(|source| source.parse())
+        .collect::<Result<Vec<FileSource>, Error>>()?;
+
+    let picker = get_picker(handle.as_deref())?;
+    let co_access = match current_file.as_deref() {
+        Some(current_file) => co_access_scores(&picker, current_file)?,
+        None => None,
+    };
+
+    Ok(SearchRequest {
+        picker,
+        query,
+        max_results,
+        max_threads,
+        current_file,
+        reverse_order,
+        include_ignored: include_ignored.unwrap_or(false),
+        pushed_sources,
+        related_rules: related_rules()?,
+        co_access,
+    })
+}
+
+/// Returns the first page of the results and keeps the rest for `get_results`
+fn deliver_results(
+    lua: &Lua,
+    request: SearchRequest,
+    mut results: RankedResults,
+) -> LuaResult<LuaValue> {
+    if !results.hold_snapshots() {
+        results = request.run()?;
+    }
+    let page = results.page(0, request.max_results).into_lua(lua)?;
+    results.release_snapshots();
+    *LAST_RESULTS.lock().map_err(|_| Error::AcquireItemLock)? = Some((request, results));
+    Ok(page)
+}
+
+pub fn fuzzy_search_files(lua: &Lua, args: SearchArgs) -> LuaResult<LuaValue> {
+    let request = search_request(args)?;
+    let results = request.run()?;
+    deliver_results(lua, request, results)
+}
+
+/// Same arguments as `fuzzy_search_files` but returns the search generation right away,
+/// the result is picked up with `poll_search_result`.
+pub fn fuzzy_search_files_async(_: &Lua, args: SearchArgs) -> LuaResult<u64> {
+    let request = search_request(args)?;
+    let async_search = ASYNC_SEARCH.get_or_try_init(AsyncSearch::new)?;
+    Ok(async_search.submit(request))
+}
+
+/// Returns nil until the search of the generation completes, the result table also contains
+/// the `generation`. Superseded searches never complete.
+///
+/// Takes no picker handle: the generations are shared by the searches of every picker, the
+/// search already named its picker when it was submitted.
+pub fn poll_search_result(lua: &Lua, generation: u64) -> LuaResult<LuaValue> {
+    let Some(async_search) = ASYNC_SEARCH.get() else {
+        return Ok(LuaValue::Nil);
+    };
+    let Some(completed) = async_search.take_result(generation) else {
+        return Ok(LuaValue::Nil);
+    };
+
+    let table = deliver_results(lua, completed.request, completed.result?)?;
+    if let LuaValue::Table(ref table) = table {
+        table.set("generation", completed.generation)?;
+    }
+    Ok(table)
+}
+
+/// Replaces the open buffers (`source` "buffers") or the oldfiles ("oldfiles") searched along
+/// with the index, returns the number of files kept
+pub fn set_source_files(
+    _: &Lua,
+    (source, paths, handle): (String, Vec<String>, Option<String>),
+) -> LuaResult<usize> {
+    let source: FileSource = source.parse()?;
+    if !source.is_pushed() {
+        return Err(Error::InvalidFileSource(source.as_str().to_string()).into());
+    }
+
+    let picker = get_picker(handle.as_deref())?;
+    let (base_path, roots, scanned) = {
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        let mut scanned = vec![picker.files_snapshot()];
+        scanned.extend(picker.ignored_files_snapshot());
+        (
+            picker.base_path().to_path_buf(),
+            picker.roots().to_vec(),
+            scanned,
+        )
+    };
+
+    // the metadata of every file is read without holding the picker lock
+    let files = {
+        let frecency = FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)?;
+        let paths = paths.into_iter().map(PathBuf::from).collect();
+        FilePicker::pushed_files(&base_path, &roots, &scanned, paths, frecency.as_ref())
+    };
+    let files_count = files.len();
+
+    picker
+        .write()
+        .map_err(|_| Error::AcquireItemLock)?
+        .set_source_files(source, files);
+    Ok(files_count)
+}
+
+/// Searches the files opened in any project, ranked by their frecency. Returns an empty result
+/// without the frecency database.
+pub fn fuzzy_search_recent(
     lua: &Lua,
-    (query, max_results, max_threads, current_file, order_reverse): (
+    (query, max_results, max_threads, reverse_order): (String, usize, usize, bool),
+) -> LuaResult<LuaValue> {
+    let (files, indexed_paths) = {
+        let mut recent_files = RECENT_FILES.lock().map_err(|_| Error::AcquireItemLock)?;
+        match recent_files.as_ref() {
+            Some(recent_files) => recent_files.clone(),
+            None => {
+                let frecency = FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)?;
+                let files = match frecency.as_ref() {
+                    Some(tracker) => recent_files::load_recent_files(tracker)?,
+                    None => Vec::new(),
+                };
+                let indexed_paths = Arc::new(IndexedPaths::new(&[&files]));
+                recent_files
+                    .insert((FileSnapshot::new(files), indexed_paths))
+                    .clone()
+            }
+        }
+    };
+
+    recent_files::fuzzy_search_recent(
+        &files,
+        &indexed_paths,
+        &query,
+        max_results,
+        max_threads,
+        reverse_order,
+    )
+    .into_lua(lua)
+}
+
+pub fn fuzzy_search_dirs(
+    lua: &Lua,
+    (query, max_results, max_threads, reverse_order, handle): (
         String,
         usize,
         usize,
-        Option<String>,
         bool,
+        Option<String>,
     ),
 ) -> LuaResult<LuaValue> {
-    let Some(ref mut picker) = *FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)? else {
-        return Err(Error::FilePickerMissing)?;
+    let picker = get_picker(handle.as_deref())?;
+    let (dir_index, files) = {
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        (picker.dir_index(), picker.files_snapshot())
     };
 
-    let results = FilePicker::fuzzy_search(
-        picker.get_files(),
-        &query,
-        max_results,
-        max_threads,
-        current_file.as_deref(),
-        order_reverse,
-    );
+    let dirs = dir_index.dirs(&files);
+    FilePicker::fuzzy_search_dirs(&dirs, &query, max_results, max_threads, reverse_order)
+        .into_lua(lua)
+}
 
-    results.into_lua(lua)
+/// Page of the last search results ranked `offset..offset + count` (0-based), returns nil
+/// before the first search. The search runs again if the index changed since then.
+///
+/// Takes no picker handle: the pages belong to the last delivered search of any picker, the
+/// search already named its picker.
+pub fn get_results(lua: &Lua, (offset, count): (usize, usize)) -> LuaResult<LuaValue> {
+    let mut last_results = LAST_RESULTS.lock().map_err(|_| Error::AcquireItemLock)?;
+    let Some((request, results)) = last_results.as_mut() else {
+        return Ok(LuaValue::Nil);
+    };
+
+    // the index changed since the search, the kept positions don't point to the matches
+    if !results.hold_snapshots() {
+        *results = request.run()?;
+    }
+    let page = results.page(offset, count).into_lua(lua);
+    results.release_snapshots();
+    page
+}
+
+/// Score breakdown of a single file for the query, nil if the file is not indexed
+pub fn explain(
+    lua: &Lua,
+    (query, path, current_file, include_ignored, handle): (
+        String,
+        String,
+        Option<String>,
+        Option<bool>,
+        Option<String>,
+    ),
+) -> LuaResult<LuaValue> {
+    let picker = get_picker(handle.as_deref())?;
+    let co_access = match current_file.as_deref() {
+        Some(current_file) => co_access_scores(&picker, current_file)?,
+        None => None,
+    };
+    let (sources, search_cache, relative_path, current_file, current_root) = {
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        let mut sources = vec![picker.files_snapshot()];
+        if include_ignored.unwrap_or(false) {
+            sources.extend(picker.ignored_files_snapshot());
+        }
+
+        let (relative_path, _) = picker.resolve_current_file(&path);
+        let (current_file, current_root) = match current_file.as_deref() {
+            Some(current_file) => {
+                let (current_file, current_root) = picker.resolve_current_file(current_file);
+                (Some(current_file), current_root.map(str::to_owned))
+            }
+            None => (None, None),
+        };
+        (
+            sources,
+            picker.search_cache(),
+            relative_path,
+            current_file,
+            current_root,
+        )
+    };
+
+    let related_rules = related_rules()?;
+    let related = current_file
+        .as_deref()
+        .and_then(|current_file| RelatedMatcher::new(&related_rules, current_file));
+    let options = SearchOptions {
+        max_threads: std::thread::available_parallelism().map_or(1, usize::from),
+        current_file: current_file.as_deref(),
+        current_root: current_root.as_deref(),
+        related: related.as_ref(),
+        co_access: co_access.as_deref(),
+        ..Default::default()
+    };
+    let frecency = FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)?;
+    let sources: Vec<_> = sources.iter().collect();
+    explain_score(
+        &sources,
+        &search_cache.indexed_paths(&sources),
+        &relative_path,
+        &query,
+        &options,
+        frecency.as_ref(),
+    )
+    .into_lua(lua)
 }
 
 pub fn track_access(_: &Lua, file_path: String) -> LuaResult<bool> {
     let file_path = PathBuf::from(&file_path);
 
     // Track access in frecency DB (expensive LMDB write, ~100-200ms)
-    // Do this WITHOUT holding FILE_PICKER lock to avoid blocking searches
+    // Do this WITHOUT holding the picker locks to avoid blocking searches
     let Some(ref frecency) = *FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)? else {
         return Ok(false);
     };
     frecency.track_access(file_path.as_path())?;
+    invalidate_access_caches()?;
+
+    // the frecency is shared, every warm index containing the file has to see the new score
+    let pickers: Vec<SharedPicker> = FILE_PICKERS
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .pickers()
+        .cloned()
+        .collect();
+    if pickers.is_empty() {
+        Err(Error::FilePickerMissing)?;
+    }
 
-    // Quick lock to update single file's frecency score in picker
-    let Some(ref mut picker) = *FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)? else {
-        return Err(Error::FilePickerMissing)?;
-    };
-    picker.update_single_file_frecency(&file_path, frecency)?;
+    for picker in pickers {
+        // the score is updated in place, searches keep running meanwhile
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        picker.update_single_file_frecency(&file_path, frecency)?;
+    }
 
     Ok(true)
 }
 
-pub fn get_scan_progress(lua: &Lua, _: ()) -> LuaResult<LuaValue> {
-    let file_picker = FILE_PICKER.read().map_err(|_| Error::AcquireItemLock)?;
-    let picker = file_picker
+/// Pins are kept per project: the base path of the picker. The path is absolute or relative
+/// to it.
+fn update_pin(path: String, handle: Option<String>, pin: bool) -> Result<bool, Error> {
+    let picker = get_picker(handle.as_deref())?;
+    let base_path = picker
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .base_path()
+        .to_path_buf();
+    let path = resolve_project_path(&base_path, Path::new(&path));
+
+    let Some(ref frecency) = *FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)? else {
+        return Ok(false);
+    };
+    let updated = if pin {
+        frecency.add_pin(&base_path, &path)?
+    } else {
+        frecency.remove_pin(&base_path, &path)?
+    };
+
+    picker
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .update_single_file_frecency(&path, frecency)?;
+
+    Ok(updated)
+}
+
+/// Returns false when the file was already pinned or there is no frecency database
+pub fn add_pin(_: &Lua, (path, handle): (String, Option<String>)) -> LuaResult<bool> {
+    Ok(update_pin(path, handle, true)?)
+}
+
+/// Returns false when the file was not pinned
+pub fn remove_pin(_: &Lua, (path, handle): (String, Option<String>)) -> LuaResult<bool> {
+    Ok(update_pin(path, handle, false)?)
+}
+
+/// Absolute paths of the files pinned in the project of the picker, in the order they were
+/// pinned
+pub fn list_pins(_: &Lua, handle: Option<String>) -> LuaResult<Vec<String>> {
+    let picker = get_picker(handle.as_deref())?;
+    let base_path = picker
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .base_path()
+        .to_path_buf();
+
+    let Some(ref frecency) = *FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)? else {
+        return Ok(Vec::new());
+    };
+    Ok(frecency
+        .pins(&base_path)?
+        .into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+fn related_rules() -> Result<Arc<RelatedRules>, Error> {
+    Ok(Arc::clone(
+        &*RELATED_RULES.read().map_err(|_| Error::AcquireItemLock)?,
+    ))
+}
+
+/// Replaces the related files rules, nil restores the defaults
+pub fn set_related_rules(_: &Lua, rules: RelatedRules) -> LuaResult<()> {
+    *RELATED_RULES.write().map_err(|_| Error::AcquireItemLock)? = Arc::new(rules);
+    Ok(())
+}
+
+/// Indexed files related to the current file (tests, headers, styles), the closest first
+pub fn get_related_files(
+    lua: &Lua,
+    (current_file, handle): (String, Option<String>),
+) -> LuaResult<LuaTable> {
+    let rules = related_rules()?;
+    let picker = get_picker(handle.as_deref())?;
+    let (files, current_file) = {
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        let (current_file, _) = picker.resolve_current_file(&current_file);
+        (picker.files_snapshot(), current_file)
+    };
+
+    lua.create_sequence_from(related_files(&files, &current_file, &rules))
+}
+
+/// Line of the symbol or the pattern location in the file, nil when nothing matches
+pub fn resolve_location(
+    _: &Lua,
+    (path, location): (String, Location),
+) -> LuaResult<Option<Location>> {
+    Ok(location_resolver::resolve_location(
+        Path::new(&path),
+        &location,
+    )?)
+}
+
+/// Files of the picker and which of them the content index can't rule out. Every file is
+/// grepped while the index is being built or updated, the grep never waits for it.
+fn grep_candidates(
+    handle: Option<&str>,
+    pattern: &str,
+    options: &GrepOptions,
+) -> Result<(FileSnapshot, Option<Vec<bool>>), Error> {
+    let picker = get_picker(handle)?;
+    let (files, index) = {
+        let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
+        (picker.files_snapshot(), picker.content_index())
+    };
+
+    let candidates = index
         .as_ref()
-        .ok_or_else(|| Error::FilePickerMissing)?;
-    let progress = picker.get_scan_progress();
+        .and_then(|index| index.try_read().ok())
+        .and_then(|index| index.file_filter(&files, pattern, options));
+    Ok((files, candidates))
+}
+
+/// Lines of the indexed files matching the pattern, in the order of the index
+pub fn grep_files(
+    lua: &Lua,
+    (pattern, options, handle): (String, GrepOptions, Option<String>),
+) -> LuaResult<LuaValue> {
+    let (files, candidates) = grep_candidates(handle.as_deref(), &pattern, &options)?;
+    grep::grep_files(&files, &pattern, &options, candidates.as_deref())?.into_lua(lua)
+}
+
+/// Same arguments as `grep_files` but returns the grep generation right away and cancels the
+/// running grep, the matches are picked up in batches with `poll_grep`.
+pub fn grep_files_async(
+    _: &Lua,
+    (pattern, options, handle): (String, GrepOptions, Option<String>),
+) -> LuaResult<u64> {
+    let (files, candidates) = grep_candidates(handle.as_deref(), &pattern, &options)?;
+    let session = GREP_SESSION.get_or_try_init(GrepSession::new)?;
+    Ok(session.start(files, &pattern, options, candidates)?)
+}
+
+/// Matches found since the previous poll of the grep, with `done` set by the last batch. A
+/// fuzzy grep is `ranked`: every batch holds the best matches so far instead.
+/// Returns nil once the grep was cancelled or superseded.
+pub fn poll_grep(lua: &Lua, generation: u64) -> LuaResult<LuaValue> {
+    match GREP_SESSION
+        .get()
+        .and_then(|session| session.poll(generation))
+    {
+        Some(batch) => batch.into_lua(lua),
+        None => Ok(LuaValue::Nil),
+    }
+}
+
+pub fn cancel_grep(_: &Lua, _: ()) -> LuaResult<()> {
+    if let Some(session) = GREP_SESSION.get() {
+        session.cancel();
+    }
+    Ok(())
+}
+
+pub fn get_scan_progress(lua: &Lua, handle: Option<String>) -> LuaResult<LuaValue> {
+    let picker = get_picker(handle.as_deref())?;
+    let progress = picker
+        .read()
+        .map_err(|_| Error::AcquireItemLock)?
+        .get_scan_progress();
 
     let table = lua.create_table()?;
     table.set("scanned_files_count", progress.scanned_files_count)?;
     table.set("is_scanning", progress.is_scanning)?;
+    table.set("is_scanning_ignored", progress.is_scanning_ignored)?;
     Ok(LuaValue::Table(table))
 }
 
-pub fn is_scanning(_: &Lua, _: ()) -> LuaResult<bool> {
-    let file_picker = FILE_PICKER.read().map_err(|_| Error::AcquireItemLock)?;
-    let picker = file_picker
-        .as_ref()
-        .ok_or_else(|| Error::FilePickerMissing)?;
+pub fn is_scanning(_: &Lua, handle: Option<String>) -> LuaResult<bool> {
+    let picker = get_picker(handle.as_deref())?;
+    let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
     Ok(picker.is_scan_active())
 }
 
-pub fn refresh_git_status(_: &Lua, _: ()) -> LuaResult<usize> {
-    FilePicker::refresh_git_status_global().map_err(Into::into)
+pub fn refresh_git_status(_: &Lua, handle: Option<String>) -> LuaResult<usize> {
+    let picker = get_picker(handle.as_deref())?;
+    FilePicker::refresh_git_status_shared(&picker).map_err(Into::into)
 }
 
-pub fn update_single_file_frecency(_: &Lua, file_path: String) -> LuaResult<bool> {
+pub fn update_single_file_frecency(
+    _: &Lua,
+    (file_path, handle): (String, Option<String>),
+) -> LuaResult<bool> {
     let Some(ref frecency) = *FRECENCY.read().map_err(|_| Error::AcquireFrecencyLock)? else {
         return Ok(false);
     };
-    let Some(ref mut picker) = *FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)? else {
-        return Err(Error::FilePickerMissing)?;
-    };
+    let picker = get_picker(handle.as_deref())?;
+    let picker = picker.read().map_err(|_| Error::AcquireItemLock)?;
 
     picker.update_single_file_frecency(&file_path, frecency)?;
     Ok(true)
 }
 
-pub fn stop_background_monitor(_: &Lua, _: ()) -> LuaResult<bool> {
-    let Some(ref mut picker) = *FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)? else {
-        return Err(Error::FilePickerMissing)?;
-    };
+pub fn stop_background_monitor(_: &Lua, handle: Option<String>) -> LuaResult<bool> {
+    let picker = get_picker(handle.as_deref())?;
+    let mut picker = picker.write().map_err(|_| Error::AcquireItemLock)?;
 
     picker.stop_background_monitor();
 
     Ok(true)
 }
 
-pub fn cleanup_file_picker(_: &Lua, _: ()) -> LuaResult<bool> {
-    let mut file_picker = FILE_PICKER.write().map_err(|_| Error::AcquireItemLock)?;
-    if let Some(picker) = file_picker.take() {
-        drop(picker);
-        ::tracing::info!("FilePicker cleanup completed");
+/// Drops the picker of the handle or all of them when called without one
+pub fn cleanup_file_picker(_: &Lua, handle: Option<String>) -> LuaResult<bool> {
+    let mut registry = FILE_PICKERS.write().map_err(|_| Error::AcquireItemLock)?;
+    let removed = match handle {
+        Some(handle) => registry.remove(Some(&handle)).is_some(),
+        None => registry.clear() > 0,
+    };
 
-        Ok(true)
-    } else {
-        Ok(false)
+    if removed {
+        // the request of the results keeps the dropped picker alive
+        *LAST_RESULTS.lock().map_err(|_| Error::AcquireItemLock)? = None;
+        ::tracing::info!("FilePicker cleanup completed");
     }
+    Ok(removed)
 }
 
+/// Scans can't be interrupted, kept for the Lua API
 pub fn cancel_scan(_: &Lua, _: ()) -> LuaResult<bool> {
     Ok(true)
 }
 
-pub fn wait_for_initial_scan(_: &Lua, timeout_ms: Option<u64>) -> LuaResult<bool> {
-    let file_picker = FILE_PICKER.read().map_err(|_| Error::AcquireItemLock)?;
-    let picker = file_picker
-        .as_ref()
-        .ok_or_else(|| Error::FilePickerMissing)?;
+pub fn wait_for_initial_scan(
+    _: &Lua,
+    (timeout_ms, handle): (Option<u64>, Option<String>),
+) -> LuaResult<bool> {
+    let picker = get_picker(handle.as_deref())?;
+    // the scan thread needs the write lock to publish the results, only lock for the check
+    let is_scan_active = || -> Result<bool, Error> {
+        Ok(picker
+            .read()
+            .map_err(|_| Error::AcquireItemLock)?
+            .is_scan_active())
+    };
 
     let timeout_ms = timeout_ms.unwrap_or(500);
     let timeout_duration = Duration::from_millis(timeout_ms);
     let start_time = std::time::Instant::now();
     let mut sleep_duration = Duration::from_millis(1);
 
-    while picker.is_scan_active() {
+    while is_scan_active()? {
         if start_time.elapsed() >= timeout_duration {
             ::tracing::warn!("wait_for_initial_scan timed out after {}ms", timeout_ms);
             return Ok(false);
@@ -247,11 +782,38 @@ fn create_exports(lua: &Lua) -> LuaResult<LuaTable> {
         "restart_index_in_path",
         lua.create_function(restart_index_in_path)?,
     )?;
+    exports.set("list_pickers", lua.create_function(list_pickers)?)?;
     exports.set("scan_files", lua.create_function(scan_files)?)?;
     exports.set(
         "fuzzy_search_files",
         lua.create_function(fuzzy_search_files)?,
     )?;
+    exports.set(
+        "fuzzy_search_files_async",
+        lua.create_function(fuzzy_search_files_async)?,
+    )?;
+    exports.set(
+        "poll_search_result",
+        lua.create_function(poll_search_result)?,
+    )?;
+    exports.set("set_source_files", lua.create_function(set_source_files)?)?;
+    exports.set("add_pin", lua.create_function(add_pin)?)?;
+    exports.set("remove_pin", lua.create_function(remove_pin)?)?;
+    exports.set("list_pins", lua.create_function(list_pins)?)?;
+    exports.set("set_related_rules", lua.create_function(set_related_rules)?)?;
+    exports.set("get_related_files", lua.create_function(get_related_files)?)?;
+    exports.set("fuzzy_search_dirs", lua.create_function(fuzzy_search_dirs)?)?;
+    exports.set(
+        "fuzzy_search_recent",
+        lua.create_function(fuzzy_search_recent)?,
+    )?;
+    exports.set("get_results", lua.create_function(get_results)?)?;
+    exports.set("resolve_location", lua.create_function(resolve_location)?)?;
+    exports.set("grep_files", lua.create_function(grep_files)?)?;
+    exports.set("grep_files_async", lua.create_function(grep_files_async)?)?;
+    exports.set("poll_grep", lua.create_function(poll_grep)?)?;
+    exports.set("cancel_grep", lua.create_function(cancel_grep)?)?;
+    exports.set("explain", lua.create_function(explain)?)?;
     exports.set("track_access", lua.create_function(track_access)?)?;
     exports.set("cancel_scan", lua.create_function(cancel_scan)?)?;
     exports.set("get_scan_progress", lua.create_function(get_scan_progress)?)?;
@@ -280,3 +842,62 @@ fn create_exports(lua: &Lua) -> LuaResult<LuaTable> {
 fn fff_nvim(lua: &Lua) -> LuaResult<LuaTable> {
     create_exports(lua)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_co_access_of_relative_current_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        for file in ["model.rs", "view.rs", "other.rs"] {
+            std::fs::write(base_path.join(file), "").unwrap();
+        }
+
+        let db_dir = tempfile::tempdir().unwrap();
+        let tracker = FrecencyTracker::new(db_dir.path().to_str().unwrap(), true).unwrap();
+        tracker.track_access(&base_path.join("model.rs")).unwrap();
+        tracker.track_access(&base_path.join("view.rs")).unwrap();
+        *FRECENCY.write().unwrap() = Some(tracker);
+        invalidate_access_caches().unwrap();
+
+        let handle = base_path.to_string_lossy().to_string();
+        let (picker, _) = FILE_PICKERS
+            .write()
+            .unwrap()
+            .activate(&base_path, ScanConfig::default())
+            .unwrap();
+        file_picker::wait_for_scan(&picker);
+
+        // picker_ui.lua sends the current file relative to the base path
+        let request = search_request((
+            String::new(),
+            10,
+            1,
+            Some("model.rs".to_string()),
+            false,
+            None,
+            Some(handle.clone()),
+            None,
+        ))
+        .unwrap();
+        let mut results = request.run().unwrap();
+        let page = results.page(0, 10);
+        let co_access_boost = |name: &str| {
+            let position = page
+                .items
+                .iter()
+                .position(|file| file.file_name == name)
+                .unwrap();
+            page.scores[position].co_access_boost
+        };
+        assert!(co_access_boost("view.rs") > 0);
+        assert_eq!(co_access_boost("other.rs"), 0);
+        assert_eq!(page.items[0].file_name, "view.rs");
+
+        FILE_PICKERS.write().unwrap().remove(Some(&handle));
+        *FRECENCY.write().unwrap() = None;
+        invalidate_access_caches().unwrap();
+    }
+}
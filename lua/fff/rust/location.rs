@@ -1,8 +1,92 @@
+use std::borrow::Cow;
+
+/// Unit of the columns, tools disagree: compilers count bytes or chars, LSP and JavaScript
+/// engines count UTF-16 code units. Neovim wants bytes.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ColumnEncoding {
+    Byte,
+    Char,
+    Utf16,
+}
+
+impl ColumnEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ColumnEncoding::Byte => "byte",
+            ColumnEncoding::Char => "char",
+            ColumnEncoding::Utf16 => "utf16",
+        }
+    }
+}
+
+impl std::str::FromStr for ColumnEncoding {
+    type Err = ();
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        match encoding {
+            "byte" => Ok(ColumnEncoding::Byte),
+            "char" => Ok(ColumnEncoding::Char),
+            "utf16" | "utf-16" => Ok(ColumnEncoding::Utf16),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ColumnEncoding {
+    fn units(self, c: char) -> usize {
+        match self {
+            ColumnEncoding::Byte => c.len_utf8(),
+            ColumnEncoding::Char => 1,
+            ColumnEncoding::Utf16 => c.len_utf16(),
+        }
+    }
+}
+
+/// Converts the 1-based column of the `line` between the encodings. A column in the middle of a
+/// character points at the character, columns past the end of the line stay past it.
+pub fn convert_column(line: &str, col: i32, from: ColumnEncoding, to: ColumnEncoding) -> i32 {
+    if from == to || col <= 1 {
+        return col;
+    }
+
+    let mut remaining = (col - 1) as usize;
+    let mut converted = 0;
+    for c in line.chars() {
+        let units = from.units(c);
+        if remaining < units {
+            remaining = 0;
+            break;
+        }
+        remaining -= units;
+        converted += to.units(c);
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    (converted + remaining) as i32 + 1
+}
+
+/// Lines and columns are 1-based, a column of 0 in a range means the whole line
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Location {
     Line(i32),
-    Range { start: (i32, i32), end: (i32, i32) },
-    Position { line: i32, col: i32 },
+    Range {
+        start: (i32, i32),
+        end: (i32, i32),
+        encoding: ColumnEncoding,
+    },
+    Position {
+        line: i32,
+        col: i32,
+        encoding: ColumnEncoding,
+    },
+    /// 0-based byte offset from the start of the file
+    ByteOffset(usize),
+    /// A definition name, resolved to a line once the file is chosen, see `location_resolver`
+    Symbol(String),
+    /// A regex matched against the file contents, the first match is the location
+    Pattern(String),
 }
 
 fn parse_number_pair(location: &str, split_char: char) -> Option<(i32, i32)> {
@@ -32,6 +116,7 @@ fn parse_simple_range(location: &str) -> Option<Location> {
     Some(Location::Range {
         start: (start, 0),
         end: (end, 0),
+        encoding: ColumnEncoding::Char,
     })
 }
 
@@ -49,6 +134,7 @@ fn parse_column_range(start_part: &str, end_part: &str) -> Option<Location> {
     Some(Location::Range {
         start: (line, start_col),
         end: (line, end_col),
+        encoding: ColumnEncoding::Char,
     })
 }
 
@@ -61,12 +147,14 @@ fn parse_position_range(start_part: &str, end_part: &str) -> Option<Location> {
         return Some(Location::Position {
             line: start_line,
             col: start_col,
+            encoding: ColumnEncoding::Char,
         });
     }
 
     Some(Location::Range {
         start: (start_line, start_col),
         end: (end_line, end_col),
+        encoding: ColumnEncoding::Char,
     })
 }
 
@@ -102,64 +190,411 @@ fn try_parse_column_position(location: &str) -> Option<Location> {
     let line = line_str.parse::<i32>().ok()?;
     let col = col_str.parse::<i32>().ok()?;
 
-    Some(Location::Position { line, col })
+    Some(Location::Position {
+        line,
+        col,
+        encoding: ColumnEncoding::Char,
+    })
+}
+
+/// Parse the location after a colon: "12", "12:4", "12-114", "12:4-20" and "12:4-14:20"
+fn parse_colon_suffix(location: &str) -> Option<Location> {
+    if let Some(range_location) = try_parse_column_range(location) {
+        return Some(range_location);
+    }
+
+    if let Some(position_location) = try_parse_column_position(location) {
+        return Some(position_location);
+    }
+
+    location.parse::<i32>().ok().map(Location::Line)
 }
 
-/// Parses various location formats like file:12, file:12:4, file:12-114
-fn parse_column_location(query: &str) -> Option<(&str, Location)> {
-    let (file_path, location_part) = query.split_once(':')?;
+/// Parse "line:col:text" and "line:text" formats of grep and compilers, the text is ignored
+fn parse_grep_location(location: &str) -> Option<Location> {
+    let (line_str, rest) = location.split_once(':')?;
+    let line = line_str.parse::<i32>().ok()?;
 
-    if let Some(range_location) = try_parse_column_range(location_part) {
-        return Some((file_path, range_location));
+    match rest
+        .split_once(':')
+        .and_then(|(col_str, _)| col_str.parse::<i32>().ok())
+    {
+        Some(col) => Some(Location::Position {
+            line,
+            col,
+            encoding: ColumnEncoding::Byte,
+        }),
+        None => Some(Location::Line(line)),
     }
+}
 
-    if let Some(position_location) = try_parse_column_position(location_part) {
-        return Some((file_path, position_location));
+/// Parse the location after an opening parenthesis: "12)" and "12,4)", TypeScript and Go print
+/// the message after it "file.ts(10,5): error TS2322: ..."
+fn parse_paren_suffix(location: &str) -> Option<Location> {
+    let (location, message) = location.split_once(')')?;
+    if !message.is_empty() && !message.starts_with(": ") {
+        return None;
     }
 
-    if let Ok(line_location) = location_part.parse::<i32>() {
-        return Some((file_path, Location::Line(line_location)));
+    if let Ok(line) = location.parse::<i32>() {
+        return Some(Location::Line(line));
     }
 
-    None
+    let (line, col) = parse_number_pair(location, ',')?;
+    Some(Location::Position {
+        line,
+        col,
+        encoding: ColumnEncoding::Utf16,
+    })
 }
 
-fn parse_vstudio_location(query: &str) -> Option<(&str, Location)> {
-    if !query.ends_with(')') {
+/// Parse the Python traceback format `File "x.py", line 10, in main`
+fn parse_python_traceback(query: &str) -> Option<(&str, Location)> {
+    let rest = query.trim_start().strip_prefix("File \"")?;
+    let (file_path, rest) = rest.split_once('"')?;
+    let line_str = rest.strip_prefix(", line ")?;
+    let line_end = line_str
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(line_str.len());
+    let line = line_str[..line_end].parse::<i32>().ok()?;
+
+    Some((file_path, Location::Line(line)))
+}
+
+/// Strips the decorations tools print around a location: the rustc `--> ` and `::: ` arrows
+/// and the Node stack frames `at fn (file.js:10:5)`, with the column unit of the tool
+fn strip_location_prefix(query: &str) -> (&str, Option<ColumnEncoding>) {
+    let trimmed = query.trim_start();
+    if let Some(location) = trimmed
+        .strip_prefix("-->")
+        .or_else(|| trimmed.strip_prefix(":::"))
+    {
+        return (location.trim(), Some(ColumnEncoding::Char));
+    }
+
+    // a query starting with "at" is only a frame when it has a location
+    let Some(frame) = trimmed
+        .strip_prefix("at ")
+        .filter(|frame| frame.contains(':'))
+    else {
+        return (query, None);
+    };
+    let frame = frame.trim_end();
+    let location = match frame.rsplit_once(" (") {
+        Some((_, location)) => location.strip_suffix(')').unwrap_or(location),
+        None => frame,
+    };
+    (location, Some(ColumnEncoding::Utf16))
+}
+
+/// Parse the pytest and JUnit test ids `tests/test_api.py::test_login`, the test name is not a
+/// location so only the file is kept
+fn parse_test_id(query: &str) -> Option<&str> {
+    let (file_path, _) = query.split_once("::")?;
+    let has_extension = file_path
+        .rsplit_once('.')
+        .is_some_and(|(stem, extension)| !stem.is_empty() && !extension.contains('/'));
+    (has_extension && !file_path.contains(' ')).then_some(file_path)
+}
+
+impl Location {
+    /// Replaces the unit of the columns, the other locations don't have any
+    fn with_encoding(self, encoding: ColumnEncoding) -> Self {
+        match self {
+            Location::Position { line, col, .. } => Location::Position {
+                line,
+                col,
+                encoding,
+            },
+            Location::Range { start, end, .. } => Location::Range {
+                start,
+                end,
+                encoding,
+            },
+            location => location,
+        }
+    }
+}
+
+/// Parse a "line" or "lineCcol" part of the line anchors
+fn parse_anchor_position(position: &str) -> Option<(i32, Option<i32>)> {
+    match position.split_once(['C', 'c']) {
+        Some((line, col)) => Some((line.parse().ok()?, Some(col.parse().ok()?))),
+        None => Some((position.parse().ok()?, None)),
+    }
+}
+
+/// Parse the line anchors of the forges: GitHub `L10-L20` and `L10C5-L20C8`, GitLab and
+/// sourcehut `L10-20`, Bitbucket `lines-10:20`
+fn parse_line_anchor(anchor: &str) -> Option<Location> {
+    let (start, end) = match anchor.strip_prefix("lines-") {
+        Some(lines) => match lines.split_once(':') {
+            Some((start, end)) => (start, Some(end)),
+            None => (lines, None),
+        },
+        None => {
+            let lines = anchor.strip_prefix('L')?;
+            match lines.split_once('-') {
+                Some((start, end)) => (start, Some(end.strip_prefix('L').unwrap_or(end))),
+                None => (lines, None),
+            }
+        }
+    };
+
+    let (start_line, start_col) = parse_anchor_position(start)?;
+    let start_location = match start_col {
+        Some(col) => Location::Position {
+            line: start_line,
+            col,
+            encoding: ColumnEncoding::Char,
+        },
+        None => Location::Line(start_line),
+    };
+    let Some(end) = end else {
+        return Some(start_location);
+    };
+
+    let (end_line, end_col) = parse_anchor_position(end)?;
+    let (start, end) = (
+        (start_line, start_col.unwrap_or(0)),
+        (end_line, end_col.unwrap_or(0)),
+    );
+    if end < start {
+        return Some(start_location);
+    }
+
+    Some(Location::Range {
+        start,
+        end,
+        encoding: ColumnEncoding::Char,
+    })
+}
+
+/// The path segments following `owner/repo` in a blob URL, the ref comes right after them
+/// followed by the rest of the marker: GitHub `blob/<ref>/`, Bitbucket `src/<ref>/` and
+/// sourcehut `tree/<ref>/item/`
+const FORGE_BLOB_MARKERS: [(&str, &str); 3] = [("blob/", ""), ("src/", ""), ("tree/", "item/")];
+
+/// Parse the blob URLs of the forges into the repository relative path and the line anchor.
+/// Refs containing a slash can't be told apart from the path and are not supported.
+fn parse_forge_url(query: &str) -> Option<(&str, Option<Location>)> {
+    let url = query
+        .strip_prefix("https://")
+        .or_else(|| query.strip_prefix("http://"))?;
+    let (url, anchor) = url.split_once('#').unwrap_or((url, ""));
+    let url = url.split_once('?').map_or(url, |(url, _)| url);
+    let (_, repository_path) = url.split_once('/')?;
+
+    let file_path = match repository_path.split_once("/-/blob/") {
+        // GitLab namespaces nest, `-` can't be the name of a group or a project
+        Some((_, blob_path)) => blob_path.split_once('/')?.1,
+        // the marker is the segment after `owner/repo`, a later one is a part of the file path
+        None => {
+            let mut segments = repository_path.splitn(3, '/');
+            let (_owner, _repository) = (segments.next()?, segments.next()?);
+            let blob_path = segments.next()?;
+            let (after_ref, blob_path) =
+                FORGE_BLOB_MARKERS.iter().find_map(|(marker, after_ref)| {
+                    Some((*after_ref, blob_path.strip_prefix(marker)?))
+                })?;
+            blob_path.split_once('/')?.1.strip_prefix(after_ref)?
+        }
+    };
+    if file_path.is_empty() {
         return None;
     }
 
-    let (file_path, location_with_paren) = query.rsplit_once('(')?;
-    let location = location_with_paren.trim_end_matches(')');
+    Some((file_path, parse_line_anchor(anchor)))
+}
 
-    if let Ok(line) = location.parse::<i32>() {
-        return Some((file_path, Location::Line(line)));
+/// Parse `file://` URIs, the location is either a line anchor or the usual suffix. The path
+/// is still percent-encoded, see `decode_file_uri`.
+fn parse_file_uri(query: &str) -> Option<(&str, Option<Location>)> {
+    let path = query.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+
+    match path.split_once('#') {
+        Some((path, anchor)) => Some((path, parse_line_anchor(anchor))),
+        None => Some(parse_location(path)),
+    }
+}
+
+/// The query with the path of its `file://` URI percent-decoded, e.g. `My%20Notes` is
+/// `My Notes`. Other queries are kept as typed, `%` is a valid file name character. An encoded
+/// `#` stays encoded, it would read as the line anchor.
+pub fn decode_file_uri(query: &str) -> Cow<'_, str> {
+    let (path, _) = parse_location(query);
+    if !path.contains('%') || !query.contains("file://") {
+        return Cow::Borrowed(query);
     }
 
-    if let Some((line, col)) = parse_number_pair(location, ',') {
-        return Some((file_path, Location::Position { line, col }));
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|hex| bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+            .filter(|&byte| byte != b'#');
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
     }
 
-    None
+    Cow::Owned(query.replacen(path, &String::from_utf8_lossy(&decoded), 1))
 }
 
+fn is_symbol_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Parse "file:/regex/" format
+fn parse_pattern_location(query: &str) -> Option<(&str, Location)> {
+    let (file_path, pattern) = query.strip_suffix('/')?.split_once(":/")?;
+    if file_path.is_empty() || pattern.is_empty() {
+        return None;
+    }
+
+    Some((file_path, Location::Pattern(pattern.to_string())))
+}
+
+/// Parse "file@symbol" and "file#symbol" formats, a line anchor after `#` is a line
+fn parse_symbol_location(query: &str) -> Option<(&str, Location)> {
+    let (file_path, symbol) = query.rsplit_once(['@', '#'])?;
+    if file_path.is_empty() || file_path.ends_with('/') {
+        return None;
+    }
+
+    if query[file_path.len()..].starts_with('#')
+        && let Some(location) = parse_line_anchor(symbol)
+    {
+        return Some((file_path, location));
+    }
+
+    is_symbol_name(symbol).then(|| (file_path, Location::Symbol(symbol.to_string())))
+}
+
+/// Parse "file:#offset" format, a 0-based byte offset
+fn parse_byte_offset_location(query: &str) -> Option<(&str, Location)> {
+    let (file_path, offset) = query.rsplit_once(":#")?;
+    if file_path.is_empty() {
+        return None;
+    }
+
+    Some((file_path, Location::ByteOffset(offset.parse().ok()?)))
+}
+
+/// Reads the query as the most plausible path and location, see `location_candidates`
 pub fn parse_location(query: &str) -> (&str, Option<Location>) {
+    parse_location_in(query, |_| false)
+}
+
+/// Reads the query as the first candidate whose path is indexed, a path containing `:` or
+/// parentheses is only split where the rest isn't a part of an indexed file name. Without an
+/// indexed candidate the most plausible one wins.
+pub fn parse_location_in(
+    query: &str,
+    is_indexed: impl Fn(&str) -> bool,
+) -> (&str, Option<Location>) {
+    let mut candidates = location_candidates(query);
+    let index = match candidates.len() {
+        1 => 0,
+        _ => candidates
+            .iter()
+            .position(|(path, _)| is_indexed(path))
+            .unwrap_or(0),
+    };
+    candidates.swap_remove(index)
+}
+
+/// Every reading of the query as a path with a location, the most plausible first. The query
+/// may split at any `:` or `(` as paths can contain them, e.g. `C:\src\main.rs:10` or
+/// `report(1).txt`, so the candidates are ordered by the format:
+///
+/// 1. tool outputs, URLs, symbols, patterns and test ids
+/// 2. a colon followed by only a location, the leftmost split first
+/// 3. a colon followed by a location and the grep text
+/// 4. a parenthesized location at the end
+///
+/// The whole query without a location always ends the list.
+pub fn location_candidates(query: &str) -> Vec<(&str, Option<Location>)> {
+    let candidates = unprefixed_candidates(query);
+    match strip_location_prefix(query) {
+        (_, Some(encoding)) => candidates
+            .into_iter()
+            .map(|(path, location)| {
+                (
+                    path,
+                    location.map(|location| location.with_encoding(encoding)),
+                )
+            })
+            .collect(),
+        (_, None) => candidates,
+    }
+}
+
+fn unprefixed_candidates(query: &str) -> Vec<(&str, Option<Location>)> {
+    if let Some((path, location)) = parse_python_traceback(query) {
+        return vec![(path, Some(location)), (query, None)];
+    }
+
+    let (query, _) = strip_location_prefix(query);
+    if let Some(parsed) = parse_file_uri(query).or_else(|| parse_forge_url(query)) {
+        return vec![parsed, (query, None)];
+    }
+
+    let mut candidates = Vec::new();
+    if let Some((path, location)) = parse_pattern_location(query)
+        .or_else(|| parse_byte_offset_location(query))
+        .or_else(|| parse_symbol_location(query))
+    {
+        candidates.push((path, Some(location)));
+    }
+
     // simply ignore the last semicolon even if there are no additional location info
     let query = query.trim_end_matches([':', '-', '(']);
-    if let Some((path, location)) = parse_column_location(query) {
-        return (path, Some(location));
+    if let Some(path) = parse_test_id(query) {
+        candidates.push((path, None));
     }
 
-    if let Some((path, location)) = parse_vstudio_location(query) {
-        return (path, Some(location));
-    }
+    let splits = |separator: char| {
+        query
+            .match_indices(separator)
+            .map(|(index, _)| (&query[..index], &query[index + 1..]))
+            .filter(|(path, _)| !path.is_empty())
+    };
+    candidates.extend(
+        splits(':')
+            .filter_map(|(path, location)| Some((path, Some(parse_colon_suffix(location)?)))),
+    );
+    candidates.extend(
+        splits(':')
+            .filter_map(|(path, location)| Some((path, Some(parse_grep_location(location)?)))),
+    );
+    candidates.extend(
+        splits('(')
+            .filter_map(|(path, location)| Some((path, Some(parse_paren_suffix(location)?)))),
+    );
 
-    (query, None)
+    candidates.push((query, None));
+    candidates
 }
 
 #[cfg(test)]
 mod tests {
     pub use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_location_parsing() {
@@ -172,7 +607,14 @@ mod tests {
         assert_eq!(parse_location("something"), ("something", None));
         assert_eq!(
             parse_location("file:12:4"),
-            ("file", Some(Location::Position { line: 12, col: 4 }))
+            (
+                "file",
+                Some(Location::Position {
+                    line: 12,
+                    col: 4,
+                    encoding: ColumnEncoding::Char
+                })
+            )
         );
 
         assert_eq!(
@@ -181,7 +623,8 @@ mod tests {
                 "file",
                 Some(Location::Range {
                     start: (12, 0),
-                    end: (114, 0)
+                    end: (114, 0),
+                    encoding: ColumnEncoding::Char
                 })
             )
         );
@@ -192,14 +635,22 @@ mod tests {
                 "file",
                 Some(Location::Range {
                     start: (12, 4),
-                    end: (12, 20)
+                    end: (12, 20),
+                    encoding: ColumnEncoding::Char
                 })
             )
         );
 
         assert_eq!(
             parse_location("file:100:4-14:20"),
-            ("file", Some(Location::Position { line: 100, col: 4 }))
+            (
+                "file",
+                Some(Location::Position {
+                    line: 100,
+                    col: 4,
+                    encoding: ColumnEncoding::Char
+                })
+            )
         );
 
         assert_eq!(
@@ -208,7 +659,8 @@ mod tests {
                 "file",
                 Some(Location::Range {
                     start: (12, 4),
-                    end: (14, 20)
+                    end: (14, 20),
+                    encoding: ColumnEncoding::Char
                 })
             )
         );
@@ -222,7 +674,263 @@ mod tests {
         );
         assert_eq!(
             parse_location("file(12,4)"),
-            ("file", Some(Location::Position { line: 12, col: 4 }))
+            (
+                "file",
+                Some(Location::Position {
+                    line: 12,
+                    col: 4,
+                    encoding: ColumnEncoding::Utf16
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn test_tool_output_parsing() {
+        assert_eq!(
+            parse_location(r#"  File "app/models.py", line 10, in save"#),
+            ("app/models.py", Some(Location::Line(10)))
+        );
+        assert_eq!(
+            parse_location("  --> src/lib.rs:10:5"),
+            (
+                "src/lib.rs",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("::: src/types.rs:3:1"),
+            (
+                "src/types.rs",
+                Some(Location::Position {
+                    line: 3,
+                    col: 1,
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("src/app.ts(10,5): error TS2322: Type 'string' is not assignable"),
+            (
+                "src/app.ts",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Utf16
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("    at render (src/view.js:10:5)"),
+            (
+                "src/view.js",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Utf16
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("at src/index.js:7:12"),
+            (
+                "src/index.js",
+                Some(Location::Position {
+                    line: 7,
+                    col: 12,
+                    encoding: ColumnEncoding::Utf16
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("tests/test_api.py::TestLogin::test_invalid_password"),
+            ("tests/test_api.py", None)
+        );
+        assert_eq!(
+            parse_location("src/main.rs:10:5:    let value = a - b;"),
+            (
+                "src/main.rs",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Byte
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("main_test.go:42: expected 1, got 2"),
+            ("main_test.go", Some(Location::Line(42)))
+        );
+        // plain queries are left alone
+        assert_eq!(parse_location("std::fmt"), ("std::fmt", None));
+        assert_eq!(parse_location("at home"), ("at home", None));
+    }
+
+    #[test]
+    fn test_url_parsing() {
+        assert_eq!(
+            parse_location("https://github.com/org/repo/blob/main/src/lib.rs#L10-L20"),
+            (
+                "src/lib.rs",
+                Some(Location::Range {
+                    start: (10, 0),
+                    end: (20, 0),
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("https://github.com/org/repo/blob/4f2a91c/src/lib.rs?plain=1#L10C5"),
+            (
+                "src/lib.rs",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("https://gitlab.com/group/sub/repo/-/blob/main/src/blob/mod.rs#L3-7"),
+            (
+                "src/blob/mod.rs",
+                Some(Location::Range {
+                    start: (3, 0),
+                    end: (7, 0),
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("https://bitbucket.org/org/repo/src/main/app/models.py#lines-12:18"),
+            (
+                "app/models.py",
+                Some(Location::Range {
+                    start: (12, 0),
+                    end: (18, 0),
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        // repositories named like the markers
+        assert_eq!(
+            parse_location("https://bitbucket.org/acme/src/src/main/lib.rs"),
+            ("lib.rs", None)
+        );
+        assert_eq!(
+            parse_location("https://github.com/blob/src/blob/main/src/blob/mod.rs"),
+            ("src/blob/mod.rs", None)
+        );
+        assert_eq!(
+            parse_location("https://git.sr.ht/~user/repo/tree/main/item/src/main.c#L42"),
+            ("src/main.c", Some(Location::Line(42)))
+        );
+        assert_eq!(
+            parse_location("https://github.com/org/repo/blob/main/README.md"),
+            ("README.md", None)
+        );
+
+        assert_eq!(
+            parse_location("file:///home/user/project/src/lib.rs"),
+            ("/home/user/project/src/lib.rs", None)
+        );
+        assert_eq!(
+            parse_location("file:///home/user/project/src/lib.rs:10:5"),
+            (
+                "/home/user/project/src/lib.rs",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(
+            parse_location("at main (file:///app/index.js#L7)"),
+            ("/app/index.js", Some(Location::Line(7)))
+        );
+        assert_eq!(
+            parse_location(&decode_file_uri(
+                "file:///home/me/My%20Notes/caf%C3%A9.md#L3"
+            )),
+            ("/home/me/My Notes/café.md", Some(Location::Line(3)))
+        );
+        assert_eq!(
+            decode_file_uri("file:///notes/C%23/100%25%zz.md"),
+            "file:///notes/C%23/100%%zz.md"
+        );
+        assert_eq!(decode_file_uri("src/100%20.rs"), "src/100%20.rs");
+    }
+
+    #[test]
+    fn test_symbol_and_pattern_parsing() {
+        assert_eq!(
+            parse_location("src/lib.rs@parse_location"),
+            (
+                "src/lib.rs",
+                Some(Location::Symbol("parse_location".to_string()))
+            )
+        );
+        assert_eq!(
+            parse_location("picker.ts#PickerState"),
+            (
+                "picker.ts",
+                Some(Location::Symbol("PickerState".to_string()))
+            )
+        );
+        assert_eq!(
+            parse_location("picker.ts#L12"),
+            ("picker.ts", Some(Location::Line(12)))
+        );
+        assert_eq!(
+            parse_location("main.go:/func (s \\*Server) Start/"),
+            (
+                "main.go",
+                Some(Location::Pattern("func (s \\*Server) Start".to_string()))
+            )
+        );
+
+        assert_eq!(
+            parse_location("node_modules/@types/node"),
+            ("node_modules/@types/node", None)
+        );
+        assert_eq!(parse_location("issue#42"), ("issue#42", None));
+        assert_eq!(parse_location("src:/"), ("src:/", None));
+    }
+
+    #[test]
+    fn test_column_encodings() {
+        // "é" is 2 bytes and 1 UTF-16 unit, "😀" is 4 bytes and 2 UTF-16 units
+        let line = "aé😀b";
+        let columns = |col, from| {
+            [
+                ColumnEncoding::Byte,
+                ColumnEncoding::Char,
+                ColumnEncoding::Utf16,
+            ]
+            .map(|to| convert_column(line, col, from, to))
+        };
+        // the column of "b"
+        assert_eq!(columns(8, ColumnEncoding::Byte), [8, 4, 5]);
+        assert_eq!(columns(4, ColumnEncoding::Char), [8, 4, 5]);
+        assert_eq!(columns(5, ColumnEncoding::Utf16), [8, 4, 5]);
+        // inside of the emoji and past the end of the line
+        assert_eq!(
+            convert_column(line, 5, ColumnEncoding::Byte, ColumnEncoding::Char),
+            3
+        );
+        assert_eq!(
+            convert_column(line, 7, ColumnEncoding::Char, ColumnEncoding::Byte),
+            11
+        );
+
+        assert_eq!(
+            parse_location("src/lib.rs:#1234"),
+            ("src/lib.rs", Some(Location::ByteOffset(1234)))
         );
     }
 
@@ -235,4 +943,141 @@ mod tests {
         assert_eq!(parse_location("file:-"), ("file", None));
         assert_eq!(parse_location("file("), ("file", None));
     }
+
+    #[test]
+    fn test_ambiguous_paths() {
+        assert_eq!(
+            parse_location(r"C:\src\main.rs:10:5"),
+            (
+                r"C:\src\main.rs",
+                Some(Location::Position {
+                    line: 10,
+                    col: 5,
+                    encoding: ColumnEncoding::Char
+                })
+            )
+        );
+        assert_eq!(parse_location("report(1).txt"), ("report(1).txt", None));
+        assert_eq!(
+            parse_location("report(1).txt:4"),
+            ("report(1).txt", Some(Location::Line(4)))
+        );
+        assert_eq!(
+            parse_location("src/a::b.scala:7"),
+            ("src/a::b.scala", Some(Location::Line(7)))
+        );
+
+        // the split depends on the indexed files
+        let notes = "notes:2:draft.md";
+        assert_eq!(parse_location(notes), ("notes", Some(Location::Line(2))));
+        assert_eq!(
+            parse_location_in(notes, |path| path == notes),
+            (notes, None)
+        );
+        assert_eq!(
+            parse_location_in("build(2)", |path| path == "build(2)"),
+            ("build(2)", None)
+        );
+        assert_eq!(
+            parse_location_in("a:1:b:3", |path| path == "a:1:b"),
+            ("a:1:b", Some(Location::Line(3)))
+        );
+    }
+
+    /// Paths made of the characters the location grammar splits on
+    fn path_strategy() -> impl Strategy<Value = String> {
+        r"[bCx_.][abzC07._ /\\:(),-]{0,11}"
+    }
+
+    /// A location and the ways tools print it after a path
+    fn location_strategy() -> impl Strategy<Value = (String, Location)> {
+        (1..500, 1..120, 0..50, 0..20, 0..8).prop_map(|(line, col, lines, cols, format)| {
+            let (end_line, end_col) = (line + lines, col + cols);
+            let char_position = Location::Position {
+                line,
+                col,
+                encoding: ColumnEncoding::Char,
+            };
+            let range = |start, end| Location::Range {
+                start,
+                end,
+                encoding: ColumnEncoding::Char,
+            };
+            match format {
+                0 => (format!(":{line}"), Location::Line(line)),
+                1 => (format!("({line})"), Location::Line(line)),
+                2 => (format!(":{line}:{col}"), char_position),
+                3 => (
+                    format!("({line},{col}): error"),
+                    Location::Position {
+                        line,
+                        col,
+                        encoding: ColumnEncoding::Utf16,
+                    },
+                ),
+                4 => (
+                    format!(":{line}:{col}: warning: unused"),
+                    Location::Position {
+                        line,
+                        col,
+                        encoding: ColumnEncoding::Byte,
+                    },
+                ),
+                5 => (
+                    format!(":{line}-{end_line}"),
+                    range((line, 0), (end_line, 0)),
+                ),
+                6 => (
+                    format!(":{line}:{col}-{end_col}"),
+                    range((line, col), (line, end_col)),
+                ),
+                _ => (
+                    format!(":{line}:{col}-{end_line}:{end_col}"),
+                    range((line, col), (end_line, end_col)),
+                ),
+            }
+        })
+    }
+
+    /// Tool output prefixes followed by anything the grammar reacts to
+    fn query_strategy() -> impl Strategy<Value = String> {
+        let prefix = prop::sample::select(vec![
+            "",
+            "at ",
+            "--> ",
+            "file://",
+            "https://github.com/o/r/blob/main/",
+            "File \"",
+        ]);
+        (prefix, r#"[aLC019:(),#@/\\. "é😀-]{0,15}"#)
+            .prop_map(|(prefix, rest)| format!("{prefix}{rest}"))
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(4096))]
+
+        #[test]
+        fn test_indexed_paths_round_trip(
+            path in path_strategy(),
+            (suffix, location) in location_strategy(),
+        ) {
+            let query = format!("{path}{suffix}");
+            prop_assert_eq!(
+                parse_location_in(&query, |candidate| candidate == path),
+                (path.as_str(), Some(location))
+            );
+        }
+
+        #[test]
+        fn test_parsing_arbitrary_queries(query in query_strategy()) {
+            let candidates = location_candidates(&query);
+            prop_assert!(candidates
+                .last()
+                .is_some_and(|(_, location)| location.is_none()));
+            for (path, _) in &candidates {
+                prop_assert!(query.contains(path));
+            }
+            prop_assert_eq!(parse_location(&query), candidates[0].clone());
+        }
+    }
 }
@@ -0,0 +1,417 @@
+use crate::error::Error;
+use crate::location::{ColumnEncoding, Location, convert_column};
+use regex::bytes::{Regex, RegexBuilder};
+use std::path::Path;
+
+/// Where the definitions of a symbol usually are in the languages by the file extension,
+/// `{}` stands for the symbol. They are heuristics, not a parser: the earliest line matching
+/// any of them wins.
+fn definition_patterns(extension: &str) -> &'static [&'static str] {
+    match extension {
+        "rs" => &[
+            r"^\s*(pub(\([^)]*\))?\s+)?((const|async|unsafe|extern\s+\S+)\s+)*(fn|struct|enum|union|trait|type|mod|const|static)\s+{}\b",
+            r"^\s*macro_rules!\s*{}\b",
+        ],
+        "py" | "pyi" => &[r"^\s*(async\s+)?(def|class)\s+{}\b", r"^{}\s*(:[^=]*)?="],
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" => &[
+            r"^\s*(export\s+)?(default\s+)?(declare\s+)?(abstract\s+)?(async\s+)?(function\*?|class|interface|type|enum|namespace|const|let|var)\s+{}\b",
+            r"^\s*((static|async|public|private|protected|readonly|get|set)\s+)*{}\s*(<[^>]*>)?\(.*\)[^;]*$",
+        ],
+        "go" => &[
+            r"^func\s+(\([^)]*\)\s+)?{}\b",
+            r"^\s*(type|var|const)\s+{}\b",
+        ],
+        "lua" => &[
+            r"^\s*(local\s+)?function\s+([\w.]+[.:])?{}\b",
+            r"^\s*(local\s+)?([\w.]+\.)?{}\s*=\s*function\b",
+        ],
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" | "m" | "mm" => &[
+            r"^\s*#\s*define\s+{}\b",
+            r"^\s*(typedef\s+)?(struct|class|enum|union)\s+{}\b",
+            r"^[A-Za-z_][\w\s\*&:<>,]*\b{}\s*\([^;]*$",
+        ],
+        "java" | "kt" | "kts" | "scala" | "cs" | "swift" => &[
+            r"\b(class|interface|enum|record|struct|object|trait|protocol|fun|func|def)\s+{}\b",
+            r"^\s*((public|private|protected|internal|static|final|abstract|override|virtual|async)\s+)*[\w<>\[\],.?]+\s+{}\s*\(",
+        ],
+        "rb" => &[r"^\s*(def\s+(self\.)?|class\s+|module\s+){}\b"],
+        _ => &[],
+    }
+}
+
+/// Definition keywords shared by most languages, used when the language ones find nothing
+const GENERIC_DEFINITION_PATTERN: &str =
+    r"\b(fn|func|function|def|class|struct|enum|trait|interface|type|module)\s+{}\b";
+
+/// Turns a location into lines and byte columns of the file as Neovim expects them: symbols
+/// and patterns become the position of their first match, byte offsets and the columns of the
+/// other encodings are converted. `None` when nothing matches.
+pub fn resolve_location(path: &Path, location: &Location) -> Result<Option<Location>, Error> {
+    let needs_contents = match location {
+        Location::Line(_) => false,
+        Location::Position { encoding, .. } | Location::Range { encoding, .. } => {
+            *encoding != ColumnEncoding::Byte
+        }
+        Location::ByteOffset(_) | Location::Symbol(_) | Location::Pattern(_) => true,
+    };
+    if !needs_contents {
+        return Ok(Some(location.clone()));
+    }
+
+    // the offsets stay the ones of the raw bytes Neovim has, the file doesn't have to be UTF-8
+    let content = std::fs::read(path).map_err(Error::ReadFile)?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    resolve_in_content(&content, &extension, location)
+}
+
+fn resolve_in_content(
+    content: &[u8],
+    extension: &str,
+    location: &Location,
+) -> Result<Option<Location>, Error> {
+    let position = match location {
+        Location::Symbol(symbol) => find_definition(content, extension, symbol)?,
+        Location::Pattern(pattern) => Regex::new(&format!("(?m){pattern}"))?
+            .find(content)
+            .map(|found| found.start()),
+        Location::ByteOffset(offset) => {
+            let offset = (*offset).min(content.len());
+            let line_start = line_start(content, offset);
+            Some(line_start + char_start(&content[line_start..], offset - line_start))
+        }
+        Location::Position {
+            line,
+            col,
+            encoding,
+        } => {
+            return Ok(Some(Location::Position {
+                line: *line,
+                col: byte_column(content, *line, *col, *encoding),
+                encoding: ColumnEncoding::Byte,
+            }));
+        }
+        Location::Range {
+            start: (start_line, start_col),
+            end: (end_line, end_col),
+            encoding,
+        } => {
+            return Ok(Some(Location::Range {
+                start: (
+                    *start_line,
+                    byte_column(content, *start_line, *start_col, *encoding),
+                ),
+                end: (
+                    *end_line,
+                    byte_column(content, *end_line, *end_col, *encoding),
+                ),
+                encoding: ColumnEncoding::Byte,
+            }));
+        }
+        Location::Line(_) => return Ok(Some(location.clone())),
+    };
+
+    Ok(position.map(|offset| position_at(content, offset)))
+}
+
+/// Only the line is decoded to count its characters
+fn byte_column(content: &[u8], line: i32, col: i32, encoding: ColumnEncoding) -> i32 {
+    let line_bytes = content
+        .split(|&byte| byte == b'\n')
+        .nth((line.max(1) - 1) as usize)
+        .unwrap_or_default();
+    let line_bytes = line_bytes.strip_suffix(b"\r").unwrap_or(line_bytes);
+    let line_text = String::from_utf8_lossy(line_bytes);
+    let col = convert_column(&line_text, col, encoding, ColumnEncoding::Byte);
+    raw_byte_column(line_bytes, col)
+}
+
+/// Byte column of the raw `line` for a byte column of its lossy decoding, where every invalid
+/// sequence became a single U+FFFD
+fn raw_byte_column(line: &[u8], decoded_col: i32) -> i32 {
+    if decoded_col <= 1 {
+        return decoded_col;
+    }
+
+    let mut remaining = (decoded_col - 1) as usize;
+    let mut raw = 0;
+    for chunk in line.utf8_chunks() {
+        let valid = chunk.valid().len();
+        if remaining <= valid {
+            return (raw + remaining) as i32 + 1;
+        }
+        remaining -= valid;
+        raw += valid;
+
+        if !chunk.invalid().is_empty() {
+            let replacement = char::REPLACEMENT_CHARACTER.len_utf8();
+            if remaining < replacement {
+                return raw as i32 + 1;
+            }
+            remaining -= replacement;
+            raw += chunk.invalid().len();
+        }
+    }
+    (raw + remaining) as i32 + 1
+}
+
+fn line_start(content: &[u8], offset: usize) -> usize {
+    content[..offset]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1)
+}
+
+/// Start of the character containing the byte at `offset` of the line, the bytes of invalid
+/// sequences stand for themselves
+fn char_start(line: &[u8], offset: usize) -> usize {
+    let mut start = 0;
+    for chunk in line.utf8_chunks() {
+        for (index, c) in chunk.valid().char_indices() {
+            if start + index + c.len_utf8() > offset {
+                return start + index;
+            }
+        }
+        start += chunk.valid().len() + chunk.invalid().len();
+        if start > offset {
+            return offset;
+        }
+    }
+    offset
+}
+
+/// Byte offset of the symbol in its earliest definition, the first mention of the symbol as a
+/// whole word when no definition is recognized
+fn find_definition(content: &[u8], extension: &str, symbol: &str) -> Result<Option<usize>, Error> {
+    let symbol_group = format!("(?P<symbol>{})", regex::escape(symbol));
+    let symbol_offset = |template: &str| -> Result<Option<usize>, Error> {
+        let definition = RegexBuilder::new(&template.replace("{}", &symbol_group))
+            .multi_line(true)
+            .build()?;
+        Ok(definition
+            .captures(content)
+            .and_then(|captures| captures.name("symbol"))
+            .map(|symbol| symbol.start()))
+    };
+
+    let mut earliest: Option<usize> = None;
+    for template in definition_patterns(extension) {
+        if let Some(offset) = symbol_offset(template)? {
+            earliest = Some(earliest.map_or(offset, |earliest| earliest.min(offset)));
+        }
+    }
+    if earliest.is_some() {
+        return Ok(earliest);
+    }
+
+    if let Some(offset) = symbol_offset(GENERIC_DEFINITION_PATTERN)? {
+        return Ok(Some(offset));
+    }
+    symbol_offset(r"\b{}\b")
+}
+
+/// 1-based line and column (in bytes) of the offset
+fn position_at(content: &[u8], offset: usize) -> Location {
+    let lines_before = content[..offset]
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count();
+    Location::Position {
+        line: lines_before as i32 + 1,
+        col: (offset - line_start(content, offset)) as i32 + 1,
+        encoding: ColumnEncoding::Byte,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(content: &str, extension: &str, location: Location) -> Option<Location> {
+        resolve_in_content(content.as_bytes(), extension, &location).unwrap()
+    }
+
+    #[test]
+    fn test_definition_heuristics() {
+        let rust = "use std::fmt;\n\n// parse_config is called by main\npub(crate) async fn parse_config() {}\n";
+        assert_eq!(
+            resolve(rust, "rs", Location::Symbol("parse_config".to_string())),
+            Some(Location::Position {
+                line: 4,
+                col: 21,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+
+        let python = "import os\n\nclass Service:\n    def start(self):\n        pass\n";
+        assert_eq!(
+            resolve(python, "py", Location::Symbol("start".to_string())),
+            Some(Location::Position {
+                line: 4,
+                col: 9,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+
+        let typescript = "const a = render();\nexport default function render() {}\n";
+        assert_eq!(
+            resolve(typescript, "ts", Location::Symbol("render".to_string())),
+            Some(Location::Position {
+                line: 2,
+                col: 25,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+
+        let go = "package main\n\nfunc (s *Server) Start() error {\n";
+        assert_eq!(
+            resolve(go, "go", Location::Symbol("Start".to_string())),
+            Some(Location::Position {
+                line: 3,
+                col: 18,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+
+        // unknown languages fall back to the keywords and then to any mention
+        assert_eq!(
+            resolve(
+                "x\ndef setup\n",
+                "txt",
+                Location::Symbol("setup".to_string())
+            ),
+            Some(Location::Position {
+                line: 2,
+                col: 5,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+        assert_eq!(
+            resolve("a\nb setup\n", "txt", Location::Symbol("setup".to_string())),
+            Some(Location::Position {
+                line: 2,
+                col: 3,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+        assert_eq!(
+            resolve(rust, "rs", Location::Symbol("missing".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pattern_resolution() {
+        let content = "first\nlet value = compute(1);\n";
+        assert_eq!(
+            resolve(
+                content,
+                "rs",
+                Location::Pattern(r"compute\(\d\)".to_string())
+            ),
+            Some(Location::Position {
+                line: 2,
+                col: 13,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+        assert!(
+            resolve_in_content(
+                content.as_bytes(),
+                "rs",
+                &Location::Pattern("(".to_string())
+            )
+            .is_err()
+        );
+        assert_eq!(
+            resolve(content, "rs", Location::Line(3)),
+            Some(Location::Line(3))
+        );
+    }
+
+    #[test]
+    fn test_columns_resolve_to_bytes() {
+        let content = "first\nlet naïve = \"😀\";\n";
+        // inside of "ï"
+        assert_eq!(
+            resolve(content, "rs", Location::ByteOffset(13)),
+            Some(Location::Position {
+                line: 2,
+                col: 7,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+        // the closing quote
+        assert_eq!(
+            resolve(
+                content,
+                "rs",
+                Location::Position {
+                    line: 2,
+                    col: 16,
+                    encoding: ColumnEncoding::Utf16
+                }
+            ),
+            Some(Location::Position {
+                line: 2,
+                col: 19,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+        assert_eq!(
+            resolve(
+                content,
+                "rs",
+                Location::Range {
+                    start: (2, 5),
+                    end: (2, 0),
+                    encoding: ColumnEncoding::Char
+                }
+            ),
+            Some(Location::Range {
+                start: (2, 5),
+                end: (2, 0),
+                encoding: ColumnEncoding::Byte
+            })
+        );
+    }
+
+    #[test]
+    fn test_offsets_of_non_utf8_files_stay_raw() {
+        // latin-1 "é" is a single invalid byte, decoding it would make it 3 bytes long
+        let content = b"let caf\xe9 = compute(1);\n";
+        let at_compute = Some(Location::Position {
+            line: 1,
+            col: 12,
+            encoding: ColumnEncoding::Byte,
+        });
+        let resolve = |location: Location| resolve_in_content(content, "rs", &location).unwrap();
+
+        assert_eq!(
+            resolve(Location::Pattern("compute".to_string())),
+            at_compute
+        );
+        assert_eq!(resolve(Location::Symbol("compute".to_string())), at_compute);
+        assert_eq!(resolve(Location::ByteOffset(11)), at_compute);
+        assert_eq!(
+            resolve(Location::Position {
+                line: 1,
+                col: 12,
+                encoding: ColumnEncoding::Utf16
+            }),
+            at_compute
+        );
+        assert_eq!(
+            resolve(Location::ByteOffset(7)),
+            Some(Location::Position {
+                line: 1,
+                col: 8,
+                encoding: ColumnEncoding::Byte
+            })
+        );
+    }
+}
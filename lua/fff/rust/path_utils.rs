@@ -1,8 +1,18 @@
+use std::path::{Component, Path, PathBuf};
+
 pub fn calculate_distance_penalty(current_file: Option<&str>, candidate_path: &str) -> i32 {
-    let Some(ref current_path) = current_file else {
+    let Some(current_path) = current_file else {
         return 0; // No penalty if no current file
     };
 
+    let penalty = -(directory_distance(current_path, candidate_path) as i32);
+
+    penalty.max(-20)
+}
+
+/// Number of directories between the directory of the current file and its closest common
+/// ancestor with the candidate, the distance penalty is one point per directory
+pub fn directory_distance(current_path: &str, candidate_path: &str) -> usize {
     let current_dir = if let Some(parent) = std::path::Path::new(current_path).parent() {
         parent.to_string_lossy().to_string()
     } else {
@@ -16,7 +26,7 @@ pub fn calculate_distance_penalty(current_file: Option<&str>, candidate_path: &s
     };
 
     if current_dir == candidate_dir {
-        return 0; // Same directory, no penalty
+        return 0; // Same directory
     }
 
     let current_parts: Vec<&str> = current_dir
@@ -34,21 +44,86 @@ pub fn calculate_distance_penalty(current_file: Option<&str>, candidate_path: &s
         .take_while(|(a, b)| a == b)
         .count();
 
-    let current_depth_from_common = current_parts.len() - common_len;
+    // 0 when the current file is at the common ancestor level
+    current_parts.len() - common_len
+}
 
-    if current_depth_from_common == 0 {
-        return 0; // Current file is at the common ancestor level
+/// Path shown for a file outside of the indexed roots, relative to the home directory when
+/// it's below it
+pub fn display_path(path: &Path) -> String {
+    let home_dir = std::env::var_os("HOME");
+    match home_dir
+        .as_deref()
+        .and_then(|home_dir| path.strip_prefix(home_dir).ok())
+    {
+        Some(home_relative) => Path::new("~").join(home_relative),
+        None => path.to_path_buf(),
     }
+    .to_string_lossy()
+    .into_owned()
+}
 
-    let penalty = -(current_depth_from_common as i32);
+/// Absolute path of a file given relative to the project or absolute, the way the index of
+/// the project stores it: `.` and `..` are resolved and a path reaching the project through
+/// a symlink is moved back under the `base_path`. Files which don't exist anymore are only
+/// resolved lexically.
+pub fn resolve_project_path(base_path: &Path, path: &Path) -> PathBuf {
+    let path = normalize_lexically(&base_path.join(path));
+    match (path.canonicalize(), base_path.canonicalize()) {
+        (Ok(real_path), Ok(real_base_path)) => match real_path.strip_prefix(&real_base_path) {
+            Ok(relative_path) => base_path.join(relative_path),
+            Err(_) => real_path,
+        },
+        _ => path,
+    }
+}
 
-    penalty.max(-20)
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_resolve_project_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("project");
+        std::fs::create_dir_all(base_path.join("src")).unwrap();
+        std::fs::write(base_path.join("src/main.rs"), "").unwrap();
+        std::os::unix::fs::symlink(&base_path, dir.path().join("link")).unwrap();
+
+        let main = base_path.join("src/main.rs");
+        assert_eq!(
+            resolve_project_path(&base_path, Path::new("src/main.rs")),
+            main
+        );
+        assert_eq!(
+            resolve_project_path(&base_path, Path::new("./src/../src/main.rs")),
+            main
+        );
+        assert_eq!(
+            resolve_project_path(&base_path, &dir.path().join("link/src/main.rs")),
+            main
+        );
+        assert_eq!(
+            resolve_project_path(&base_path, Path::new("gone/../deleted.rs")),
+            base_path.join("deleted.rs")
+        );
+    }
+
     #[test]
     #[cfg(not(target_family = "windows"))]
     fn test_calculate_distance_penalty() {
@@ -0,0 +1,238 @@
+use crate::error::Error;
+use crate::file_picker::{FilePicker, SharedPicker};
+use crate::scan_config::ScanConfig;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::info;
+
+/// How many indexes are kept warm when no limit is configured
+pub const DEFAULT_MAX_PICKERS: usize = 4;
+
+/// Pickers keyed by their base path. The Lua side refers to a picker by its base path (the
+/// "handle"), functions called without a handle use the most recently activated picker. The
+/// results of a search are not looked up by a handle: they belong to the search which already
+/// named its picker, see `get_results`.
+///
+/// Every lookup counts as a use, the least recently used pickers over the limit are dropped.
+#[derive(Debug)]
+pub struct PickerRegistry {
+    /// In the order they were activated, the active picker last
+    pickers: Vec<RegisteredPicker>,
+    max_pickers: usize,
+    /// Source of the `last_used` ticks, lookups only hold the registry read lock
+    clock: AtomicU64,
+}
+
+#[derive(Debug)]
+struct RegisteredPicker {
+    /// Canonical base path, resolved once when the picker is activated
+    key: PathBuf,
+    /// Base path as it was activated, usually the handle Lua passes back
+    base_path: PathBuf,
+    picker: SharedPicker,
+    last_used: AtomicU64,
+}
+
+impl RegisteredPicker {
+    /// The handle is compared as it is first, it is only resolved when it doesn't match as is
+    fn is_handle(&self, handle: &Path) -> bool {
+        handle == self.base_path || handle == self.key
+    }
+}
+
+impl Default for PickerRegistry {
+    fn default() -> Self {
+        Self {
+            pickers: Vec::new(),
+            max_pickers: DEFAULT_MAX_PICKERS,
+            clock: AtomicU64::new(0),
+        }
+    }
+}
+
+impl PickerRegistry {
+    pub fn set_max_pickers(&mut self, max_pickers: usize) {
+        self.max_pickers = max_pickers.max(1);
+        self.evict();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pickers.is_empty()
+    }
+
+    /// Canonical base paths in the order the pickers were activated
+    pub fn handles(&self) -> Vec<&Path> {
+        self.pickers
+            .iter()
+            .map(|registered| registered.key.as_path())
+            .collect()
+    }
+
+    pub fn pickers(&self) -> impl Iterator<Item = &SharedPicker> {
+        self.pickers.iter().map(|registered| &registered.picker)
+    }
+
+    /// Picker of the handle or the active one, marks it as recently used
+    pub fn get(&self, handle: Option<&str>) -> Option<SharedPicker> {
+        let registered = match handle {
+            Some(handle) => &self.pickers[self.position(Path::new(handle))?],
+            None => self.pickers.last()?,
+        };
+
+        self.touch(registered);
+        Some(Arc::clone(&registered.picker))
+    }
+
+    /// Makes the picker of the path active creating it if there is none. Returns the picker and
+    /// whether it was created. The least recently used pickers over the limit are dropped
+    /// which stops their watchers.
+    pub fn activate(
+        &mut self,
+        base_path: &Path,
+        scan_config: ScanConfig,
+    ) -> Result<(SharedPicker, bool), Error> {
+        if let Some(index) = self.position(base_path) {
+            let registered = self.pickers.remove(index);
+            self.touch(&registered);
+            let picker = Arc::clone(&registered.picker);
+            self.pickers.push(registered);
+            return Ok((picker, false));
+        }
+
+        let picker =
+            FilePicker::new_with_config(base_path.to_string_lossy().to_string(), scan_config)?;
+        let registered = RegisteredPicker {
+            key: canonical_path(base_path),
+            base_path: base_path.to_path_buf(),
+            picker: Arc::clone(&picker),
+            last_used: AtomicU64::new(0),
+        };
+        self.touch(&registered);
+        self.pickers.push(registered);
+        self.evict();
+
+        Ok((picker, true))
+    }
+
+    pub fn remove(&mut self, handle: Option<&str>) -> Option<SharedPicker> {
+        let index = match handle {
+            Some(handle) => self.position(Path::new(handle))?,
+            None => self.pickers.len().checked_sub(1)?,
+        };
+
+        Some(self.pickers.remove(index).picker)
+    }
+
+    pub fn clear(&mut self) -> usize {
+        let count = self.pickers.len();
+        self.pickers.clear();
+        count
+    }
+
+    fn position(&self, handle: &Path) -> Option<usize> {
+        self.pickers
+            .iter()
+            .position(|registered| registered.is_handle(handle))
+            .or_else(|| {
+                let key = canonical_path(handle);
+                self.pickers
+                    .iter()
+                    .position(|registered| registered.key == key)
+            })
+    }
+
+    fn touch(&self, registered: &RegisteredPicker) {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        registered.last_used.fetch_max(tick, Ordering::Relaxed);
+    }
+
+    fn evict(&mut self) {
+        while self.pickers.len() > self.max_pickers {
+            let Some(index) = self
+                .pickers
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, registered)| registered.last_used.load(Ordering::Relaxed))
+                .map(|(index, _)| index)
+            else {
+                break;
+            };
+
+            let registered = self.pickers.remove(index);
+            info!(
+                "Dropping least recently used picker: {}",
+                registered.key.display()
+            );
+        }
+    }
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_keeps_recent_pickers() {
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let mut registry = PickerRegistry::default();
+        registry.set_max_pickers(2);
+
+        let (first, created) = registry
+            .activate(dirs[0].path(), ScanConfig::default())
+            .unwrap();
+        assert!(created);
+        registry
+            .activate(dirs[1].path(), ScanConfig::default())
+            .unwrap();
+
+        // switching back reuses the warm index and marks it as recently used
+        let (again, created) = registry
+            .activate(dirs[0].path(), ScanConfig::default())
+            .unwrap();
+        assert!(!created);
+        assert!(Arc::ptr_eq(&first, &again));
+
+        registry
+            .activate(dirs[2].path(), ScanConfig::default())
+            .unwrap();
+        let handle = |index: usize| dirs[index].path().canonicalize().unwrap();
+        assert_eq!(registry.handles(), vec![handle(0), handle(2)]);
+        assert!(registry.get(Some(&handle(1).to_string_lossy())).is_none());
+
+        let active = registry.get(None).unwrap();
+        assert_eq!(active.read().unwrap().base_path(), dirs[2].path());
+    }
+
+    #[test]
+    fn test_lookups_by_handle_count_as_use() {
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let mut registry = PickerRegistry::default();
+        registry.set_max_pickers(2);
+
+        for dir in &dirs[..2] {
+            registry
+                .activate(dir.path(), ScanConfig::default())
+                .unwrap();
+        }
+        // the first picker is searched by its handle while the second one is active
+        let handle = dirs[0].path().to_string_lossy();
+        assert!(registry.get(Some(&handle)).is_some());
+
+        registry
+            .activate(dirs[2].path(), ScanConfig::default())
+            .unwrap();
+        let canonical = |index: usize| dirs[index].path().canonicalize().unwrap();
+        assert_eq!(registry.handles(), vec![canonical(0), canonical(2)]);
+        assert!(registry.get(Some(&handle)).is_some());
+        assert!(
+            registry
+                .get(Some(&canonical(0).to_string_lossy()))
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::file_picker::{FileSnapshot, WeakSnapshot};
+use crate::location::Location;
+use crate::sort_buffer::sort_with_buffer;
+use crate::types::{FileItem, FileSource, Score, SearchResult};
+use std::cmp::Ordering;
+use std::sync::{Arc, Weak};
+
+/// A single match addressed by its snapshot and the position of the file in it
+#[derive(Debug, Clone)]
+struct RankedMatch {
+    source: u32,
+    index: u32,
+    score: Score,
+}
+
+/// Every match of a search kept in rank order so the UI can page through all of them without
+/// searching again. Only the first page is sorted right away, the rest is sorted on the first
+/// request past it.
+///
+/// The searched snapshots are held only while the pages are read, see `release_snapshots`.
+/// Kept results refer to them weakly and are stale once the index replaced any of them: the
+/// matches are positions in the snapshots, the search has to run again.
+#[derive(Debug)]
+pub struct RankedResults {
+    /// Empty after `release_snapshots`
+    sources: Vec<FileSnapshot>,
+    snapshots: Vec<WeakSnapshot>,
+    /// Where the files of every snapshot come from, in the same order as `snapshots`
+    source_kinds: Vec<FileSource>,
+    matches: Vec<RankedMatch>,
+    /// Length of the best-first prefix of `matches`, the rest is unordered
+    sorted_len: usize,
+    total_files: usize,
+    reverse_order: bool,
+    location: Option<Location>,
+}
+
+impl RankedResults {
+    /// `matches` are `(source, file index, score)` in any order
+    pub fn new(
+        sources: Vec<(FileSource, FileSnapshot)>,
+        matches: Vec<(u32, u32, Score)>,
+        first_page: usize,
+        reverse_order: bool,
+        location: Option<Location>,
+    ) -> Self {
+        let (source_kinds, sources): (_, Vec<FileSnapshot>) = sources.into_iter().unzip();
+        let mut results = Self {
+            snapshots: sources.iter().map(Arc::downgrade).collect(),
+            source_kinds,
+            matches: matches
+                .into_iter()
+                .map(|(source, index, score)| RankedMatch {
+                    source,
+                    index,
+                    score,
+                })
+                .collect(),
+            sorted_len: 0,
+            total_files: sources.iter().map(|files| files.len()).sum(),
+            reverse_order,
+            location,
+            sources,
+        };
+        results.sort_first(first_page);
+        results
+    }
+
+    /// Drops the searched snapshots so the kept results don't pin the index: a writer would
+    /// copy the files held by them. `hold_snapshots` before reading the next page.
+    pub fn release_snapshots(&mut self) {
+        self.sources.clear();
+    }
+
+    /// Takes the searched snapshots back, false if the index replaced any of them
+    pub fn hold_snapshots(&mut self) -> bool {
+        if self.sources.len() == self.snapshots.len() {
+            return true;
+        }
+
+        match self.snapshots.iter().map(Weak::upgrade).collect() {
+            Some(sources) => {
+                self.sources = sources;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn total_matched(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn total_files(&self) -> usize {
+        self.total_files
+    }
+
+    /// Matches ranked `offset..offset + count`, 0 being the best one. With `reverse_order`
+    /// every page is reversed the same way as the first one, worst match first. The snapshots
+    /// have to be held.
+    pub fn page(&mut self, offset: usize, count: usize) -> SearchResult<'_> {
+        debug_assert_eq!(self.sources.len(), self.snapshots.len());
+        let end = offset.saturating_add(count).min(self.matches.len());
+        let offset = offset.min(end);
+        if end > self.sorted_len {
+            let sources = &self.sources;
+            sort_with_buffer(&mut self.matches[self.sorted_len..], |a, b| {
+                compare_rank(sources, a, b)
+            });
+            self.sorted_len = self.matches.len();
+        }
+
+        let mut items: Vec<&FileItem> = Vec::with_capacity(end - offset);
+        let mut sources = Vec::with_capacity(end - offset);
+        let mut scores: Vec<Score> = Vec::with_capacity(end - offset);
+        for ranked in &self.matches[offset..end] {
+            items.push(self.file(ranked));
+            sources.push(self.source_kinds[ranked.source as usize]);
+            scores.push(ranked.score.clone());
+        }
+        if self.reverse_order {
+            items.reverse();
+            sources.reverse();
+            scores.reverse();
+        }
+
+        SearchResult {
+            items,
+            sources,
+            scores,
+            total_matched: self.total_matched(),
+            total_files: self.total_files(),
+            location: self.location.clone(),
+        }
+    }
+
+    fn file(&self, ranked: &RankedMatch) -> &FileItem {
+        &self.sources[ranked.source as usize][ranked.index as usize]
+    }
+
+    /// Same partial sort as `sort_and_truncate`: select the first page out of a large result
+    /// set and only sort that
+    fn sort_first(&mut self, count: usize) {
+        let count = count.min(self.matches.len());
+        if count == 0 {
+            return;
+        }
+
+        let sources = &self.sources;
+        let compare = |a: &RankedMatch, b: &RankedMatch| compare_rank(sources, a, b);
+        if self.matches.len() > count * 2 {
+            self.matches.select_nth_unstable_by(count - 1, compare);
+            sort_with_buffer(&mut self.matches[..count], compare);
+            self.sorted_len = count;
+        } else {
+            sort_with_buffer(&mut self.matches, compare);
+            self.sorted_len = self.matches.len();
+        }
+    }
+}
+
+/// Best match first, ties are resolved by the most recently modified file
+fn compare_rank(sources: &[FileSnapshot], a: &RankedMatch, b: &RankedMatch) -> Ordering {
+    let modified =
+        |ranked: &RankedMatch| sources[ranked.source as usize][ranked.index as usize].modified;
+    b.score
+        .total
+        .cmp(&a.score.total)
+        .then_with(|| modified(b).cmp(&modified(a)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(path: &str, modified: u64) -> Arc<FileItem> {
+        Arc::new(FileItem::from_parts(
+            PathBuf::from(path),
+            path.to_string(),
+            path.to_string(),
+            0,
+            modified,
+            None,
+        ))
+    }
+
+    fn score(total: i32) -> Score {
+        Score {
+            total,
+            base_score: total,
+            filename_bonus: 0,
+            special_filename_bonus: 0,
+            frecency_boost: 0,
+            distance_penalty: 0,
+            current_file_penalty: 0,
+            ignored_penalty: 0,
+            pin_bonus: 0,
+            related_bonus: 0,
+            co_access_boost: 0,
+            exact_match: false,
+            match_type: "test",
+        }
+    }
+
+    fn paths(result: &SearchResult) -> Vec<String> {
+        result
+            .items
+            .iter()
+            .map(|file| file.relative_path.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_pages_follow_rank_across_sources() {
+        let tracked: FileSnapshot = Arc::new((0..6).map(|i| file(&format!("t{i}"), i)).collect());
+        let ignored: FileSnapshot = Arc::new(vec![file("i0", 0), file("i1", 0)]);
+        // tracked files rank by index, "t2" and "i1" tie and the more recently modified wins
+        let matches = (0..6)
+            .map(|i| (0, i, score(100 - i as i32 * 10)))
+            .chain([(1, 0, score(5)), (1, 1, score(80))])
+            .collect();
+
+        let sources = vec![(FileSource::Index, tracked), (FileSource::Ignored, ignored)];
+        let mut results = RankedResults::new(sources, matches, 2, false, None);
+        assert_eq!(results.total_matched(), 8);
+        assert_eq!(results.total_files(), 8);
+
+        assert_eq!(paths(&results.page(0, 2)), vec!["t0", "t1"]);
+        let page = results.page(2, 3);
+        assert_eq!(paths(&page), vec!["t2", "i1", "t3"]);
+        assert_eq!(page.sources[1], FileSource::Ignored);
+        assert_eq!(paths(&results.page(6, 10)), vec!["t5", "i0"]);
+        assert!(results.page(20, 10).items.is_empty());
+    }
+
+    #[test]
+    fn test_reversed_pages_keep_display_order() {
+        let files: FileSnapshot = Arc::new((0..5).map(|i| file(&format!("f{i}"), 0)).collect());
+        let matches = (0..5).map(|i| (0, i, score(50 - i as i32))).collect();
+
+        let mut results =
+            RankedResults::new(vec![(FileSource::Index, files)], matches, 2, true, None);
+        assert_eq!(paths(&results.page(0, 2)), vec!["f1", "f0"]);
+        assert_eq!(paths(&results.page(2, 2)), vec!["f3", "f2"]);
+    }
+
+    #[test]
+    fn test_released_results_go_stale_with_the_index() {
+        let mut files: FileSnapshot = Arc::new((0..3).map(|i| file(&format!("f{i}"), 0)).collect());
+        let matches = (0..3).map(|i| (0, i, score(50 - i as i32))).collect();
+        let mut results = RankedResults::new(
+            vec![(FileSource::Index, Arc::clone(&files))],
+            matches,
+            1,
+            false,
+            None,
+        );
+
+        results.release_snapshots();
+        assert_eq!(Arc::strong_count(&files), 1);
+        assert!(results.hold_snapshots());
+        assert_eq!(paths(&results.page(1, 2)), vec!["f1", "f2"]);
+
+        // the only holder of the snapshot moves it to a new allocation on update
+        results.release_snapshots();
+        Arc::make_mut(&mut files).remove(0);
+        assert!(!results.hold_snapshots());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::Error;
+use crate::file_picker::FilePicker;
+use crate::frecency::FrecencyTracker;
+use crate::path_utils::display_path;
+use crate::score::score_candidates;
+use crate::search_cache::IndexedPaths;
+use crate::sort_buffer::sort_with_buffer;
+use crate::types::{FileItem, FileSource, SearchOptions, SearchResult};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Files accessed in any project within the frecency history, most frecent first. They are
+/// shown relative to the home directory as they don't belong to a picker root.
+pub fn load_recent_files(tracker: &FrecencyTracker) -> Result<Vec<Arc<FileItem>>, Error> {
+    let files = tracker
+        .recent_files()?
+        .into_iter()
+        .map(|recent| {
+            let relative_path = display_path(&recent.path);
+            let file = FileItem::with_relative_path(recent.path, relative_path, None);
+            file.set_frecency_scores(recent.access_score, 0);
+            Arc::new(file)
+        })
+        .collect();
+
+    Ok(files)
+}
+
+/// Fuzzy matches the recent files ranking them by the frecency first, the match score only
+/// orders files accessed about as often. `files` are expected in the order of
+/// `load_recent_files`, which also breaks the remaining ties. The `indexed_paths` must be built
+/// from the `files`.
+pub fn fuzzy_search_recent<'a>(
+    files: &'a [Arc<FileItem>],
+    indexed_paths: &IndexedPaths,
+    query: &'a str,
+    max_results: usize,
+    max_threads: usize,
+    reverse_order: bool,
+) -> SearchResult<'a> {
+    let options = SearchOptions {
+        max_results,
+        max_threads,
+        reverse_order,
+        ..Default::default()
+    };
+    let (context, location) =
+        FilePicker::scoring_context(query, &options, &[files], Some(indexed_paths));
+
+    let time = std::time::Instant::now();
+    let (mut matches, _) = score_candidates(files, None, &context);
+    let total_matched = matches.len();
+    sort_with_buffer(&mut matches, |(a_index, a_score), (b_index, b_score)| {
+        let (a, b) = (&files[*a_index as usize], &files[*b_index as usize]);
+        b.access_frecency_score()
+            .cmp(&a.access_frecency_score())
+            .then_with(|| b_score.total.cmp(&a_score.total))
+            .then_with(|| a_index.cmp(b_index))
+    });
+    matches.truncate(max_results);
+    if reverse_order {
+        matches.reverse();
+    }
+    debug!(?query, completed_in = ?time.elapsed(), "Recent files search completed");
+
+    let (items, scores): (Vec<&FileItem>, Vec<_>) = matches
+        .into_iter()
+        .map(|(index, score)| (files[index as usize].as_ref(), score))
+        .unzip();
+
+    SearchResult {
+        sources: vec![FileSource::Recent; items.len()],
+        items,
+        scores,
+        total_matched,
+        total_files: files.len(),
+        location,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frecency_ranks_before_match_quality() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracker = FrecencyTracker::new(dir.path().join("db").to_str().unwrap(), true).unwrap();
+
+        let exact = dir.path().join("config.rs");
+        let frequent = dir.path().join("app_config_loader.rs");
+        let unrelated = dir.path().join("main.rs");
+        for path in [&exact, &frequent, &unrelated] {
+            std::fs::write(path, "").unwrap();
+            tracker.track_access(path).unwrap();
+        }
+        for _ in 0..3 {
+            tracker.track_access(&frequent).unwrap();
+        }
+
+        let files = load_recent_files(&tracker).unwrap();
+        let indexed_paths = IndexedPaths::new(&[&files]);
+        let result = fuzzy_search_recent(&files, &indexed_paths, "config", 10, 1, false);
+        let paths: Vec<_> = result.items.iter().map(|file| &file.path).collect();
+        assert_eq!(paths, vec![&frequent, &exact]);
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.sources, vec![FileSource::Recent; 2]);
+    }
+}
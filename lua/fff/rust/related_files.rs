@@ -0,0 +1,256 @@
+use crate::path_utils::directory_distance;
+use crate::types::FileItem;
+use mlua::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Naming rules telling which files belong together, e.g. `user_service.rs` with
+/// `user_service_test.rs` or `button.h` with `button.cpp`.
+///
+/// Two files are related when their names reduce to the same base name and their extensions
+/// form one of the `extension_pairs`, or only one of them is a test of the same language: the
+/// same extension or two extensions of the same group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelatedRules {
+    /// File names of tests without the extension, `{}` stands for the name of the tested file
+    pub test_patterns: Vec<String>,
+    /// Groups of extensions related to each other, e.g. headers and sources
+    pub extension_pairs: Vec<(Vec<String>, Vec<String>)>,
+}
+
+impl Default for RelatedRules {
+    fn default() -> Self {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        Self {
+            test_patterns: strings(&[
+                "{}_test", "{}_tests", "test_{}", "{}.test", "{}.spec", "{}_spec", "{}Test",
+                "{}Tests", "{}Spec",
+            ]),
+            extension_pairs: vec![
+                (
+                    strings(&["h", "hh", "hpp", "hxx"]),
+                    strings(&["c", "cc", "cpp", "cxx", "m", "mm"]),
+                ),
+                (
+                    strings(&["js", "jsx", "ts", "tsx", "vue", "svelte"]),
+                    strings(&["css", "scss", "sass", "less", "styl"]),
+                ),
+            ],
+        }
+    }
+}
+
+/// The parts of a file name the rules compare
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NameKey {
+    /// Lowercase name without the test affixes and the extensions
+    base: String,
+    is_test: bool,
+    extension: String,
+}
+
+impl RelatedRules {
+    fn name_key(&self, file_name: &str) -> Option<NameKey> {
+        let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+        let test_base = self.test_patterns.iter().find_map(|pattern| {
+            let (prefix, suffix) = pattern.split_once("{}")?;
+            stem.strip_prefix(prefix)?
+                .strip_suffix(suffix)
+                .filter(|base| !base.is_empty())
+        });
+
+        // `button.module.css` pairs with `button.tsx`
+        let base = test_base.unwrap_or(stem);
+        let base = base.split('.').next().unwrap_or(base);
+        if base.is_empty() {
+            return None;
+        }
+
+        Some(NameKey {
+            base: base.to_lowercase(),
+            is_test: test_base.is_some(),
+            extension: extension.to_lowercase(),
+        })
+    }
+
+    fn is_same_language(&self, a: &str, b: &str) -> bool {
+        a == b
+            || self
+                .extension_pairs
+                .iter()
+                .flat_map(|(left, right)| [left, right])
+                .any(|group| group.iter().any(|e| e == a) && group.iter().any(|e| e == b))
+    }
+
+    fn are_paired_extensions(&self, a: &str, b: &str) -> bool {
+        let contains = |group: &[String], extension: &str| group.iter().any(|e| e == extension);
+        self.extension_pairs.iter().any(|(left, right)| {
+            (contains(left, a) && contains(right, b)) || (contains(left, b) && contains(right, a))
+        })
+    }
+}
+
+/// Tells whether files are related to the current file, built once per search
+#[derive(Debug, Clone)]
+pub struct RelatedMatcher<'r> {
+    rules: &'r RelatedRules,
+    current_file: String,
+    current: NameKey,
+}
+
+impl<'r> RelatedMatcher<'r> {
+    /// `None` when the current file name has nothing to match on
+    pub fn new(rules: &'r RelatedRules, current_file: &str) -> Option<Self> {
+        let file_name = Path::new(current_file).file_name()?.to_str()?;
+        let current = rules.name_key(file_name)?;
+        Some(Self {
+            rules,
+            current_file: current_file.to_string(),
+            current,
+        })
+    }
+
+    pub fn is_related(&self, file: &FileItem) -> bool {
+        if file.relative_path == self.current_file {
+            return false;
+        }
+
+        // cheap check first, the base name is always a part of a related file name
+        if !file.file_name_lower.contains(&self.current.base) {
+            return false;
+        }
+
+        let (rules, current) = (self.rules, &self.current);
+        rules.name_key(&file.file_name).is_some_and(|key| {
+            key.base == current.base
+                && ((key.is_test != current.is_test
+                    && rules.is_same_language(&key.extension, &current.extension))
+                    || rules.are_paired_extensions(&key.extension, &current.extension))
+        })
+    }
+}
+
+/// Files of the index related to the current file (relative to the base path), the closest
+/// directories first
+pub fn related_files<'a>(
+    files: &'a [Arc<FileItem>],
+    current_file: &str,
+    rules: &RelatedRules,
+) -> Vec<&'a FileItem> {
+    let Some(matcher) = RelatedMatcher::new(rules, current_file) else {
+        return Vec::new();
+    };
+
+    let mut related: Vec<&FileItem> = files
+        .iter()
+        .map(Arc::as_ref)
+        .filter(|file| matcher.is_related(file))
+        .collect();
+    related.sort_by_cached_key(|file| {
+        (
+            directory_distance(current_file, &file.relative_path),
+            file.relative_path.clone(),
+        )
+    });
+    related
+}
+
+impl FromLua for RelatedRules {
+    fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let table = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(table) => table,
+            other => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: other.type_name(),
+                    to: "RelatedRules".to_string(),
+                    message: Some("expected a table with related files rules".to_string()),
+                });
+            }
+        };
+
+        let default = Self::default();
+        Ok(Self {
+            test_patterns: table
+                .get::<Option<Vec<String>>>("test_patterns")?
+                .unwrap_or(default.test_patterns),
+            // `{ { 'h', 'hpp' }, { 'c', 'cpp' } }` pairs
+            extension_pairs: table
+                .get::<Option<Vec<[Vec<String>; 2]>>>("extension_pairs")?
+                .map(|pairs| {
+                    pairs
+                        .into_iter()
+                        .map(|[left, right]| (left, right))
+                        .collect()
+                })
+                .unwrap_or(default.extension_pairs),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(relative_path: &str) -> Arc<FileItem> {
+        let file_name = relative_path.rsplit('/').next().unwrap().to_string();
+        Arc::new(FileItem::from_parts(
+            PathBuf::from(relative_path),
+            relative_path.to_string(),
+            file_name,
+            0,
+            0,
+            None,
+        ))
+    }
+
+    fn related_paths(files: &[Arc<FileItem>], current_file: &str) -> Vec<String> {
+        related_files(files, current_file, &RelatedRules::default())
+            .iter()
+            .map(|file| file.relative_path.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_naming_rules() {
+        let files: Vec<Arc<FileItem>> = [
+            "src/user_service.rs",
+            "tests/user_service_test.rs",
+            "web/user_service.spec.ts",
+            "src/user_service_helpers.rs",
+            "lib/button.h",
+            "lib/button.cpp",
+            "lib/button_test.cpp",
+            "lib/other/button.h",
+            "ui/Button.tsx",
+            "ui/Button.module.css",
+            "ui/ButtonTest.tsx",
+            "src/a/mod.rs",
+            "src/b/mod.rs",
+        ]
+        .into_iter()
+        .map(file)
+        .collect();
+
+        // a test in another language tests another file of the same name
+        assert_eq!(
+            related_paths(&files, "src/user_service.rs"),
+            vec!["tests/user_service_test.rs"]
+        );
+        assert_eq!(
+            related_paths(&files, "tests/user_service_test.rs"),
+            vec!["src/user_service.rs"]
+        );
+        assert_eq!(
+            related_paths(&files, "lib/button.cpp"),
+            vec!["lib/button.h", "lib/button_test.cpp", "lib/other/button.h"]
+        );
+        assert_eq!(
+            related_paths(&files, "ui/Button.tsx"),
+            vec!["ui/Button.module.css", "ui/ButtonTest.tsx"]
+        );
+        // same names without a test or an extension pair are unrelated
+        assert!(related_paths(&files, "src/a/mod.rs").is_empty());
+    }
+}
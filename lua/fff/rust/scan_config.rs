@@ -0,0 +1,313 @@
+use crate::error::Error;
+use ignore::gitignore::Gitignore;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{Match, WalkBuilder};
+use mlua::prelude::*;
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+/// Project-local ignore file using the `.gitignore` syntax that affects only the picker
+pub const FFF_IGNORE_FILENAME: &str = ".fffignore";
+
+/// Picker specific indexing rules applied on top of the repository ignore files
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    /// Globs with the same semantics as ripgrep `--glob`: plain globs whitelist files (even the
+    /// gitignored ones) and when present exclude everything else, `!` prefixed globs exclude.
+    pub overrides: Vec<String>,
+    /// Index dot files and directories, specific ones can still be excluded with the overrides
+    pub hidden: bool,
+    /// Walk into symlinked directories, loops are detected and every file is indexed only once
+    pub follow_symlinks: bool,
+    /// Additional directories indexed next to the base path, e.g. a shared `proto/` checkout
+    pub roots: Vec<RootConfig>,
+    /// Directory of the persisted trigram indexes pruning the grepped files, `None` disables it
+    pub content_index: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootConfig {
+    pub path: PathBuf,
+    /// Prefix of the relative paths of this root, defaults to the directory name
+    pub label: Option<String>,
+}
+
+impl FromLua for RootConfig {
+    fn from_lua(value: LuaValue, lua: &Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Table(table) => Ok(Self {
+                path: expand_home(&table.get::<String>("path")?),
+                label: table.get::<Option<String>>("label")?,
+            }),
+            value => Ok(Self {
+                path: expand_home(&String::from_lua(value, lua)?),
+                label: None,
+            }),
+        }
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~") {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            match std::env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(rest.trim_start_matches(['/', '\\'])),
+                None => PathBuf::from(path),
+            }
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            overrides: Vec::new(),
+            hidden: true,
+            follow_symlinks: false,
+            roots: Vec::new(),
+            content_index: None,
+        }
+    }
+}
+
+impl FromLua for ScanConfig {
+    fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let table = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(table) => table,
+            other => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: other.type_name(),
+                    to: "ScanConfig".to_string(),
+                    message: Some("expected a table with indexing options".to_string()),
+                });
+            }
+        };
+
+        let default = Self::default();
+        Ok(Self {
+            overrides: table
+                .get::<Option<Vec<String>>>("overrides")?
+                .unwrap_or_default(),
+            hidden: table
+                .get::<Option<bool>>("hidden")?
+                .unwrap_or(default.hidden),
+            follow_symlinks: table
+                .get::<Option<bool>>("follow_symlinks")?
+                .unwrap_or(default.follow_symlinks),
+            roots: table
+                .get::<Option<Vec<RootConfig>>>("roots")?
+                .unwrap_or_default(),
+            // `false` disables the index the same way as nil
+            content_index: match table.get::<LuaValue>("content_index")? {
+                LuaValue::String(dir) => Some(expand_home(&dir.to_str()?)),
+                _ => None,
+            },
+        })
+    }
+}
+
+/// Compiled `ScanConfig` shared between the filesystem walker and the background watcher.
+///
+/// The walker reads every nested `.fffignore` on its own, the watcher only knows about the one
+/// in the root. The picker compiles the filter again when an ignore file changes.
+#[derive(Debug, Clone)]
+pub struct ScanFilter {
+    overrides: Override,
+    fff_ignore: Gitignore,
+    hidden: bool,
+    follow_symlinks: bool,
+}
+
+impl ScanFilter {
+    pub fn new(base_path: &Path, config: &ScanConfig) -> Result<Self, Error> {
+        let mut builder = OverrideBuilder::new(base_path);
+        for glob in &config.overrides {
+            builder.add(glob)?;
+        }
+
+        // a missing file is the common case, only the errors of an existing one are reported
+        let fff_ignore_path = base_path.join(FFF_IGNORE_FILENAME);
+        let fff_ignore = if fff_ignore_path.is_file() {
+            let (fff_ignore, error) = Gitignore::new(&fff_ignore_path);
+            if let Some(error) = error {
+                warn!(
+                    ?error,
+                    "Failed to parse {}, the invalid rules are skipped",
+                    fff_ignore_path.display()
+                );
+            }
+            fff_ignore
+        } else {
+            Gitignore::empty()
+        };
+
+        Ok(Self {
+            overrides: builder.build()?,
+            fff_ignore,
+            hidden: config.hidden,
+            follow_symlinks: config.follow_symlinks,
+        })
+    }
+
+    pub fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    pub fn configure_walker(&self, builder: &mut WalkBuilder) {
+        builder
+            .hidden(!self.hidden)
+            .follow_links(self.follow_symlinks)
+            .add_custom_ignore_filename(FFF_IGNORE_FILENAME);
+        if !self.overrides.is_empty() {
+            builder.overrides(self.overrides.clone());
+        }
+    }
+
+    /// Matches a single file the same way the walker would, `Match::None` means that the
+    /// decision is up to the repository ignore rules.
+    pub fn matched(&self, path: &Path) -> Match<()> {
+        if !self.overrides.is_empty() {
+            // directories excluded by the overrides are never entered by the walker
+            let root = self.overrides.path();
+            let excluded_dir = path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(root) && *dir != root)
+                .any(|dir| self.overrides.matched(dir, true).is_ignore());
+            if excluded_dir {
+                return Match::Ignore(());
+            }
+
+            match self.overrides.matched(path, false) {
+                Match::Ignore(_) => return Match::Ignore(()),
+                Match::Whitelist(_) => return Match::Whitelist(()),
+                Match::None => {}
+            }
+        }
+
+        if !self.fff_ignore.is_empty() && path.starts_with(self.fff_ignore.path()) {
+            match self.fff_ignore.matched_path_or_any_parents(path, false) {
+                Match::Ignore(_) => return Match::Ignore(()),
+                Match::Whitelist(_) => return Match::Whitelist(()),
+                Match::None => {}
+            }
+        }
+
+        if !self.hidden && self.is_hidden(path) {
+            return Match::Ignore(());
+        }
+
+        Match::None
+    }
+
+    /// Any dot component between the root and the file hides it from the walker
+    fn is_hidden(&self, path: &Path) -> bool {
+        path.strip_prefix(self.overrides.path())
+            .unwrap_or(path)
+            .components()
+            .any(|component| match component {
+                Component::Normal(name) => name.as_encoded_bytes().starts_with(b"."),
+                _ => false,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_filter(base_path: &Path, overrides: &[&str]) -> ScanFilter {
+        let config = ScanConfig {
+            overrides: overrides.iter().map(|glob| glob.to_string()).collect(),
+            ..Default::default()
+        };
+
+        ScanFilter::new(base_path, &config).unwrap()
+    }
+
+    #[test]
+    fn test_override_globs() {
+        let base_path = Path::new("/project");
+        let filter = build_filter(base_path, &["!*.snap", "!fixtures/**"]);
+
+        assert!(filter.matched(Path::new("/project/src/lib.rs")).is_none());
+        assert!(
+            filter
+                .matched(Path::new("/project/src/__snapshots__/a.snap"))
+                .is_ignore()
+        );
+        assert!(
+            filter
+                .matched(Path::new("/project/fixtures/data/a.json"))
+                .is_ignore()
+        );
+
+        // whitelisted globs take precedence over gitignore but exclude everything else
+        let filter = build_filter(base_path, &[".env"]);
+        assert!(filter.matched(Path::new("/project/.env")).is_whitelist());
+        assert!(filter.matched(Path::new("/project/src/lib.rs")).is_ignore());
+    }
+
+    #[test]
+    fn test_fffignore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(FFF_IGNORE_FILENAME),
+            "data/\n*.csv\n!keep.csv\n",
+        )
+        .unwrap();
+
+        let filter = build_filter(dir.path(), &[]);
+        assert!(filter.matched(&dir.path().join("src/main.rs")).is_none());
+        assert!(filter.matched(&dir.path().join("data/big.bin")).is_ignore());
+        assert!(filter.matched(&dir.path().join("report.csv")).is_ignore());
+        assert!(filter.matched(&dir.path().join("keep.csv")).is_whitelist());
+    }
+
+    #[test]
+    fn test_invalid_fffignore_rules_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(FFF_IGNORE_FILENAME), "data/{a\n*.csv\n").unwrap();
+
+        let filter = build_filter(dir.path(), &[]);
+        assert!(filter.matched(&dir.path().join("report.csv")).is_ignore());
+        assert!(filter.matched(&dir.path().join("data/{a")).is_none());
+    }
+
+    #[test]
+    fn test_hidden_files_policy() {
+        let base_path = Path::new("/dotfiles");
+        let config = ScanConfig {
+            overrides: vec!["!.cache/".to_string()],
+            ..Default::default()
+        };
+
+        let filter = ScanFilter::new(base_path, &config).unwrap();
+        assert!(filter.matched(Path::new("/dotfiles/.zshrc")).is_none());
+        assert!(
+            filter
+                .matched(Path::new("/dotfiles/.config/nvim/init.lua"))
+                .is_none()
+        );
+        assert!(
+            filter
+                .matched(Path::new("/dotfiles/.cache/nvim/log"))
+                .is_ignore()
+        );
+
+        let config = ScanConfig {
+            hidden: false,
+            ..Default::default()
+        };
+        let filter = ScanFilter::new(base_path, &config).unwrap();
+        assert!(filter.matched(Path::new("/dotfiles/.zshrc")).is_ignore());
+        assert!(
+            filter
+                .matched(Path::new("/dotfiles/.config/nvim/init.lua"))
+                .is_ignore()
+        );
+        assert!(filter.matched(Path::new("/dotfiles/src/main.rs")).is_none());
+    }
+}
@@ -1,24 +1,43 @@
 use std::path::MAIN_SEPARATOR;
+use std::sync::Arc;
 
 use crate::{
     git::is_modified_status,
     path_utils::calculate_distance_penalty,
     sort_buffer::{sort_by_key_with_buffer, sort_with_buffer},
-    types::{FileItem, Score, ScoringContext},
+    types::{DirItem, FileItem, Score, ScoringContext},
 };
+use git2::Status;
 use neo_frizbee::Scoring;
 use rayon::prelude::*;
 
 pub fn match_and_score_files<'a>(
-    files: &'a [FileItem],
+    files: &'a [Arc<FileItem>],
     context: &ScoringContext,
 ) -> (Vec<&'a FileItem>, Vec<Score>, usize) {
+    let (matches, _) = score_candidates(files, None, context);
+    let results = matches
+        .into_iter()
+        .map(|(index, score)| (files[index as usize].as_ref(), score))
+        .collect();
+    sort_and_truncate(results, context)
+}
+
+/// Scores the files at the `candidates` indices (all of them if `None`) and returns every
+/// match unsorted as the file index with its score. Also returns the sorted indices of the
+/// matched files, those are the only possible matches of any query extending this one.
+/// Short queries scored by frecency match everything and return `None`.
+pub fn score_candidates(
+    files: &[Arc<FileItem>],
+    candidates: Option<&[u32]>,
+    context: &ScoringContext,
+) -> (Vec<(u32, Score)>, Option<Vec<u32>>) {
     if context.query.len() < 2 {
-        return score_all_by_frecency(files, context);
+        return (score_all_by_frecency(files, context), None);
     }
 
-    if files.is_empty() {
-        return (vec![], vec![], 0);
+    if files.is_empty() || candidates.is_some_and(<[u32]>::is_empty) {
+        return (vec![], Some(vec![]));
     }
 
     let has_uppercase_letter = context.query.chars().any(|c| c.is_uppercase());
@@ -34,10 +53,21 @@ pub fn match_and_score_files<'a>(
     };
 
     let query_contains_path_separator = context.query.contains(MAIN_SEPARATOR);
-    let haystack: Vec<&str> = files
-        .iter()
-        .map(|f| f.relative_path_lower.as_str())
-        .collect();
+    let haystack: Vec<&str> = match candidates {
+        Some(candidates) => candidates
+            .iter()
+            .map(|&index| files[index as usize].relative_path_lower.as_str())
+            .collect(),
+        None => files
+            .iter()
+            .map(|f| f.relative_path_lower.as_str())
+            .collect(),
+    };
+    // matches are reported by the position in the haystack
+    let file_index = |haystack_index: u32| match candidates {
+        Some(candidates) => candidates[haystack_index as usize] as usize,
+        None => haystack_index as usize,
+    };
     tracing::debug!(
         "Starting fuzzy search for query '{}' in {} files",
         context.query,
@@ -58,10 +88,14 @@ pub fn match_and_score_files<'a>(
         .iter()
         .filter_map(|m| {
             files
-                .get(m.index as usize)
+                .get(file_index(m.index))
                 .map(|f| f.file_name_lower.as_str())
         })
         .collect();
+    let mut matched_indices: Vec<u32> = path_matches
+        .iter()
+        .map(|m| file_index(m.index) as u32)
+        .collect();
 
     // if there is a / in the query we don't even match filenames
     let filename_matches = if query_contains_path_separator {
@@ -95,13 +129,14 @@ pub fn match_and_score_files<'a>(
         .into_iter()
         .enumerate()
         .map(|(index, path_match)| {
-            let file_idx = path_match.index as usize;
-            let file = &files[file_idx];
+            let position = file_index(path_match.index);
+            let file = &files[position];
 
             let mut base_score = path_match.score as i32;
-            let frecency_boost = base_score.saturating_mul(file.total_frecency_score as i32) / 100;
+            let frecency_boost = base_score.saturating_mul(file.total_frecency_score() as i32) / 100;
             let distance_penalty =
-                calculate_distance_penalty(context.current_file, &file.relative_path);
+                calculate_distance_penalty(context.current_file, &file.relative_path)
+                    .saturating_add(calculate_other_root_penalty(file, base_score, context));
 
             let filename_match = filename_matches
                 .get(next_filename_match_index)
@@ -149,11 +184,21 @@ pub fn match_and_score_files<'a>(
                 tracing::debug!(file =?file.relative_path, ?current_file_penalty, "Applied penalty");
             }
 
+            let ignored_penalty = calculate_ignored_penalty(file, base_score);
+            // a pinned file counts its match twice
+            let pin_bonus = if file.is_pinned() { base_score } else { 0 };
+            let related_bonus = calculate_related_bonus(file, base_score, context);
+            // 2% of the match for every recent time the file was opened after the current one
+            let co_access_boost = base_score.saturating_mul(co_access_score(file, context)) / 50;
             let total = base_score
                 .saturating_add(frecency_boost)
                 .saturating_add(distance_penalty)
                 .saturating_add(filename_bonus)
-                .saturating_add(current_file_penalty);
+                .saturating_add(current_file_penalty)
+                .saturating_add(ignored_penalty)
+                .saturating_add(pin_bonus)
+                .saturating_add(related_bonus)
+                .saturating_add(co_access_boost);
 
             let score = Score {
                 total,
@@ -167,6 +212,10 @@ pub fn match_and_score_files<'a>(
                 },
                 frecency_boost,
                 distance_penalty,
+                ignored_penalty,
+                pin_bonus,
+                related_bonus,
+                co_access_boost,
                 exact_match: path_match.exact || filename_match.is_some_and(|m| m.exact),
                 match_type: match filename_match {
                     Some(filename_match) if filename_match.exact => "exact_filename",
@@ -175,11 +224,150 @@ pub fn match_and_score_files<'a>(
                 },
             };
 
-            (file, score)
+            (position as u32, score)
         })
         .collect();
 
-    sort_and_truncate(results, context)
+    // keep the refined haystack in the same order as the full one so ties resolve the same way
+    matched_indices.sort_unstable();
+    (results, Some(matched_indices))
+}
+
+/// Directories are matched by their relative path with the directory name taking the role of
+/// the file name, the frecency of the contained files boosts the score. Short queries are
+/// sorted by the frecency alone.
+pub fn match_and_score_dirs<'a>(
+    dirs: &'a [DirItem],
+    context: &ScoringContext,
+) -> (Vec<&'a DirItem>, Vec<Score>, usize) {
+    let results: Vec<(&DirItem, Score)> = if context.query.len() < 2 {
+        dirs.iter()
+            .map(|dir| {
+                let score = Score {
+                    total: dir.frecency_score as i32,
+                    base_score: 0,
+                    filename_bonus: 0,
+                    special_filename_bonus: 0,
+                    frecency_boost: dir.frecency_score as i32,
+                    distance_penalty: 0,
+                    current_file_penalty: 0,
+                    ignored_penalty: 0,
+                    pin_bonus: 0,
+                    related_bonus: 0,
+                    co_access_boost: 0,
+                    exact_match: false,
+                    match_type: "frecency",
+                };
+                (dir, score)
+            })
+            .collect()
+    } else {
+        score_matching_dirs(dirs, context)
+    };
+
+    let total_matched = results.len();
+    let mut results = results;
+    // on equal scores the shallower directory wins, then the alphabetical order
+    sort_with_buffer(&mut results, |a, b| {
+        b.1.total
+            .cmp(&a.1.total)
+            .then_with(|| a.0.relative_path.len().cmp(&b.0.relative_path.len()))
+            .then_with(|| a.0.relative_path.cmp(&b.0.relative_path))
+    });
+    results.truncate(context.max_results);
+    if context.reverse_order {
+        results.reverse();
+    }
+
+    let (items, scores) = results.into_iter().unzip();
+    (items, scores, total_matched)
+}
+
+fn score_matching_dirs<'a>(
+    dirs: &'a [DirItem],
+    context: &ScoringContext,
+) -> Vec<(&'a DirItem, Score)> {
+    let options = neo_frizbee::Config {
+        prefilter: true,
+        max_typos: Some(context.max_typos),
+        sort: false,
+        ..Default::default()
+    };
+
+    let haystack: Vec<&str> = dirs
+        .iter()
+        .map(|dir| dir.relative_path_lower.as_str())
+        .collect();
+    let path_matches = if haystack.len() > 1000 {
+        neo_frizbee::match_list_parallel(context.query, &haystack, &options, context.max_threads)
+    } else {
+        neo_frizbee::match_list(context.query, &haystack, &options)
+    };
+
+    let query_contains_path_separator = context.query.contains(MAIN_SEPARATOR);
+    let names: Vec<&str> = path_matches
+        .iter()
+        .map(|m| dirs[m.index as usize].dir_name_lower.as_str())
+        .collect();
+    let mut name_matches = if query_contains_path_separator {
+        vec![]
+    } else {
+        neo_frizbee::match_list(context.query, &names, &options)
+    };
+    sort_by_key_with_buffer(&mut name_matches, |m| m.index);
+
+    let mut next_name_match_index = 0;
+    path_matches
+        .into_iter()
+        .enumerate()
+        .map(|(index, path_match)| {
+            let dir = &dirs[path_match.index as usize];
+            let name_match = name_matches.get(next_name_match_index).and_then(|m| {
+                if m.index == index as u32 {
+                    next_name_match_index += 1;
+                    Some(m)
+                } else {
+                    None
+                }
+            });
+
+            // same bonuses as the file names get, see `score_candidates`
+            let mut base_score = path_match.score as i32;
+            let filename_bonus = match name_match {
+                Some(name_match) if name_match.exact => name_match.score as i32 / 5 * 2,
+                Some(name_match) if name_match.score >= path_match.score => {
+                    base_score = name_match.score as i32;
+                    (base_score / 6).min(30)
+                }
+                _ => 0,
+            };
+            let frecency_boost = base_score.saturating_mul(dir.frecency_score as i32) / 100;
+
+            let score = Score {
+                total: base_score
+                    .saturating_add(filename_bonus)
+                    .saturating_add(frecency_boost),
+                base_score,
+                filename_bonus,
+                special_filename_bonus: 0,
+                frecency_boost,
+                distance_penalty: 0,
+                current_file_penalty: 0,
+                ignored_penalty: 0,
+                pin_bonus: 0,
+                related_bonus: 0,
+                co_access_boost: 0,
+                exact_match: path_match.exact || name_match.is_some_and(|m| m.exact),
+                match_type: match name_match {
+                    Some(name_match) if name_match.exact => "exact_dirname",
+                    Some(_) => "fuzzy_dirname",
+                    None => "fuzzy_path",
+                },
+            };
+
+            (dir, score)
+        })
+        .collect()
 }
 
 /// Check if a filename is a special entry point file that deserves bonus scoring
@@ -207,19 +395,36 @@ fn is_special_entry_point_file(filename: &str) -> bool {
     )
 }
 
-fn score_all_by_frecency<'a>(
-    files: &'a [FileItem],
-    context: &ScoringContext,
-) -> (Vec<&'a FileItem>, Vec<Score>, usize) {
-    let results: Vec<_> = files
+/// Keeps the pinned files above any frecency score when the query is empty
+const PINNED_FRECENCY_BONUS: i32 = 1000;
+/// Puts the files related to the current one right after the pinned files for an empty query
+const RELATED_FRECENCY_BONUS: i32 = 100;
+
+fn score_all_by_frecency(files: &[Arc<FileItem>], context: &ScoringContext) -> Vec<(u32, Score)> {
+    files
         .par_iter()
-        .map(|file| {
-            let total_frecency_score = file.access_frecency_score as i32
-                + (file.modification_frecency_score as i32).saturating_mul(4);
+        .enumerate()
+        .map(|(index, file)| {
+            let total_frecency_score = file.access_frecency_score() as i32
+                + (file.modification_frecency_score() as i32).saturating_mul(4);
 
             let current_file_penalty =
                 calculate_current_file_penalty(file, total_frecency_score, context);
-            let total = total_frecency_score.saturating_add(current_file_penalty);
+            let ignored_penalty = calculate_ignored_penalty(file, total_frecency_score);
+            let pin_bonus = if file.is_pinned() {
+                PINNED_FRECENCY_BONUS
+            } else {
+                0
+            };
+            let related_bonus = calculate_related_bonus(file, RELATED_FRECENCY_BONUS * 2, context);
+            // weighted as the modification score, a file of the usual sequence beats older accesses
+            let co_access_boost = co_access_score(file, context).saturating_mul(4);
+            let total = total_frecency_score
+                .saturating_add(current_file_penalty)
+                .saturating_add(ignored_penalty)
+                .saturating_add(pin_bonus)
+                .saturating_add(related_bonus)
+                .saturating_add(co_access_boost);
 
             let score = Score {
                 total,
@@ -228,16 +433,53 @@ fn score_all_by_frecency<'a>(
                 distance_penalty: 0,
                 special_filename_bonus: 0,
                 current_file_penalty,
+                ignored_penalty,
+                pin_bonus,
+                related_bonus,
+                co_access_boost,
                 frecency_boost: total_frecency_score,
                 exact_match: false,
                 match_type: "frecency",
             };
 
-            (file, score)
+            (index as u32, score)
         })
-        .collect();
+        .collect()
+}
 
-    sort_and_truncate(results, context)
+/// Related files of the current file get half of the `score` while the query is too short to
+/// tell what the user is after, longer queries rank by the match alone
+fn calculate_related_bonus(file: &FileItem, score: i32, context: &ScoringContext) -> i32 {
+    match context.related {
+        Some(related) if context.query.len() < 4 && related.is_related(file) => score / 2,
+        _ => 0,
+    }
+}
+
+/// Decayed number of the times the file was opened right after the current one
+fn co_access_score(file: &FileItem, context: &ScoringContext) -> i32 {
+    context
+        .co_access
+        .filter(|co_access| !co_access.is_empty())
+        .and_then(|co_access| co_access.get(&file.path))
+        .map_or(0, |&score| score as i32)
+}
+
+/// Files of the other roots are penalized the same way as distant directories are
+fn calculate_other_root_penalty(file: &FileItem, base_score: i32, context: &ScoringContext) -> i32 {
+    let Some(current_root) = context.current_root else {
+        return 0;
+    };
+
+    let in_current_root = file
+        .relative_path
+        .strip_prefix(current_root)
+        .is_some_and(|rest| rest.starts_with(std::path::MAIN_SEPARATOR));
+    if in_current_root {
+        0
+    } else {
+        -(base_score * 5 / 100)
+    }
 }
 
 #[inline]
@@ -251,7 +493,7 @@ fn calculate_current_file_penalty(
     if let Some(current) = context.current_file
         && file.relative_path.as_str() == current
     {
-        penalty -= match file.git_status {
+        penalty -= match file.git_status() {
             Some(status) if is_modified_status(status) => base_score / 2,
             _ => base_score,
         };
@@ -262,9 +504,19 @@ fn calculate_current_file_penalty(
     penalty
 }
 
+/// Ignored files are searched only on demand and should rank below comparable tracked files,
+/// the penalty is at least 1 so they lose the tie even when nothing else is scored.
+#[inline]
+fn calculate_ignored_penalty(file: &FileItem, base_score: i32) -> i32 {
+    match file.git_status() {
+        Some(status) if status.contains(Status::IGNORED) => -(base_score / 2).max(1),
+        _ => 0,
+    }
+}
+
 /// Dynamically sorts and returns the top results either in ascending or descending order
 /// Uses partial sorting for large result sets to improve performance
-fn sort_and_truncate<'a>(
+pub fn sort_and_truncate<'a>(
     mut results: Vec<(&'a FileItem, Score)>,
     context: &ScoringContext,
 ) -> (Vec<&'a FileItem>, Vec<Score>, usize) {
@@ -331,22 +583,18 @@ fn sort_and_truncate<'a>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::related_files::{RelatedMatcher, RelatedRules};
     use std::path::PathBuf;
 
     fn create_test_file(path: &str, score: i32, modified: u64) -> (FileItem, Score) {
-        let file = FileItem {
-            path: PathBuf::from(path),
-            relative_path: path.to_string(),
-            relative_path_lower: path.to_lowercase(),
-            file_name: path.split('/').last().unwrap_or(path).to_string(),
-            file_name_lower: path.split('/').last().unwrap_or(path).to_lowercase(),
-            size: 0,
+        let file = FileItem::from_parts(
+            PathBuf::from(path),
+            path.to_string(),
+            path.split('/').next_back().unwrap_or(path).to_string(),
+            0,
             modified,
-            access_frecency_score: 0,
-            modification_frecency_score: 0,
-            total_frecency_score: 0,
-            git_status: None,
-        };
+            None,
+        );
         let score_obj = Score {
             total: score,
             base_score: score,
@@ -354,6 +602,10 @@ mod tests {
             distance_penalty: 0,
             special_filename_bonus: 0,
             current_file_penalty: 0,
+            ignored_penalty: 0,
+            pin_bonus: 0,
+            related_bonus: 0,
+            co_access_boost: 0,
             frecency_boost: 0,
             exact_match: false,
             match_type: "test",
@@ -389,6 +641,9 @@ mod tests {
             max_threads: 1,
             max_typos: 2,
             current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
             reverse_order: false,
         };
 
@@ -430,6 +685,9 @@ mod tests {
             max_threads: 1,
             max_typos: 2,
             current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
             reverse_order: false,
         };
 
@@ -464,6 +722,9 @@ mod tests {
             max_threads: 1,
             max_typos: 2,
             current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
             reverse_order: false,
         };
 
@@ -499,6 +760,9 @@ mod tests {
             max_threads: 1,
             max_typos: 2,
             current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
             reverse_order: true,
         };
 
@@ -514,4 +778,113 @@ mod tests {
         assert_eq!(items[1].relative_path, "file6.rs");
         assert_eq!(items[2].relative_path, "file4.rs");
     }
+
+    #[test]
+    fn test_pinned_files_rank_first() {
+        let (config, _) = create_test_file("src/config.rs", 0, 0);
+        let (loader, _) = create_test_file("src/config_loader.rs", 0, 0);
+        let (frequent, _) = create_test_file("src/main.rs", 0, 0);
+        loader.set_pinned(true);
+        frequent.set_frecency_scores(50, 0);
+        let files = vec![Arc::new(config), Arc::new(loader), Arc::new(frequent)];
+        let context = ScoringContext {
+            query: "config",
+            max_results: 10,
+            max_threads: 1,
+            max_typos: 2,
+            current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
+            reverse_order: false,
+        };
+
+        let (items, scores, _) = match_and_score_files(&files, &context);
+        assert_eq!(items[0].relative_path, "src/config_loader.rs");
+        assert_eq!(scores[0].pin_bonus, scores[0].base_score);
+        assert_eq!(scores[1].pin_bonus, 0);
+
+        let context = ScoringContext {
+            query: "",
+            ..context
+        };
+        let (items, _, _) = match_and_score_files(&files, &context);
+        assert_eq!(items[0].relative_path, "src/config_loader.rs");
+        assert_eq!(items[1].relative_path, "src/main.rs");
+    }
+
+    #[test]
+    fn test_related_files_boosted_for_short_queries() {
+        let (service, _) = create_test_file("src/user_service.rs", 0, 0);
+        let (test, _) = create_test_file("tests/user_service_test.rs", 0, 0);
+        let (frequent, _) = create_test_file("src/users.rs", 0, 0);
+        frequent.set_frecency_scores(50, 0);
+        let files = vec![Arc::new(service), Arc::new(test), Arc::new(frequent)];
+        let rules = RelatedRules::default();
+        let related = RelatedMatcher::new(&rules, "src/user_service.rs").unwrap();
+        let context = ScoringContext {
+            query: "",
+            max_results: 10,
+            max_threads: 1,
+            max_typos: 2,
+            current_file: Some("src/user_service.rs"),
+            current_root: None,
+            related: Some(&related),
+            co_access: None,
+            reverse_order: false,
+        };
+
+        let (items, scores, _) = match_and_score_files(&files, &context);
+        assert_eq!(items[0].relative_path, "tests/user_service_test.rs");
+        assert!(scores[0].related_bonus > 0);
+
+        // a longer query is ranked by the match alone
+        let context = ScoringContext {
+            query: "user_serv",
+            ..context
+        };
+        let (_, scores, _) = match_and_score_files(&files, &context);
+        assert!(scores.iter().all(|score| score.related_bonus == 0));
+    }
+
+    #[test]
+    fn test_dir_frecency_breaks_match_ties() {
+        let dir = |relative_path: &str, frecency_score: i64| DirItem {
+            path: relative_path.into(),
+            relative_path: relative_path.to_string(),
+            relative_path_lower: relative_path.to_lowercase(),
+            dir_name: relative_path.rsplit('/').next().unwrap().to_string(),
+            dir_name_lower: relative_path.rsplit('/').next().unwrap().to_lowercase(),
+            file_count: 1,
+            frecency_score,
+        };
+        let dirs = vec![dir("docs", 0), dir("a/utils", 0), dir("b/utils", 40)];
+        let context = ScoringContext {
+            query: "utils",
+            max_results: 10,
+            max_threads: 1,
+            max_typos: 2,
+            current_file: None,
+            current_root: None,
+            related: None,
+            co_access: None,
+            reverse_order: false,
+        };
+
+        let (items, scores, total) = match_and_score_dirs(&dirs, &context);
+        assert_eq!(total, 2);
+        assert_eq!(items[0].relative_path, "b/utils");
+        assert_eq!(items[1].relative_path, "a/utils");
+        assert_eq!(scores[0].match_type, "exact_dirname");
+        assert!(scores[0].frecency_boost > 0);
+
+        let context = ScoringContext {
+            query: "",
+            ..context
+        };
+        let (items, _, total) = match_and_score_dirs(&dirs, &context);
+        assert_eq!(total, 3);
+        assert_eq!(items[0].relative_path, "b/utils");
+        assert_eq!(items[1].relative_path, "docs");
+    }
 }
@@ -0,0 +1,224 @@
+use crate::file_picker::{FileSnapshot, WeakSnapshot};
+use crate::types::FileItem;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Matches of the previous query reused when the user keeps typing. A query extending the
+/// previous one can only match a subset of its matches, so only those are re-scored.
+///
+/// The cache is keyed by the identity of the searched snapshots: adding or removing files
+/// creates a new snapshot (or moves it to a new allocation) which invalidates it. Frecency
+/// and git status are updated in place, they don't change which files match.
+#[derive(Debug, Default)]
+pub struct SearchCache {
+    entry: Mutex<Option<CachedMatches>>,
+    indexed_paths: Mutex<Option<CachedPaths>>,
+}
+
+#[derive(Debug)]
+struct CachedPaths {
+    snapshots: Vec<WeakSnapshot>,
+    paths: Arc<IndexedPaths>,
+}
+
+/// Files of the searched snapshots by their name, checks whether a path typed in the query
+/// is indexed without walking every file on each keystroke
+#[derive(Debug, Default)]
+pub struct IndexedPaths {
+    /// Source and position of every file with the name
+    by_name: HashMap<String, Vec<(u32, u32)>>,
+}
+
+impl IndexedPaths {
+    pub fn new(sources: &[&[Arc<FileItem>]]) -> Self {
+        let mut by_name: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        for (source, files) in sources.iter().enumerate() {
+            for (index, file) in files.iter().enumerate() {
+                by_name
+                    .entry(file_name(&file.relative_path).to_string())
+                    .or_default()
+                    .push((source as u32, index as u32));
+            }
+        }
+
+        Self { by_name }
+    }
+
+    /// Whether a file of the `sources` the paths were built from has the `path` as its
+    /// relative path or its last components
+    pub fn contains(&self, sources: &[&[Arc<FileItem>]], path: &str) -> bool {
+        self.by_name.get(file_name(path)).is_some_and(|files| {
+            files.iter().any(|&(source, index)| {
+                is_path_suffix(
+                    &sources[source as usize][index as usize].relative_path,
+                    path,
+                )
+            })
+        })
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(std::path::is_separator).next().unwrap_or(path)
+}
+
+/// Whether the `path` is the relative path or its last components
+pub fn is_path_suffix(relative_path: &str, path: &str) -> bool {
+    relative_path
+        .strip_suffix(path)
+        .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with(std::path::is_separator))
+}
+
+#[derive(Debug)]
+struct CachedMatches {
+    snapshots: Vec<WeakSnapshot>,
+    query: String,
+    max_typos: u16,
+    /// Sorted indices of the matched files for every snapshot
+    matches: Vec<Vec<u32>>,
+}
+
+impl CachedMatches {
+    fn is_refined_by(&self, sources: &[&FileSnapshot], query: &str, max_typos: u16) -> bool {
+        // the typo budget grows with the query length, more typos can match new files
+        self.max_typos == max_typos
+            && query.starts_with(&self.query)
+            && is_same_snapshots(&self.snapshots, sources)
+    }
+}
+
+fn is_same_snapshots(cached: &[WeakSnapshot], sources: &[&FileSnapshot]) -> bool {
+    cached.len() == sources.len()
+        && cached
+            .iter()
+            .zip(sources)
+            .all(|(cached, source)| std::ptr::eq(cached.as_ptr(), Arc::as_ptr(source)))
+}
+
+impl SearchCache {
+    /// Candidates for the query if it extends the cached one over the same snapshots.
+    /// Anything else (backspace, an edit in the middle, index update) gives `None` and the
+    /// caller falls back to the full search.
+    pub fn candidates(
+        &self,
+        sources: &[&FileSnapshot],
+        query: &str,
+        max_typos: u16,
+    ) -> Option<Vec<Vec<u32>>> {
+        let mut entry = self.entry.lock().ok()?;
+        if entry
+            .as_ref()
+            .is_some_and(|cached| cached.is_refined_by(sources, query, max_typos))
+        {
+            // the caller stores the refined matches right after, no need to copy these
+            entry.take().map(|cached| cached.matches)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(
+        &self,
+        sources: &[&FileSnapshot],
+        query: &str,
+        max_typos: u16,
+        matches: Vec<Vec<u32>>,
+    ) {
+        if let Ok(mut entry) = self.entry.lock() {
+            *entry = Some(CachedMatches {
+                snapshots: sources
+                    .iter()
+                    .map(|source| Arc::downgrade(source))
+                    .collect(),
+                query: query.to_string(),
+                max_typos,
+                matches,
+            });
+        }
+    }
+
+    /// Paths of the snapshots, built once per snapshot the same way as the directories of
+    /// `DirIndex`
+    pub fn indexed_paths(&self, sources: &[&FileSnapshot]) -> Arc<IndexedPaths> {
+        let files: Vec<&[Arc<FileItem>]> = sources.iter().map(|files| files.as_slice()).collect();
+        let Ok(mut entry) = self.indexed_paths.lock() else {
+            return Arc::new(IndexedPaths::new(&files));
+        };
+
+        if let Some(cached) = entry.as_ref()
+            && is_same_snapshots(&cached.snapshots, sources)
+        {
+            return Arc::clone(&cached.paths);
+        }
+
+        let paths = Arc::new(IndexedPaths::new(&files));
+        *entry = Some(CachedPaths {
+            snapshots: sources
+                .iter()
+                .map(|source| Arc::downgrade(source))
+                .collect(),
+            paths: Arc::clone(&paths),
+        });
+        paths
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entry) = self.entry.lock() {
+            *entry = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_require_extended_query_and_same_snapshot() {
+        let mut snapshot: FileSnapshot = Arc::new(Vec::new());
+        let cache = SearchCache::default();
+
+        cache.store(&[&snapshot], "fil", 2, vec![vec![1, 4]]);
+        assert_eq!(
+            cache.candidates(&[&snapshot], "file", 2),
+            Some(vec![vec![1, 4]])
+        );
+
+        // backspace and edits in the middle are not refinements
+        cache.store(&[&snapshot], "file", 2, vec![vec![4]]);
+        assert_eq!(cache.candidates(&[&snapshot], "fil", 2), None);
+        cache.store(&[&snapshot], "file", 2, vec![vec![4]]);
+        assert_eq!(cache.candidates(&[&snapshot], "fxle_picker", 2), None);
+
+        // a larger typo budget could match files outside of the cached set
+        cache.store(&[&snapshot], "file_pic", 2, vec![vec![4]]);
+        assert_eq!(cache.candidates(&[&snapshot], "file_picker", 3), None);
+
+        // adding or removing files moves the snapshot to a new allocation
+        cache.store(&[&snapshot], "file", 2, vec![vec![4]]);
+        Arc::make_mut(&mut snapshot).clear();
+        assert_eq!(cache.candidates(&[&snapshot], "file_", 2), None);
+    }
+
+    #[test]
+    fn test_indexed_paths_match_trailing_components() {
+        let file = |path: &str| {
+            Arc::new(FileItem::with_relative_path(
+                path.into(),
+                path.to_string(),
+                None,
+            ))
+        };
+        let snapshot: FileSnapshot = Arc::new(vec![file("src/a:b.rs"), file("lib/mod.rs")]);
+        let cache = SearchCache::default();
+        let paths = cache.indexed_paths(&[&snapshot]);
+        let files = [snapshot.as_slice()];
+
+        assert!(paths.contains(&files, "src/a:b.rs"));
+        assert!(paths.contains(&files, "a:b.rs"));
+        assert!(paths.contains(&files, "mod.rs"));
+        assert!(!paths.contains(&files, "b.rs"));
+        assert!(!paths.contains(&files, "src/mod.rs"));
+        assert!(Arc::ptr_eq(&paths, &cache.indexed_paths(&[&snapshot])));
+    }
+}
@@ -0,0 +1,619 @@
+use crate::error::Error;
+use crate::grep::GrepOptions;
+use crate::types::FileItem;
+use rayon::prelude::*;
+use regex_syntax::hir::{Class, Hir, HirKind};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info};
+
+/// The index is shared between the picker, its watchers and the greps, see `FilePicker::content_index`
+pub type SharedTrigramIndex = Arc<RwLock<TrigramIndex>>;
+
+/// Larger files are not indexed, a grep always reads them
+const MAX_INDEXED_FILE_SIZE: u64 = 16 * 1024 * 1024;
+const FILE_MAGIC: &[u8; 8] = b"FFFTRI01";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexedFile {
+    path: PathBuf,
+    /// Modification time the contents were read at, the file is indexed again once it differs
+    modified: u64,
+}
+
+/// Which files contain every 3 byte sequence of their contents, ASCII letters folded to the
+/// lowercase. A grep only reads the files containing all the trigrams its pattern requires.
+///
+/// Files are numbered in the order they were indexed, a removed or changed file keeps its
+/// number in the postings until enough of them pile up to compact the index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrigramIndex {
+    /// `None` for the files removed from the index
+    files: Vec<Option<IndexedFile>>,
+    ids: HashMap<PathBuf, u32>,
+    /// Ascending ids of the files containing the trigram
+    postings: HashMap<u32, Vec<u32>>,
+    removed_count: usize,
+}
+
+/// Trigrams every match of the pattern contains
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TrigramQuery {
+    /// No trigram is known, any file can match
+    Any,
+    Trigrams(Vec<u32>),
+    And(Vec<TrigramQuery>),
+    Or(Vec<TrigramQuery>),
+}
+
+fn trigram(bytes: &[u8]) -> u32 {
+    let fold = |byte: u8| byte.to_ascii_lowercase() as u32;
+    (fold(bytes[0]) << 16) | (fold(bytes[1]) << 8) | fold(bytes[2])
+}
+
+/// Sorted unique trigrams of the contents, the ones spanning lines are left out as a match of
+/// the grep never does
+pub fn content_trigrams(content: &[u8]) -> Vec<u32> {
+    let mut trigrams: Vec<u32> = content
+        .windows(3)
+        .filter(|window| !window.contains(&b'\n'))
+        .map(trigram)
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// Trigrams of a literal part of the pattern. Only the ASCII ones are used: the other case
+/// insensitive characters don't fold to a single byte sequence.
+fn literal_query(literal: &[u8]) -> TrigramQuery {
+    let trigrams: Vec<u32> = literal
+        .windows(3)
+        .filter(|window| window.is_ascii() && !window.contains(&b'\n'))
+        .map(trigram)
+        .collect();
+    match trigrams.is_empty() {
+        true => TrigramQuery::Any,
+        false => TrigramQuery::Trigrams(trigrams),
+    }
+}
+
+/// The byte of a class matching a single ASCII character in any case, e.g. `[kK]`
+fn class_byte(class: &Class) -> Option<u8> {
+    let mut bytes = match class {
+        Class::Unicode(class) => class
+            .iter()
+            .flat_map(|range| range.start()..=range.end())
+            .take(3)
+            .map(|c| u8::try_from(c).ok().filter(u8::is_ascii))
+            .collect::<Option<Vec<u8>>>()?,
+        Class::Bytes(class) => class
+            .iter()
+            .flat_map(|range| range.start()..=range.end())
+            .take(3)
+            .map(|byte| byte.is_ascii().then_some(byte))
+            .collect::<Option<Vec<u8>>>()?,
+    };
+    bytes
+        .iter_mut()
+        .for_each(|byte| *byte = byte.to_ascii_lowercase());
+    bytes.dedup();
+    (bytes.len() == 1).then(|| bytes[0])
+}
+
+/// Required trigrams of the regex, conservative: anything not understood matches any file
+fn regex_query(hir: &Hir) -> TrigramQuery {
+    match hir.kind() {
+        HirKind::Literal(literal) => literal_query(&literal.0),
+        HirKind::Capture(capture) => regex_query(&capture.sub),
+        HirKind::Repetition(repetition) if repetition.min > 0 => regex_query(&repetition.sub),
+        HirKind::Alternation(alternatives) => {
+            let alternatives: Vec<TrigramQuery> = alternatives.iter().map(regex_query).collect();
+            match alternatives.contains(&TrigramQuery::Any) {
+                true => TrigramQuery::Any,
+                false => TrigramQuery::Or(alternatives),
+            }
+        }
+        HirKind::Concat(parts) => {
+            // adjacent literals and single letter classes form longer literals
+            let mut queries = Vec::new();
+            let mut literal = Vec::new();
+            for part in parts {
+                match part.kind() {
+                    HirKind::Literal(part) => literal.extend_from_slice(&part.0),
+                    HirKind::Class(class) if class_byte(class).is_some() => {
+                        literal.extend(class_byte(class));
+                    }
+                    _ => {
+                        queries.push(literal_query(&std::mem::take(&mut literal)));
+                        queries.push(regex_query(part));
+                    }
+                }
+            }
+            queries.push(literal_query(&literal));
+            queries.retain(|query| *query != TrigramQuery::Any);
+            match queries.is_empty() {
+                true => TrigramQuery::Any,
+                false => TrigramQuery::And(queries),
+            }
+        }
+        _ => TrigramQuery::Any,
+    }
+}
+
+/// Parses the pattern the same way as the grep, the fuzzy matches need none of its trigrams
+fn pattern_query(pattern: &str, options: &GrepOptions) -> TrigramQuery {
+    let Some((pattern, case_insensitive)) = options.regex_pattern(pattern) else {
+        return TrigramQuery::Any;
+    };
+
+    regex_syntax::ParserBuilder::new()
+        .case_insensitive(case_insensitive)
+        .multi_line(true)
+        .crlf(true)
+        .build()
+        .parse(&pattern)
+        .map_or(TrigramQuery::Any, |hir| regex_query(&hir))
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut a, mut b) = (a.iter().peekable(), b.iter().peekable());
+    let mut ids = Vec::new();
+    while let (Some(&&x), Some(&&y)) = (a.peek(), b.peek()) {
+        match x.cmp(&y) {
+            std::cmp::Ordering::Less => {
+                a.next();
+            }
+            std::cmp::Ordering::Greater => {
+                b.next();
+            }
+            std::cmp::Ordering::Equal => {
+                ids.push(x);
+                a.next();
+                b.next();
+            }
+        }
+    }
+    ids
+}
+
+/// Reads the file for the index, `None` when it is too large or unreadable. Binary files have
+/// no trigrams as the grep skips them anyway.
+pub fn file_trigrams(path: &Path) -> Option<Vec<u32>> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.len() > MAX_INDEXED_FILE_SIZE {
+        return None;
+    }
+
+    let bytes = std::fs::read(path).ok()?;
+    if bytes[..bytes.len().min(8192)].contains(&0) {
+        return Some(Vec::new());
+    }
+    Some(content_trigrams(&bytes))
+}
+
+/// Modification time of the file in the same unit as `FileItem::modified`
+pub fn modified_secs(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_secs())
+}
+
+impl TrigramIndex {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Replaces the indexed contents of the file
+    pub fn insert(&mut self, path: &Path, modified: u64, trigrams: &[u32]) {
+        self.remove(path);
+
+        let id = self.files.len() as u32;
+        self.files.push(Some(IndexedFile {
+            path: path.to_path_buf(),
+            modified,
+        }));
+        self.ids.insert(path.to_path_buf(), id);
+        for trigram in trigrams {
+            self.postings.entry(*trigram).or_default().push(id);
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        let Some(id) = self.ids.remove(path) else {
+            return;
+        };
+        self.files[id as usize] = None;
+        self.removed_count += 1;
+
+        if self.removed_count > self.files.len() / 2 {
+            self.compact();
+        }
+    }
+
+    /// Renumbers the files dropping the removed ones from the postings
+    fn compact(&mut self) {
+        let mut new_ids = vec![None; self.files.len()];
+        let files = std::mem::take(&mut self.files);
+        for (id, file) in files.into_iter().enumerate() {
+            if let Some(file) = file {
+                new_ids[id] = Some(self.files.len() as u32);
+                self.ids.insert(file.path.clone(), self.files.len() as u32);
+                self.files.push(Some(file));
+            }
+        }
+
+        self.postings.retain(|_, ids| {
+            ids.retain_mut(|id| match new_ids[*id as usize] {
+                Some(new_id) => {
+                    *id = new_id;
+                    true
+                }
+                None => false,
+            });
+            !ids.is_empty()
+        });
+        self.removed_count = 0;
+    }
+
+    /// Brings the index up to date with the scanned files: the removed files are dropped, the
+    /// new and the modified ones are read in parallel
+    pub fn sync_files(&mut self, files: &[Arc<FileItem>]) {
+        let time = std::time::Instant::now();
+        let stale: Vec<PathBuf> = self
+            .files
+            .iter()
+            .flatten()
+            .filter(|indexed| {
+                files
+                    .binary_search_by(|file| file.path.as_os_str().cmp(indexed.path.as_os_str()))
+                    .map_or(true, |index| files[index].modified != indexed.modified)
+            })
+            .map(|indexed| indexed.path.clone())
+            .collect();
+        for path in &stale {
+            self.remove(path);
+        }
+
+        let read: Vec<(&FileItem, Vec<u32>)> = files
+            .par_iter()
+            .filter(|file| !self.ids.contains_key(&file.path))
+            .filter_map(|file| Some((file.as_ref(), file_trigrams(&file.path)?)))
+            .collect();
+        let read_count = read.len();
+        for (file, trigrams) in read {
+            self.insert(&file.path, file.modified, &trigrams);
+        }
+
+        info!(
+            files = self.len(),
+            removed = stale.len(),
+            read = read_count,
+            completed_in = ?time.elapsed(),
+            "Content index synced"
+        );
+    }
+
+    fn query_ids(&self, query: &TrigramQuery) -> Option<Vec<u32>> {
+        match query {
+            TrigramQuery::Any => None,
+            TrigramQuery::Trigrams(trigrams) => {
+                let mut postings = trigrams
+                    .iter()
+                    .map(|trigram| self.postings.get(trigram).map_or(&[][..], Vec::as_slice))
+                    .collect::<Vec<_>>();
+                // the rarest trigram first keeps the intersections small
+                postings.sort_by_key(|ids| ids.len());
+                let (first, rest) = postings.split_first()?;
+                Some(
+                    rest.iter()
+                        .fold(first.to_vec(), |ids, other| intersect(&ids, other)),
+                )
+            }
+            TrigramQuery::And(queries) => queries
+                .iter()
+                .filter_map(|query| self.query_ids(query))
+                .reduce(|ids, other| intersect(&ids, &other)),
+            TrigramQuery::Or(queries) => {
+                let mut ids = Vec::new();
+                for query in queries {
+                    ids.extend(self.query_ids(query)?);
+                }
+                ids.sort_unstable();
+                ids.dedup();
+                Some(ids)
+            }
+        }
+    }
+
+    /// Which of the files can contain a match of the pattern, in the order of the files. Files
+    /// missing from the index or modified since they were indexed always can. `None` when the pattern requires no trigram.
+    pub fn file_filter(
+        &self,
+        files: &[Arc<FileItem>],
+        pattern: &str,
+        options: &GrepOptions,
+    ) -> Option<Vec<bool>> {
+        let query = pattern_query(pattern, options);
+        let ids = self.query_ids(&query)?;
+        debug!(
+            ?pattern,
+            candidates = ids.len(),
+            files = self.len(),
+            "Content index pruned the files"
+        );
+
+        Some(
+            files
+                .par_iter()
+                .map(|file| match self.ids.get(&file.path) {
+                    // contents changed since they were indexed, the watcher hasn't caught up
+                    Some(id) if self.is_current(*id, file) => ids.binary_search(id).is_ok(),
+                    _ => true,
+                })
+                .collect(),
+        )
+    }
+
+    fn is_current(&self, id: u32, file: &FileItem) -> bool {
+        self.files[id as usize]
+            .as_ref()
+            .is_some_and(|indexed| indexed.modified == file.modified)
+    }
+
+    /// The index in the file format, compacted: the removed files are left out and the others
+    /// numbered again. Only needs to read the index, the file is written by `save_index_file`
+    /// without holding any lock.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut new_ids = vec![None; self.files.len()];
+        let mut files = Vec::with_capacity(self.files.len() - self.removed_count);
+        for (id, file) in self.files.iter().enumerate() {
+            if let Some(file) = file {
+                new_ids[id] = Some(files.len() as u32);
+                files.push(file);
+            }
+        }
+
+        let mut bytes = FILE_MAGIC.to_vec();
+        bytes.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        for file in files {
+            let path = file.path.to_string_lossy();
+            bytes.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(path.as_bytes());
+            bytes.extend_from_slice(&file.modified.to_le_bytes());
+        }
+
+        let postings: Vec<(u32, Vec<u32>)> = self
+            .postings
+            .iter()
+            .map(|(trigram, ids)| {
+                let ids: Vec<u32> = ids.iter().filter_map(|id| new_ids[*id as usize]).collect();
+                (*trigram, ids)
+            })
+            .filter(|(_, ids)| !ids.is_empty())
+            .collect();
+        bytes.extend_from_slice(&(postings.len() as u32).to_le_bytes());
+        for (trigram, ids) in postings {
+            bytes.extend_from_slice(&trigram.to_le_bytes());
+            bytes.extend_from_slice(&(ids.len() as u32).to_le_bytes());
+            for id in ids {
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Every length read is checked against the rest of the file, a corrupted one fails to load
+    /// instead of allocating it
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = std::fs::read(path).map_err(Error::ReadFile)?;
+        let invalid = || Error::InvalidContentIndex(path.to_path_buf());
+        let mut rest = bytes.as_slice();
+        let take = |rest: &mut &[u8], len: usize| -> Result<Vec<u8>, Error> {
+            let (taken, tail) = rest.split_at_checked(len).ok_or_else(invalid)?;
+            *rest = tail;
+            Ok(taken.to_vec())
+        };
+        let read_u32 = |rest: &mut &[u8]| -> Result<u32, Error> {
+            let (taken, tail) = rest.split_first_chunk::<4>().ok_or_else(invalid)?;
+            *rest = tail;
+            Ok(u32::from_le_bytes(*taken))
+        };
+
+        if take(&mut rest, FILE_MAGIC.len())? != FILE_MAGIC {
+            return Err(invalid());
+        }
+
+        let mut index = Self::default();
+        let files_count = read_u32(&mut rest)?;
+        for id in 0..files_count {
+            let len = read_u32(&mut rest)? as usize;
+            let path =
+                PathBuf::from(String::from_utf8(take(&mut rest, len)?).map_err(|_| invalid())?);
+            let (modified, tail) = rest.split_first_chunk::<8>().ok_or_else(invalid)?;
+            rest = tail;
+
+            index.ids.insert(path.clone(), id);
+            index.files.push(Some(IndexedFile {
+                path,
+                modified: u64::from_le_bytes(*modified),
+            }));
+        }
+
+        let postings_count = read_u32(&mut rest)?;
+        for _ in 0..postings_count {
+            let trigram = read_u32(&mut rest)?;
+            let len = read_u32(&mut rest)? as usize;
+            let ids = take(&mut rest, len.checked_mul(4).ok_or_else(invalid)?)?
+                .chunks_exact(4)
+                .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+                .map(|id| (id < files_count).then_some(id).ok_or_else(invalid))
+                .collect::<Result<Vec<u32>, Error>>()?;
+            index.postings.insert(trigram, ids);
+        }
+
+        Ok(index)
+    }
+}
+
+/// Writes the index bytes to the file. The file is replaced at once so a crash never leaves a
+/// partial index behind.
+pub fn save_index_file(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let temp_path = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    };
+
+    write().map_err(Error::ContentIndexWrite)
+}
+
+/// Index file of the picker base path in the content index directory
+pub fn index_file_path(dir: &Path, base_path: &Path) -> PathBuf {
+    let hash = blake3::hash(base_path.to_string_lossy().as_bytes());
+    dir.join(format!("{}.trigrams", &hash.to_hex()[..16]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grep::GrepMode;
+
+    fn indexed(files: &[(&str, &str)]) -> (TrigramIndex, Vec<Arc<FileItem>>) {
+        let mut index = TrigramIndex::default();
+        let items = files
+            .iter()
+            .map(|(path, content)| {
+                index.insert(Path::new(path), 0, &content_trigrams(content.as_bytes()));
+                Arc::new(FileItem::with_relative_path(
+                    PathBuf::from(path),
+                    path.to_string(),
+                    None,
+                ))
+            })
+            .collect();
+        (index, items)
+    }
+
+    #[test]
+    fn test_patterns_prune_files() {
+        let (mut index, files) = indexed(&[
+            ("a.rs", "fn parse_location() {}"),
+            ("b.rs", "let Parser = new();"),
+            ("c.rs", "nothing to see"),
+        ]);
+        let filter = |index: &TrigramIndex, pattern, mode| {
+            let options = GrepOptions {
+                mode,
+                ..Default::default()
+            };
+            index.file_filter(&files, pattern, &options)
+        };
+
+        assert_eq!(
+            filter(&index, "PARSE", GrepMode::Literal),
+            Some(vec![true, true, false])
+        );
+        assert_eq!(
+            filter(&index, "parse_loc", GrepMode::Literal),
+            Some(vec![true, false, false])
+        );
+        assert_eq!(
+            filter(&index, r"fn \w+_location|thing$", GrepMode::Regex),
+            Some(vec![true, false, true])
+        );
+        assert_eq!(
+            filter(&index, "(?i)parser =", GrepMode::Regex),
+            Some(vec![false, true, false])
+        );
+        // nothing to prune with
+        assert_eq!(filter(&index, r"\w+", GrepMode::Regex), None);
+        assert_eq!(filter(&index, "ps", GrepMode::Literal), None);
+        assert_eq!(filter(&index, "parse", GrepMode::Fuzzy), None);
+
+        // changed and removed files, the unindexed ones are always searched
+        index.insert(Path::new("c.rs"), 0, &content_trigrams(b"parse"));
+        index.remove(Path::new("a.rs"));
+        assert_eq!(
+            filter(&index, "parse", GrepMode::Literal),
+            Some(vec![true, true, true])
+        );
+        assert_eq!(
+            filter(&index, "nothing", GrepMode::Literal),
+            Some(vec![true, false, false])
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = index_file_path(dir.path(), Path::new("/repo"));
+        save_index_file(&path, &index.to_bytes()).unwrap();
+        let mut compacted = index.clone();
+        compacted.compact();
+        assert_eq!(TrigramIndex::load(&path).unwrap(), compacted);
+
+        // a length past the end of the file
+        let mut bytes = index.to_bytes();
+        bytes.truncate(FILE_MAGIC.len() + 4);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            TrigramIndex::load(&path),
+            Err(Error::InvalidContentIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_files_modified_since_indexed_are_searched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, "fn old_name() {}\n").unwrap();
+        let file = Arc::new(FileItem::with_relative_path(
+            path.clone(),
+            "lib.rs".to_string(),
+            None,
+        ));
+        let mut index = TrigramIndex::default();
+        index.sync_files(std::slice::from_ref(&file));
+
+        // an edit the watcher missed, the index still has the old contents
+        std::fs::write(&path, "fn new_name() {}\n").unwrap();
+        let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let files = vec![Arc::new(FileItem::with_relative_path(
+            path,
+            "lib.rs".to_string(),
+            None,
+        ))];
+
+        let options = GrepOptions::default();
+        let candidates = index.file_filter(&files, "new_name", &options).unwrap();
+        assert_eq!(candidates, vec![true]);
+        let result =
+            crate::grep::grep_files(&files, "new_name", &options, Some(&candidates)).unwrap();
+        assert_eq!(result.matches.len(), 1);
+
+        // unchanged files are still pruned
+        assert_eq!(
+            index.file_filter(std::slice::from_ref(&file), "new_name", &options),
+            Some(vec![false])
+        );
+    }
+}
@@ -1,9 +1,17 @@
 use mlua::prelude::*;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
 
-use crate::{git::format_git_status, location::Location};
+use crate::{
+    git::format_git_status,
+    location::{ColumnEncoding, Location},
+    related_files::RelatedMatcher,
+};
 
-#[derive(Debug, Clone)]
+/// An indexed file. The snapshots share the items, a changed file is replaced by a new item
+/// while its frecency, git status and pin are updated in place, see `FileState`.
+#[derive(Debug)]
 pub struct FileItem {
     pub path: PathBuf,
     pub relative_path: String,
@@ -12,10 +20,128 @@ pub struct FileItem {
     pub file_name_lower: String,
     pub size: u64,
     pub modified: u64,
-    pub access_frecency_score: i64,
-    pub modification_frecency_score: i64,
-    pub total_frecency_score: i64,
-    pub git_status: Option<git2::Status>,
+    state: FileState,
+}
+
+/// State of a file which changes without the file itself: updating it doesn't copy the item
+/// nor the snapshots holding it, every snapshot sees the new value.
+#[derive(Debug)]
+struct FileState {
+    access_frecency_score: AtomicI64,
+    modification_frecency_score: AtomicI64,
+    /// Bits of the `git2::Status`, `NO_GIT_STATUS` when unknown
+    git_status: AtomicU32,
+    /// Pinned in its project, see `FrecencyTracker::add_pin`
+    pinned: AtomicBool,
+}
+
+const NO_GIT_STATUS: u32 = u32::MAX;
+
+impl FileItem {
+    pub fn from_parts(
+        path: PathBuf,
+        relative_path: String,
+        file_name: String,
+        size: u64,
+        modified: u64,
+        git_status: Option<git2::Status>,
+    ) -> Self {
+        Self {
+            path,
+            relative_path_lower: relative_path.to_lowercase(),
+            relative_path,
+            file_name_lower: file_name.to_lowercase(),
+            file_name,
+            size,
+            modified,
+            state: FileState {
+                access_frecency_score: AtomicI64::new(0),
+                modification_frecency_score: AtomicI64::new(0),
+                git_status: AtomicU32::new(
+                    git_status.map_or(NO_GIT_STATUS, |status| status.bits()),
+                ),
+                pinned: AtomicBool::new(false),
+            },
+        }
+    }
+
+    pub fn access_frecency_score(&self) -> i64 {
+        self.state.access_frecency_score.load(Ordering::Relaxed)
+    }
+
+    pub fn modification_frecency_score(&self) -> i64 {
+        self.state
+            .modification_frecency_score
+            .load(Ordering::Relaxed)
+    }
+
+    pub fn total_frecency_score(&self) -> i64 {
+        self.access_frecency_score() + self.modification_frecency_score()
+    }
+
+    pub fn set_frecency_scores(&self, access_score: i64, modification_score: i64) {
+        let state = &self.state;
+        state
+            .access_frecency_score
+            .store(access_score, Ordering::Relaxed);
+        state
+            .modification_frecency_score
+            .store(modification_score, Ordering::Relaxed);
+    }
+
+    pub fn git_status(&self) -> Option<git2::Status> {
+        match self.state.git_status.load(Ordering::Relaxed) {
+            NO_GIT_STATUS => None,
+            bits => Some(git2::Status::from_bits_retain(bits)),
+        }
+    }
+
+    pub fn set_git_status(&self, git_status: Option<git2::Status>) {
+        let bits = git_status.map_or(NO_GIT_STATUS, |status| status.bits());
+        self.state.git_status.store(bits, Ordering::Relaxed);
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.state.pinned.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pinned(&self, pinned: bool) {
+        self.state.pinned.store(pinned, Ordering::Relaxed);
+    }
+}
+
+/// Copies the current state, the copy is updated on its own
+impl Clone for FileItem {
+    fn clone(&self) -> Self {
+        let file = Self::from_parts(
+            self.path.clone(),
+            self.relative_path.clone(),
+            self.file_name.clone(),
+            self.size,
+            self.modified,
+            self.git_status(),
+        );
+        file.set_frecency_scores(
+            self.access_frecency_score(),
+            self.modification_frecency_score(),
+        );
+        file.set_pinned(self.is_pinned());
+        file
+    }
+}
+
+/// A directory containing indexed files, derived from the file index
+#[derive(Debug, Clone)]
+pub struct DirItem {
+    pub path: PathBuf,
+    pub relative_path: String,
+    pub relative_path_lower: String,
+    pub dir_name: String,
+    pub dir_name_lower: String,
+    /// Indexed files anywhere below the directory
+    pub file_count: usize,
+    /// Access frecency of the contained files summed with diminishing returns
+    pub frecency_score: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +153,10 @@ pub struct Score {
     pub frecency_boost: i32,
     pub distance_penalty: i32,
     pub current_file_penalty: i32,
+    pub ignored_penalty: i32,
+    pub pin_bonus: i32,
+    pub related_bonus: i32,
+    pub co_access_boost: i32,
     pub exact_match: bool,
     pub match_type: &'static str,
 }
@@ -35,21 +165,99 @@ pub struct Score {
 pub struct ScoringContext<'a> {
     pub query: &'a str,
     pub current_file: Option<&'a str>,
+    /// Label of the root containing the current file, set only when indexing multiple roots
+    pub current_root: Option<&'a str>,
     pub max_results: usize,
     pub max_typos: u16,
     pub max_threads: usize,
     pub reverse_order: bool,
+    /// Files related to the current file get a bonus for empty and short queries
+    pub related: Option<&'a RelatedMatcher<'a>>,
+    /// Files usually opened after the current file (by the absolute path) with the score of
+    /// those transitions, see `FrecencyTracker::co_access_scores`
+    pub co_access: Option<&'a HashMap<PathBuf, i64>>,
+}
+
+/// Options of a single `FilePicker::rank_sources` call
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions<'a> {
+    pub max_results: usize,
+    pub max_threads: usize,
+    pub current_file: Option<&'a str>,
+    /// Label of the root containing the current file, set only when indexing multiple roots
+    pub current_root: Option<&'a str>,
+    pub reverse_order: bool,
+    pub related: Option<&'a RelatedMatcher<'a>>,
+    pub co_access: Option<&'a HashMap<PathBuf, i64>>,
+}
+
+/// The list a searched file comes from, every result reports it to Lua as `source`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FileSource {
+    /// Files found by the scan of the picker roots
+    #[default]
+    Index,
+    /// Files hidden by the ignore rules
+    Ignored,
+    /// Open buffers pushed from Lua with `set_source_files`
+    Buffer,
+    /// Neovim oldfiles pushed from Lua with `set_source_files`
+    Oldfile,
+    /// Files of the frecency database from every project, see `recent_files`
+    Recent,
+}
+
+impl FileSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileSource::Index => "index",
+            FileSource::Ignored => "ignored",
+            FileSource::Buffer => "buffer",
+            FileSource::Oldfile => "oldfile",
+            FileSource::Recent => "recent",
+        }
+    }
+
+    /// Pushed files can be anywhere on disk and take over the same file found by the scan
+    pub fn is_pushed(self) -> bool {
+        matches!(self, FileSource::Buffer | FileSource::Oldfile)
+    }
+}
+
+impl std::str::FromStr for FileSource {
+    type Err = crate::error::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "index" => Ok(FileSource::Index),
+            "ignored" => Ok(FileSource::Ignored),
+            "buffer" | "buffers" => Ok(FileSource::Buffer),
+            "oldfile" | "oldfiles" => Ok(FileSource::Oldfile),
+            "recent" => Ok(FileSource::Recent),
+            _ => Err(crate::error::Error::InvalidFileSource(source.to_string())),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct SearchResult<'a> {
     pub items: Vec<&'a FileItem>,
+    /// Source of every item in the same order
+    pub sources: Vec<FileSource>,
     pub scores: Vec<Score>,
     pub total_matched: usize,
     pub total_files: usize,
     pub location: Option<Location>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct DirSearchResult<'a> {
+    pub items: Vec<&'a DirItem>,
+    pub scores: Vec<Score>,
+    pub total_matched: usize,
+    pub total_dirs: usize,
+}
+
 impl IntoLua for &FileItem {
     fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
         let table = lua.create_table()?;
@@ -58,13 +266,28 @@ impl IntoLua for &FileItem {
         table.set("name", self.file_name.clone())?;
         table.set("size", self.size)?;
         table.set("modified", self.modified)?;
-        table.set("access_frecency_score", self.access_frecency_score)?;
+        table.set("access_frecency_score", self.access_frecency_score())?;
         table.set(
             "modification_frecency_score",
-            self.modification_frecency_score,
+            self.modification_frecency_score(),
         )?;
-        table.set("total_frecency_score", self.total_frecency_score)?;
-        table.set("git_status", format_git_status(self.git_status))?;
+        table.set("total_frecency_score", self.total_frecency_score())?;
+        table.set("git_status", format_git_status(self.git_status()))?;
+        table.set("pinned", self.is_pinned())?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+impl IntoLua for &DirItem {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("path", self.path.to_string_lossy().to_string())?;
+        table.set("relative_path", self.relative_path.clone())?;
+        table.set("name", self.dir_name.clone())?;
+        table.set("is_dir", true)?;
+        table.set("file_count", self.file_count)?;
+        table.set("access_frecency_score", self.frecency_score)?;
+        table.set("total_frecency_score", self.frecency_score)?;
         Ok(LuaValue::Table(table))
     }
 }
@@ -79,6 +302,10 @@ impl IntoLua for Score {
         table.set("frecency_boost", self.frecency_boost)?;
         table.set("distance_penalty", self.distance_penalty)?;
         table.set("current_file_penalty", self.current_file_penalty)?;
+        table.set("ignored_penalty", self.ignored_penalty)?;
+        table.set("pin_bonus", self.pin_bonus)?;
+        table.set("related_bonus", self.related_bonus)?;
+        table.set("co_access_boost", self.co_access_boost)?;
         table.set("match_type", self.match_type)?;
         table.set("exact_match", self.exact_match)?;
         Ok(LuaValue::Table(table))
@@ -99,31 +326,134 @@ impl IntoLua for LuaPosition {
 impl IntoLua for SearchResult<'_> {
     fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
         let table = lua.create_table()?;
-        table.set("items", self.items)?;
+        let items = lua.create_table_with_capacity(self.items.len(), 0)?;
+        for (item, source) in self.items.into_iter().zip(self.sources) {
+            let item = item.into_lua(lua)?;
+            if let LuaValue::Table(ref item_table) = item {
+                item_table.set("source", source.as_str())?;
+            }
+            items.raw_push(item)?;
+        }
+        table.set("items", items)?;
         table.set("scores", self.scores)?;
         table.set("total_matched", self.total_matched)?;
         table.set("total_files", self.total_files)?;
 
-        if let Some(location) = &self.location {
-            let location_table = lua.create_table()?;
-
-            match location {
-                Location::Line(line) => {
-                    location_table.set("line", *line)?;
-                }
-                Location::Position { line, col } => {
-                    location_table.set("line", *line)?;
-                    location_table.set("col", *col)?;
-                }
-                Location::Range { start, end } => {
-                    location_table.set("start", LuaPosition(*start))?;
-                    location_table.set("end", LuaPosition(*end))?;
-                }
+        table.set("location", self.location)?;
+
+        Ok(LuaValue::Table(table))
+    }
+}
+
+impl IntoLua for Location {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        match self {
+            Location::Line(line) => {
+                table.set("line", line)?;
+            }
+            Location::Position {
+                line,
+                col,
+                encoding,
+            } => {
+                table.set("line", line)?;
+                table.set("col", col)?;
+                table.set("encoding", encoding.as_str())?;
+            }
+            Location::Range {
+                start,
+                end,
+                encoding,
+            } => {
+                table.set("start", LuaPosition(start))?;
+                table.set("end", LuaPosition(end))?;
+                table.set("encoding", encoding.as_str())?;
+            }
+            Location::ByteOffset(offset) => {
+                table.set("byte_offset", offset)?;
+            }
+            Location::Symbol(symbol) => {
+                table.set("symbol", symbol)?;
+            }
+            Location::Pattern(pattern) => {
+                table.set("pattern", pattern)?;
             }
+        }
+        Ok(LuaValue::Table(table))
+    }
+}
+
+/// Reads back the tables of `IntoLua`, columns without an encoding are bytes as in Neovim
+impl FromLua for Location {
+    fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let conversion_error = |message: &str| LuaError::FromLuaConversionError {
+            from: value.type_name(),
+            to: "Location".to_string(),
+            message: Some(message.to_string()),
+        };
+        let LuaValue::Table(table) = &value else {
+            return Err(conversion_error("expected a location table"));
+        };
+
+        if let Some(symbol) = table.get::<Option<String>>("symbol")? {
+            return Ok(Location::Symbol(symbol));
+        }
+        if let Some(pattern) = table.get::<Option<String>>("pattern")? {
+            return Ok(Location::Pattern(pattern));
+        }
+        if let Some(offset) = table.get::<Option<usize>>("byte_offset")? {
+            return Ok(Location::ByteOffset(offset));
+        }
 
-            table.set("location", location_table)?;
+        let encoding = match table.get::<Option<String>>("encoding")? {
+            Some(encoding) => encoding
+                .parse()
+                .map_err(|_| conversion_error("unknown column encoding"))?,
+            None => ColumnEncoding::Byte,
+        };
+        if let Some(line) = table.get::<Option<i32>>("line")? {
+            return Ok(match table.get::<Option<i32>>("col")? {
+                Some(col) => Location::Position {
+                    line,
+                    col,
+                    encoding,
+                },
+                None => Location::Line(line),
+            });
         }
 
+        let position = |key: &str| -> LuaResult<Option<(i32, i32)>> {
+            table
+                .get::<Option<LuaTable>>(key)?
+                .map(|position| {
+                    Ok((
+                        position.get("line")?,
+                        position.get::<Option<i32>>("col")?.unwrap_or(0),
+                    ))
+                })
+                .transpose()
+        };
+        match (position("start")?, position("end")?) {
+            (Some(start), Some(end)) => Ok(Location::Range {
+                start,
+                end,
+                encoding,
+            }),
+            _ => Err(conversion_error(
+                "expected a line, a range, a symbol or a pattern",
+            )),
+        }
+    }
+}
+
+impl IntoLua for DirSearchResult<'_> {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("items", self.items)?;
+        table.set("scores", self.scores)?;
+        table.set("total_matched", self.total_matched)?;
+        table.set("total_dirs", self.total_dirs)?;
         Ok(LuaValue::Table(table))
     }
 }
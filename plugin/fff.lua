@@ -49,6 +49,18 @@ end, {
   desc = 'Find files with FFF (use directory path or search query)',
 })
 
+vim.api.nvim_create_user_command('FFFFindDirs', function() require('fff').find_dirs() end, {
+  desc = 'Find directories with FFF and change the working directory to the selected one',
+})
+
+vim.api.nvim_create_user_command('FFFRecent', function() require('fff').find_recent() end, {
+  desc = 'Find files opened in any project with FFF, ranked by frecency',
+})
+
+vim.api.nvim_create_user_command('FFFAlternate', function() require('fff').open_alternate() end, {
+  desc = 'Open the file related to the current one (its test, header or source), pick one if there are several',
+})
+
 vim.api.nvim_create_user_command('FFFScan', function() require('fff').scan_files() end, {
   desc = 'Scan files for FFF',
 })
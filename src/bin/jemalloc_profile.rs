@@ -1,8 +1,11 @@
-use fff_nvim::{FILE_PICKER, file_picker::FilePicker};
+use fff_nvim::file_picker::{FilePicker, SharedPicker};
 use std::env;
+use std::sync::OnceLock;
 use std::thread;
 use std::time::Duration;
 
+static FILE_PICKER: OnceLock<SharedPicker> = OnceLock::new();
+
 fn get_mem_stat() -> Result<(usize, usize, usize), Box<dyn std::error::Error>> {
     // Use system memory info since jemalloc-ctl conflicts
     #[cfg(target_os = "macos")]
@@ -81,20 +84,19 @@ fn test_search_memory_pattern(
         let query = query_pattern(i);
 
         let (result_count, _total_matched) = {
-            let file_picker_guard = FILE_PICKER.read().unwrap();
-            if let Some(ref picker) = *file_picker_guard {
-                let search_result = FilePicker::fuzzy_search(
-                    picker.get_files(),
-                    &query,
-                    50 + (i % 50), // Vary result count
-                    1 + (i % 4),   // Vary thread count
-                    None,
-                    false, // prompt_position not relevant for test
-                );
-                (search_result.items.len(), search_result.total_matched)
-            } else {
+            let Some(picker) = FILE_PICKER.get() else {
                 continue;
-            }
+            };
+            let picker = picker.read().unwrap();
+            let search_result = FilePicker::fuzzy_search(
+                picker.get_files(),
+                &query,
+                50 + (i % 50), // Vary result count
+                1 + (i % 4),   // Vary thread count
+                None,
+                false, // prompt_position not relevant for test
+            );
+            (search_result.items.len(), search_result.total_matched)
         };
 
         if i % 100 == 0 {
@@ -166,19 +168,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!();
 
     // Initialize FilePicker
-    {
-        let mut file_picker_guard = FILE_PICKER.write().unwrap();
-        if file_picker_guard.is_none() {
-            println!("Initializing FilePicker...");
-            *file_picker_guard = Some(FilePicker::new(base_path.clone())?);
-        }
-    }
+    println!("Initializing FilePicker...");
+    let shared_picker = FILE_PICKER.get_or_init(|| FilePicker::new(base_path.clone()).unwrap());
 
     // Wait for initial scan
     println!("Waiting for file scan...");
     loop {
-        if let Ok(file_picker_guard) = FILE_PICKER.read()
-            && let Some(ref picker) = *file_picker_guard
+        if let Ok(picker) = shared_picker.read()
             && !picker.is_scan_active()
             && !picker.get_files().is_empty()
         {
@@ -188,8 +184,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let file_count = {
-        let file_picker_guard = FILE_PICKER.read()?;
-        file_picker_guard.as_ref().unwrap().get_files().len()
+        let picker = shared_picker
+            .read()
+            .map_err(|_| "Failed to lock the picker")?;
+        picker.get_files().len()
     };
 
     println!("📊 Found {} files", file_count);
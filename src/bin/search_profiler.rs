@@ -1,7 +1,14 @@
-use fff_nvim::FILE_PICKER;
-use fff_nvim::file_picker::FilePicker;
+use fff_nvim::FILE_PICKERS;
+use fff_nvim::file_picker::{FilePicker, SharedPicker};
 use std::time::{Duration, Instant};
 
+fn active_picker() -> Result<Option<SharedPicker>, String> {
+    let registry = FILE_PICKERS
+        .read()
+        .map_err(|_| "Failed to acquire read lock")?;
+    Ok(registry.get(None))
+}
+
 /// Wait for background scan to complete
 fn wait_for_scan(timeout_secs: u64) -> Result<usize, String> {
     let start = Instant::now();
@@ -11,10 +18,8 @@ fn wait_for_scan(timeout_secs: u64) -> Result<usize, String> {
     loop {
         iteration += 1;
 
-        let picker_guard = FILE_PICKER
-            .read()
-            .map_err(|_| "Failed to acquire read lock")?;
-        if let Some(ref picker) = *picker_guard {
+        if let Some(picker) = active_picker()? {
+            let picker = picker.read().map_err(|_| "Failed to acquire read lock")?;
             let is_scanning = picker.is_scan_active();
             let file_count = picker.get_files().len();
 
@@ -47,22 +52,19 @@ fn wait_for_scan(timeout_secs: u64) -> Result<usize, String> {
 
 /// Initialize FilePicker and insert into global state
 fn init_file_picker(path: &str) -> Result<(), String> {
-    let picker = FilePicker::new(path.to_string())
-        .map_err(|e| format!("Failed to create FilePicker: {:?}", e))?;
-
-    let mut picker_guard = FILE_PICKER
+    let mut registry = FILE_PICKERS
         .write()
         .map_err(|_| "Failed to acquire write lock")?;
-    *picker_guard = Some(picker);
+    registry
+        .activate(std::path::Path::new(path), Default::default())
+        .map_err(|e| format!("Failed to create FilePicker: {:?}", e))?;
     Ok(())
 }
 
 /// Get files snapshot from global state
-fn get_files() -> Result<Vec<fff_nvim::types::FileItem>, String> {
-    let picker_guard = FILE_PICKER
-        .read()
-        .map_err(|_| "Failed to acquire read lock")?;
-    if let Some(ref picker) = *picker_guard {
+fn get_files() -> Result<Vec<std::sync::Arc<fff_nvim::types::FileItem>>, String> {
+    if let Some(picker) = active_picker()? {
+        let picker = picker.read().map_err(|_| "Failed to acquire read lock")?;
         Ok(picker.get_files().to_vec())
     } else {
         Err("FilePicker not initialized".to_string())
@@ -1,4 +1,4 @@
-use fff_nvim::{FILE_PICKER, file_picker::FilePicker};
+use fff_nvim::file_picker::FilePicker;
 use std::env;
 use std::io::{self, Write};
 use std::thread;
@@ -78,22 +78,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Initialize the file picker directly
     println!("📁 Initializing FilePicker...");
-    {
-        let mut file_picker_guard = FILE_PICKER.write().unwrap();
-        if file_picker_guard.is_none() {
-            println!("Creating new FilePicker for path: {}", base_path);
-            match FilePicker::new(base_path.clone()) {
-                Ok(picker) => {
-                    println!("FilePicker created successfully");
-                    *file_picker_guard = Some(picker);
-                }
-                Err(e) => {
-                    eprintln!("Failed to create FilePicker: {:?}", e);
-                    std::process::exit(1);
-                }
-            }
+    println!("Creating new FilePicker for path: {}", base_path);
+    let shared_picker = match FilePicker::new(base_path.clone()) {
+        Ok(picker) => {
+            println!("FilePicker created successfully");
+            picker
         }
-    }
+        Err(e) => {
+            eprintln!("Failed to create FilePicker: {:?}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Wait for initial scan to complete
     println!("⏳ Waiting for initial file scan to complete...");
@@ -101,9 +96,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut scan_completed = false;
 
     loop {
-        if let Ok(file_picker_guard) = FILE_PICKER.read()
-            && let Some(ref picker) = *file_picker_guard
-        {
+        if let Ok(picker) = shared_picker.read() {
             if !picker.is_scan_active() {
                 println!("Scan inactive, checking file count...");
                 let file_count = picker.get_files().len();
@@ -127,32 +120,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // If async scan didn't work, trigger a manual scan
     if !scan_completed {
         println!("Triggering manual rescan...");
-        if let Ok(mut file_picker_guard) = FILE_PICKER.write()
-            && let Some(ref mut picker) = *file_picker_guard
-        {
-            match picker.trigger_rescan() {
-                Ok(_) => println!("Manual rescan completed"),
-                Err(e) => println!("Manual rescan failed: {:?}", e),
-            }
+        match FilePicker::trigger_rescan_shared(&shared_picker) {
+            Ok(_) => println!("Manual rescan completed"),
+            Err(e) => println!("Manual rescan failed: {:?}", e),
         }
     }
 
     let initial_file_count = {
-        let file_picker_guard = FILE_PICKER.read()?;
-        if let Some(ref picker) = *file_picker_guard {
-            let files = picker.get_files();
-            println!("Found {} files in picker", files.len());
-            if !files.is_empty() {
-                println!("Sample files:");
-                for (i, file) in files.iter().take(5).enumerate() {
-                    println!("  {}. {}", i + 1, file.relative_path);
-                }
+        let picker = shared_picker
+            .read()
+            .map_err(|_| "Failed to lock the picker")?;
+        let files = picker.get_files();
+        println!("Found {} files in picker", files.len());
+        if !files.is_empty() {
+            println!("Sample files:");
+            for (i, file) in files.iter().take(5).enumerate() {
+                println!("  {}. {}", i + 1, file.relative_path);
             }
-            files.len()
-        } else {
-            println!("No picker found in FILE_PICKER static!");
-            0
         }
+        files.len()
     };
 
     println!(
@@ -197,21 +183,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let search_start = Instant::now();
         let (result_count, search_duration) = {
-            let file_picker_guard = FILE_PICKER.read().unwrap();
-            if let Some(ref picker) = *file_picker_guard {
-                let search_result = FilePicker::fuzzy_search(
-                    picker.get_files(),
-                    query,
-                    max_results,
-                    max_threads,
-                    None,
-                    false, // prompt_position not relevant for test
-                );
-                let duration = search_start.elapsed();
-                (search_result.items.len(), duration)
-            } else {
-                (0, search_start.elapsed())
-            }
+            let picker = shared_picker.read().unwrap();
+            let search_result = FilePicker::fuzzy_search(
+                picker.get_files(),
+                query,
+                max_results,
+                max_threads,
+                None,
+                false, // prompt_position not relevant for test
+            );
+            let duration = search_start.elapsed();
+            (search_result.items.len(), duration)
         };
 
         search_count += 1;
@@ -2,21 +2,25 @@
 #![allow(dead_code)]
 #![allow(clippy::enum_variant_names)]
 
-use fff_nvim::{FILE_PICKER, FRECENCY, file_picker::FilePicker, git::format_git_status};
+use fff_nvim::file_picker::{FilePicker, SharedPicker};
+use fff_nvim::{FILE_PICKERS, FRECENCY, git::format_git_status};
 use std::env;
 use std::io::{self, Write};
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
 
+fn active_picker() -> SharedPicker {
+    FILE_PICKERS.read().unwrap().get(None).unwrap()
+}
+
 fn cleanup_global_state() {
     // Clean up file picker
     {
-        let mut file_picker = FILE_PICKER.write().unwrap();
-        if let Some(mut picker) = file_picker.take() {
-            let _ = picker.stop_background_monitor();
-            drop(picker);
+        let mut file_pickers = FILE_PICKERS.write().unwrap();
+        if file_pickers.clear() > 0 {
             println!("🧹 FilePicker cleaned up");
         }
     }
@@ -51,18 +55,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut git_stats = std::collections::HashMap::new();
     // Initialize the global file picker using lib.rs function
     {
-        let mut file_picker = FILE_PICKER.write().unwrap();
-        if file_picker.is_some() {
+        let mut file_pickers = FILE_PICKERS.write().unwrap();
+        if !file_pickers.is_empty() {
             eprintln!("❌ FilePicker already initialized");
             std::process::exit(1);
         }
-        *file_picker = Some(FilePicker::new(base_path.clone())?);
+        file_pickers.activate(Path::new(&base_path), Default::default())?;
     }
 
     // Get initial file count from global state
     let initial_count = {
-        let file_picker = FILE_PICKER.read().unwrap();
-        let files = file_picker.as_ref().unwrap().get_files();
+        let shared_picker = active_picker();
+        let file_picker = shared_picker.read().unwrap();
+        let files = file_picker.get_files();
         println!("Initial file count: {}", files.len());
 
         if !files.is_empty() {
@@ -72,7 +77,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "  {}. {} ({})",
                     i + 1,
                     file.relative_path,
-                    format_git_status(file.git_status)
+                    format_git_status(file.git_status())
                 );
             }
             if files.len() > 5 {
@@ -94,8 +99,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         iteration += 1;
 
         let current_count = {
-            let file_picker = FILE_PICKER.read().unwrap();
-            file_picker.as_ref().unwrap().get_files().len()
+            let shared_picker = active_picker();
+            let file_picker = shared_picker.read().unwrap();
+            file_picker.get_files().len()
         };
 
         if current_count != last_count {
@@ -109,8 +115,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 );
 
                 // Show some recently added files
-                let file_picker = FILE_PICKER.read().unwrap();
-                let files = file_picker.as_ref().unwrap().get_files();
+                let shared_picker = active_picker();
+                let file_picker = shared_picker.read().unwrap();
+                let files = file_picker.get_files();
                 let newest_files = files.iter().rev().take(added.min(3));
                 for file in newest_files {
                     println!("   ➕ {}", file.relative_path);
@@ -134,12 +141,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 timestamp, current_count
             );
 
-            let file_picker = FILE_PICKER.read().unwrap();
-            let current_files = file_picker.as_ref().unwrap().get_files();
+            let shared_picker = active_picker();
+            let file_picker = shared_picker.read().unwrap();
+            let current_files = file_picker.get_files();
 
             git_stats.clear();
             for file in current_files {
-                let status = format_git_status(file.git_status);
+                let status = format_git_status(file.git_status());
                 *git_stats.entry(status).or_insert(0) += 1;
             }
 
@@ -154,8 +162,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         if iteration % 40 == 0 {
             let timestamp = chrono::Local::now().format("%H:%M:%S");
-            let file_picker = FILE_PICKER.read().unwrap();
-            let files = file_picker.as_ref().unwrap().get_files();
+            let shared_picker = active_picker();
+            let file_picker = shared_picker.read().unwrap();
+            let files = file_picker.get_files();
             let search_results = FilePicker::fuzzy_search(files, "rs", 5, 2, None, false);
 
             println!(